@@ -35,6 +35,17 @@ pub trait TextMap {
 
     /// Gets the value of `key'.
     fn get(&self, key: &str) -> Option<&str>;
+
+    /// Returns an iterator for traversing the entries of the map.
+    ///
+    /// This is needed by formats that have prefixed keys
+    /// (e.g., the `uberctx-{name}` baggage items of the Jaeger format).
+    ///
+    /// The default implementation returns an empty iterator,
+    /// so custom carriers that want to propagate such entries must override this method.
+    fn entries(&self) -> Box<dyn Iterator<Item = (&str, &str)> + '_> {
+        Box::new(std::iter::empty())
+    }
 }
 impl<S: BuildHasher> TextMap for HashMap<String, String, S> {
     fn set(&mut self, key: &str, value: &str) {
@@ -43,6 +54,9 @@ impl<S: BuildHasher> TextMap for HashMap<String, String, S> {
     fn get(&self, key: &str) -> Option<&str> {
         self.get(key).map(|v| v.as_ref())
     }
+    fn entries(&self) -> Box<dyn Iterator<Item = (&str, &str)> + '_> {
+        Box::new(self.iter().map(|x| (x.0.as_ref(), x.1.as_ref())))
+    }
 }
 impl TextMap for BTreeMap<String, String> {
     fn set(&mut self, key: &str, value: &str) {
@@ -51,6 +65,20 @@ impl TextMap for BTreeMap<String, String> {
     fn get(&self, key: &str) -> Option<&str> {
         self.get(key).map(|v| v.as_ref())
     }
+    fn entries(&self) -> Box<dyn Iterator<Item = (&str, &str)> + '_> {
+        Box::new(self.iter().map(|x| (x.0.as_ref(), x.1.as_ref())))
+    }
+}
+impl<T: TextMap + ?Sized> TextMap for &mut T {
+    fn set(&mut self, key: &str, value: &str) {
+        (**self).set(key, value)
+    }
+    fn get(&self, key: &str) -> Option<&str> {
+        (**self).get(key)
+    }
+    fn entries(&self) -> Box<dyn Iterator<Item = (&str, &str)> + '_> {
+        (**self).entries()
+    }
 }
 
 /// This trait allows to inject `SpanContext` to HTTP header.
@@ -84,6 +112,11 @@ impl<S: BuildHasher> SetHttpHeaderField for HashMap<String, String, S> {
         Ok(())
     }
 }
+impl<T: SetHttpHeaderField + ?Sized> SetHttpHeaderField for &mut T {
+    fn set_http_header_field(&mut self, name: &str, value: &str) -> Result<()> {
+        (**self).set_http_header_field(name, value)
+    }
+}
 
 /// This trait allows to iterate over the fields of a HTTP header.
 pub trait IterHttpHeaderFields<'a> {
@@ -105,6 +138,16 @@ where
         Box::new(self.iter().map(|x| (x.0.as_ref(), x.1.as_ref())))
     }
 }
+impl<'a, 'b: 'a, T> IterHttpHeaderFields<'a> for &'b T
+where
+    T: IterHttpHeaderFields<'a> + ?Sized,
+{
+    type Fields = T::Fields;
+
+    fn fields(&'a self) -> Self::Fields {
+        (**self).fields()
+    }
+}
 
 /// This trait allows to inject `SpanContext` to binary stream.
 pub trait InjectToBinary<T>: Sized
@@ -1,4 +1,5 @@
 use trackable::error::ErrorKind as TrackableErrorKind;
+use trackable::error::ErrorKindExt;
 use trackable::error::TrackableError;
 
 /// This crate specific error type.
@@ -15,3 +16,8 @@ pub enum ErrorKind {
     Other,
 }
 impl TrackableErrorKind for ErrorKind {}
+impl From<std::io::Error> for Error {
+    fn from(f: std::io::Error) -> Self {
+        ErrorKind::Other.cause(f).into()
+    }
+}
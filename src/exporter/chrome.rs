@@ -0,0 +1,391 @@
+use super::json::ObjectWriter;
+use super::{unix_micros, write_json_tag_value, Exporter};
+use crate::span::FinishedSpan;
+use crate::state::{SpanContextState, TraceId};
+use crate::tag::TagValue;
+use crate::{Error, ErrorKind, Result};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::process;
+
+/// The default maximum number of tracks remembered by `ChromeTraceExporter`.
+pub const DEFAULT_CHROME_MAX_TRACKS: usize = 1024;
+
+/// How `ChromeTraceExporter` groups spans into the tracks (i.e., "threads" in the Trace Event Format).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ChromeTraceGrouping {
+    /// Spans started on the same thread are placed on the same track.
+    ///
+    /// The thread is identified by the `thread.id` and `thread.name` tags,
+    /// so `ThreadInfoProcessor` should be registered on the tracer.
+    /// Spans which do not have the tags are placed on the track `0`.
+    Thread,
+
+    /// Spans in the same trace are placed on the same track.
+    ///
+    /// Since every trace has its own track, only the most recent tracks are remembered
+    /// (see `ChromeTraceExporter::max_tracks`).
+    Trace,
+}
+
+/// Exporter which writes spans in the [Chrome Trace Event Format][format].
+///
+/// The output can be loaded into `chrome://tracing` or [Perfetto](https://ui.perfetto.dev/).
+///
+/// Spans become complete (`"X"`) events whose `args` are the tags,
+/// and logs become instant (`"i"`) events whose `args` are the fields.
+/// The output is a JSON array which is closed when the exporter is shut down
+/// (the viewers can load the output even if it is not closed).
+/// Exporting spans after the shutdown results in an error.
+///
+/// [format]: https://docs.google.com/document/d/1CvAClvFfyA5R-PhYUmn5OOQtYMH4h6I0nSsKchNAySU/
+///
+/// # Examples
+///
+/// ```
+/// use rustracing::exporter::{ChromeTraceExporter, ChromeTraceGrouping, Exporter};
+/// use rustracing::processor::ThreadInfoProcessor;
+/// use rustracing::sampler::AllSampler;
+/// use rustracing::state::SpanContextState;
+/// use rustracing::Tracer;
+///
+/// let (span_tx, span_rx) = crossbeam_channel::bounded(10);
+/// let mut tracer = Tracer::with_sender(AllSampler, span_tx);
+/// tracer.add_processor(ThreadInfoProcessor::new());
+/// {
+///     let _span = tracer.span("foo").start_with_state(SpanContextState::root());
+/// }
+///
+/// let mut exporter = ChromeTraceExporter::new(Vec::new()).grouping(ChromeTraceGrouping::Thread);
+/// exporter.export(span_rx.try_iter().collect()).unwrap();
+/// exporter.shutdown().unwrap();
+///
+/// let json = String::from_utf8(exporter.into_inner()).unwrap();
+/// assert!(json.contains(r#""name":"foo","cat":"span","ph":"X""#));
+/// ```
+#[derive(Debug)]
+pub struct ChromeTraceExporter<W> {
+    writer: W,
+    grouping: ChromeTraceGrouping,
+    pid: u32,
+    max_tracks: usize,
+    tracks: HashMap<Track, u64>,
+    track_order: Vec<Track>,
+    next_tid: u64,
+    started: bool,
+    closed: bool,
+}
+impl ChromeTraceExporter<BufWriter<File>> {
+    /// Makes a new `ChromeTraceExporter` instance which writes to the file `path`.
+    ///
+    /// If the file already exists, it is truncated.
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let file = track!(File::create(path).map_err(Error::from))?;
+        Ok(Self::new(BufWriter::new(file)))
+    }
+}
+impl<W: Write> ChromeTraceExporter<W> {
+    /// Makes a new `ChromeTraceExporter` instance.
+    ///
+    /// The default grouping is `ChromeTraceGrouping::Thread`.
+    pub fn new(writer: W) -> Self {
+        ChromeTraceExporter {
+            writer,
+            grouping: ChromeTraceGrouping::Thread,
+            pid: process::id(),
+            max_tracks: DEFAULT_CHROME_MAX_TRACKS,
+            tracks: HashMap::new(),
+            track_order: Vec::new(),
+            next_tid: 1,
+            started: false,
+            closed: false,
+        }
+    }
+
+    /// Sets how spans are grouped into tracks.
+    pub fn grouping(mut self, grouping: ChromeTraceGrouping) -> Self {
+        self.grouping = grouping;
+        self
+    }
+
+    /// Sets the maximum number of tracks remembered by this exporter.
+    ///
+    /// If a new track exceeds this limit, the oldest one is forgotten
+    /// and the following spans which belong to it are placed on a new track.
+    ///
+    /// The default value is `DEFAULT_CHROME_MAX_TRACKS`.
+    /// A value of `0` is treated as `1`.
+    pub fn max_tracks(mut self, n: usize) -> Self {
+        self.max_tracks = n.max(1);
+        self
+    }
+
+    /// Returns a reference to the underlying writer.
+    pub fn inner_ref(&self) -> &W {
+        &self.writer
+    }
+
+    /// Converts into the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    fn track(&mut self, span: &FinishedSpan<SpanContextState>) -> (u64, Option<String>) {
+        let (track, name) = match self.grouping {
+            ChromeTraceGrouping::Thread => {
+                let tag = |name| span.tags().iter().find(|t| t.name() == name);
+                match tag("thread.id").map(|t| t.value()) {
+                    Some(TagValue::Integer(id)) => {
+                        let name = match tag("thread.name").map(|t| t.value()) {
+                            Some(TagValue::String(name)) => format!("{} ({})", name, id),
+                            _ => format!("thread {}", id),
+                        };
+                        (Track::Thread(*id), name)
+                    }
+                    _ => return (0, None),
+                }
+            }
+            ChromeTraceGrouping::Trace => {
+                let trace_id = span.context().state().trace_id();
+                (Track::Trace(trace_id), format!("trace {}", trace_id))
+            }
+        };
+        if let Some(tid) = self.tracks.get(&track) {
+            (*tid, None)
+        } else {
+            if self.track_order.len() == self.max_tracks {
+                let oldest = self.track_order.remove(0);
+                self.tracks.remove(&oldest);
+            }
+            let tid = self.next_tid;
+            self.next_tid += 1;
+            self.tracks.insert(track, tid);
+            self.track_order.push(track);
+            (tid, Some(name))
+        }
+    }
+
+    fn write_event(&mut self, event: &str) -> Result<()> {
+        let separator = if self.started { ",\n" } else { "[\n" };
+        self.started = true;
+        track!(self
+            .writer
+            .write_all(separator.as_bytes())
+            .map_err(Error::from))?;
+        track!(self.writer.write_all(event.as_bytes()).map_err(Error::from))?;
+        Ok(())
+    }
+}
+impl<W: Write> Exporter<SpanContextState> for ChromeTraceExporter<W> {
+    fn export(&mut self, spans: Vec<FinishedSpan<SpanContextState>>) -> Result<()> {
+        track_assert!(
+            !self.closed,
+            ErrorKind::Other,
+            "The exporter has already been shut down"
+        );
+        for span in &spans {
+            let (tid, new_track_name) = self.track(span);
+            if let Some(name) = new_track_name {
+                let event = metadata_event(self.pid, tid, &name);
+                track!(self.write_event(&event))?;
+            }
+            let event = span_event(self.pid, tid, span);
+            track!(self.write_event(&event))?;
+            for log in span.logs() {
+                let mut event = String::new();
+                let mut object = ObjectWriter::new(&mut event);
+                let name = log
+                    .fields()
+                    .iter()
+                    .find(|f| f.name() == "event")
+                    .map_or("log", |f| f.value());
+                object
+                    .string("name", name)
+                    .string("cat", "log")
+                    .string("ph", "i")
+                    .string("s", "t")
+                    .u64("ts", unix_micros(log.time()))
+                    .u64("pid", u64::from(self.pid))
+                    .u64("tid", tid);
+                let mut args = ObjectWriter::new(object.key("args"));
+                for field in log.fields() {
+                    args.string(field.name(), field.value());
+                }
+                args.finish();
+                object.finish();
+                track!(self.write_event(&event))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        track!(self.writer.flush().map_err(Error::from))
+    }
+
+    fn shutdown(&mut self) -> Result<()> {
+        if !self.closed {
+            self.closed = true;
+            let tail = if self.started { "\n]\n" } else { "[]\n" };
+            track!(self.writer.write_all(tail.as_bytes()).map_err(Error::from))?;
+        }
+        track!(self.flush())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Track {
+    Thread(i64),
+    Trace(TraceId),
+}
+
+fn metadata_event(pid: u32, tid: u64, name: &str) -> String {
+    let mut event = String::new();
+    let mut object = ObjectWriter::new(&mut event);
+    object
+        .string("name", "thread_name")
+        .string("ph", "M")
+        .u64("pid", u64::from(pid))
+        .u64("tid", tid);
+    let mut args = ObjectWriter::new(object.key("args"));
+    args.string("name", name);
+    args.finish();
+    object.finish();
+    event
+}
+
+fn span_event(pid: u32, tid: u64, span: &FinishedSpan<SpanContextState>) -> String {
+    let state = span.context().state();
+    let start = unix_micros(span.start_time());
+    let finish = unix_micros(span.finish_time());
+
+    let mut event = String::new();
+    let mut object = ObjectWriter::new(&mut event);
+    object
+        .string("name", span.operation_name())
+        .string("cat", "span")
+        .string("ph", "X")
+        .u64("ts", start)
+        .u64("dur", finish.saturating_sub(start))
+        .u64("pid", u64::from(pid))
+        .u64("tid", tid);
+    let mut args = ObjectWriter::new(object.key("args"));
+    args.string("trace_id", &state.trace_id().to_string())
+        .string("span_id", &state.span_id().to_string());
+    if let Some(parent) = state.parent_span_id() {
+        args.string("parent_span_id", &parent.to_string());
+    }
+    for tag in span.tags() {
+        write_json_tag_value(args.key(tag.name()), tag.value());
+    }
+    args.finish();
+    object.finish();
+    event
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_span::TestSpan;
+    use super::*;
+    use crate::tag::Tag;
+
+    fn finished_spans() -> Vec<FinishedSpan<SpanContextState>> {
+        [(1, 7i64), (2, 7), (1, 8)]
+            .iter()
+            .map(|&(trace_id, thread_id)| {
+                TestSpan {
+                    trace_id,
+                    start_micros: 10,
+                    finish_micros: 20,
+                    tags: vec![Tag::new("thread.id", thread_id)],
+                    logs: vec![(15, vec![("event", "sent")])],
+                    ..TestSpan::default()
+                }
+                .finish()
+            })
+            .collect()
+    }
+
+    fn tids(json: &str) -> Vec<&str> {
+        json.lines()
+            .filter(|l| l.contains(r#""ph":"X""#))
+            .map(|l| {
+                let start = l.find(r#""tid":"#).unwrap() + 6;
+                let end = start + l[start..].find(',').unwrap();
+                &l[start..end]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn export_works() {
+        let mut exporter = ChromeTraceExporter::new(Vec::new());
+        exporter.export(finished_spans()).unwrap();
+        exporter.shutdown().unwrap();
+        let json = String::from_utf8(exporter.into_inner()).unwrap();
+        let pid = process::id();
+
+        let lines = json.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], "[");
+        assert_eq!(
+            lines[1],
+            format!(
+                r#"{{"name":"thread_name","ph":"M","pid":{},"tid":1,"args":{{"name":"thread 7"}}}},"#,
+                pid
+            )
+        );
+        assert_eq!(
+            lines[2],
+            format!(
+                concat!(
+                    r#"{{"name":"foo","cat":"span","ph":"X","ts":10,"dur":10,"pid":{},"tid":1,"#,
+                    r#""args":{{"trace_id":"00000000000000000000000000000001","#,
+                    r#""span_id":"0000000000000001","thread.id":7}}}},"#
+                ),
+                pid
+            )
+        );
+        assert_eq!(
+            lines[3],
+            format!(
+                concat!(
+                    r#"{{"name":"sent","cat":"log","ph":"i","s":"t","ts":15,"pid":{},"tid":1,"#,
+                    r#""args":{{"event":"sent"}}}},"#
+                ),
+                pid
+            )
+        );
+        assert_eq!(lines.last(), Some(&"]"));
+        assert_eq!(tids(&json), ["1", "1", "2"]);
+    }
+
+    #[test]
+    fn export_after_shutdown_fails() {
+        let mut exporter = ChromeTraceExporter::new(Vec::new());
+        exporter.shutdown().unwrap();
+        assert!(exporter.export(finished_spans()).is_err());
+        assert_eq!(exporter.inner_ref(), b"[]\n");
+    }
+
+    #[test]
+    fn grouping_by_trace_works() {
+        let mut exporter =
+            ChromeTraceExporter::new(Vec::new()).grouping(ChromeTraceGrouping::Trace);
+        exporter.export(finished_spans()).unwrap();
+        let json = String::from_utf8(exporter.into_inner()).unwrap();
+        assert_eq!(tids(&json), ["1", "2", "1"]);
+        assert!(json.contains(r#""args":{"name":"trace 00000000000000000000000000000002"}"#));
+    }
+
+    #[test]
+    fn oldest_track_is_forgotten_if_max_tracks_exceeded() {
+        let mut exporter = ChromeTraceExporter::new(Vec::new())
+            .grouping(ChromeTraceGrouping::Trace)
+            .max_tracks(1);
+        exporter.export(finished_spans()).unwrap();
+        let json = String::from_utf8(exporter.into_inner()).unwrap();
+        assert_eq!(tids(&json), ["1", "2", "3"]);
+    }
+}
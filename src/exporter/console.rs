@@ -0,0 +1,413 @@
+use super::Exporter;
+use crate::span::{FinishedSpan, SpanReceiver};
+use crate::state::{SpanContextState, TraceId};
+use crate::tag::{Tag, TagValue};
+use crate::{Error, Result};
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::time::{Duration, SystemTime};
+
+/// The default width of the waterfall bars printed by `ConsoleExporter`.
+pub const DEFAULT_CONSOLE_BAR_WIDTH: usize = 40;
+
+/// The default maximum number of traces buffered by `ConsoleExporter`.
+pub const DEFAULT_CONSOLE_MAX_TRACES: usize = 100;
+
+const BOLD: &str = "\x1b[1m";
+const DIM: &str = "\x1b[2m";
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const YELLOW: &str = "\x1b[33m";
+const RESET: &str = "\x1b[0m";
+
+/// Exporter which prints traces as human-readable trees.
+///
+/// Spans are buffered per trace, and a trace is printed when its root span
+/// (i.e., a span that has no parent) is exported.
+/// The traces which do not have a root span in this process (e.g., the ones continuing a remote trace)
+/// are printed when the exporter is flushed, or when the number of buffered traces exceeds `max_traces`
+/// (the oldest trace is printed first).
+///
+/// Each span is printed with its waterfall bar, duration, operation name and tags,
+/// followed by its logs:
+///
+/// ```text
+/// trace 00000000000000000000000000000001
+/// [========================================]   10.000ms parent http.method=GET
+/// [..........====================..........]    5.000ms   child
+///                                              +3.000ms     - event=sent
+/// ```
+///
+/// # Examples
+///
+/// ```
+/// use rustracing::exporter::ConsoleExporter;
+/// use rustracing::sampler::AllSampler;
+/// use rustracing::state::SpanContextState;
+/// use rustracing::Tracer;
+///
+/// let (span_tx, span_rx) = crossbeam_channel::bounded(10);
+/// let tracer = Tracer::with_sender(AllSampler, span_tx);
+/// {
+///     let parent = tracer.span("parent").start();
+///     let _child = parent.child("child", |options| options.start());
+/// }
+///
+/// let mut printer = ConsoleExporter::stdout().color(true);
+/// printer.print_received(&span_rx).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct ConsoleExporter<W> {
+    writer: W,
+    bar_width: usize,
+    color: bool,
+    max_traces: usize,
+    pending: HashMap<TraceId, Vec<FinishedSpan<SpanContextState>>>,
+    pending_order: Vec<TraceId>,
+}
+impl ConsoleExporter<io::Stdout> {
+    /// Makes a new `ConsoleExporter` instance which prints to the standard output.
+    pub fn stdout() -> Self {
+        Self::new(io::stdout())
+    }
+}
+impl<W: Write> ConsoleExporter<W> {
+    /// Makes a new `ConsoleExporter` instance.
+    ///
+    /// By default, the output is not colored.
+    pub fn new(writer: W) -> Self {
+        ConsoleExporter {
+            writer,
+            bar_width: DEFAULT_CONSOLE_BAR_WIDTH,
+            color: false,
+            max_traces: DEFAULT_CONSOLE_MAX_TRACES,
+            pending: HashMap::new(),
+            pending_order: Vec::new(),
+        }
+    }
+
+    /// Sets whether the output is colored using ANSI escape sequences.
+    pub fn color(mut self, color: bool) -> Self {
+        self.color = color;
+        self
+    }
+
+    /// Sets the width of the waterfall bars.
+    ///
+    /// The default value is `DEFAULT_CONSOLE_BAR_WIDTH`.
+    /// A value of `0` is treated as `1`.
+    pub fn bar_width(mut self, width: usize) -> Self {
+        self.bar_width = width.max(1);
+        self
+    }
+
+    /// Sets the maximum number of traces buffered until their root spans are exported.
+    ///
+    /// If a new trace exceeds this limit, the oldest buffered trace is printed as it is.
+    ///
+    /// The default value is `DEFAULT_CONSOLE_MAX_TRACES`.
+    /// A value of `0` is treated as `1`.
+    pub fn max_traces(mut self, n: usize) -> Self {
+        self.max_traces = n.max(1);
+        self
+    }
+
+    /// Returns a reference to the underlying writer.
+    pub fn inner_ref(&self) -> &W {
+        &self.writer
+    }
+
+    /// Converts into the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    /// Prints all the spans which have been received by `span_rx`.
+    ///
+    /// This is the same as exporting the spans and then flushing this exporter.
+    pub fn print_received(&mut self, span_rx: &SpanReceiver<SpanContextState>) -> Result<()> {
+        track!(self.export(span_rx.try_iter().collect()))?;
+        track!(self.flush())
+    }
+
+    fn print_trace(&mut self, trace_id: TraceId) -> Result<()> {
+        let spans = if let Some(spans) = self.pending.remove(&trace_id) {
+            spans
+        } else {
+            return Ok(());
+        };
+        self.pending_order.retain(|id| *id != trace_id);
+        let output = self.render(trace_id, spans);
+        track!(self
+            .writer
+            .write_all(output.as_bytes())
+            .map_err(Error::from))
+    }
+
+    fn render(&self, trace_id: TraceId, mut spans: Vec<FinishedSpan<SpanContextState>>) -> String {
+        spans.sort_by_key(|s| s.start_time());
+        let trace_start = spans
+            .iter()
+            .map(|s| s.start_time())
+            .min()
+            .expect("never fails");
+        let trace_end = spans
+            .iter()
+            .map(|s| s.finish_time())
+            .max()
+            .expect("never fails");
+        let renderer = Renderer {
+            exporter: self,
+            spans: &spans,
+            trace_start,
+            trace_duration: elapsed(trace_start, trace_end).max(Duration::from_nanos(1)),
+        };
+
+        let mut out = String::new();
+        out.push_str(&renderer.paint(BOLD, &format!("trace {}", trace_id)));
+        out.push('\n');
+        let span_ids = spans
+            .iter()
+            .map(|s| s.context().state().span_id())
+            .collect::<Vec<_>>();
+        for (i, span) in spans.iter().enumerate() {
+            let is_root = span
+                .context()
+                .state()
+                .parent_span_id()
+                .filter(|parent| span_ids.contains(parent))
+                .is_none();
+            if is_root {
+                renderer.render_span(&mut out, i, 0);
+            }
+        }
+        out
+    }
+}
+impl<W: Write> Exporter<SpanContextState> for ConsoleExporter<W> {
+    fn export(&mut self, spans: Vec<FinishedSpan<SpanContextState>>) -> Result<()> {
+        for span in spans {
+            let state = span.context().state();
+            let trace_id = state.trace_id();
+            let is_root = state.parent_span_id().is_none();
+            if !self.pending.contains_key(&trace_id) {
+                if self.pending_order.len() == self.max_traces {
+                    let oldest = self.pending_order[0];
+                    track!(self.print_trace(oldest))?;
+                }
+                self.pending_order.push(trace_id);
+            }
+            self.pending.entry(trace_id).or_default().push(span);
+            if is_root {
+                track!(self.print_trace(trace_id))?;
+            }
+        }
+        track!(self.writer.flush().map_err(Error::from))
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        for trace_id in self.pending_order.clone() {
+            track!(self.print_trace(trace_id))?;
+        }
+        track!(self.writer.flush().map_err(Error::from))
+    }
+}
+
+struct Renderer<'a, W> {
+    exporter: &'a ConsoleExporter<W>,
+    spans: &'a [FinishedSpan<SpanContextState>],
+    trace_start: SystemTime,
+    trace_duration: Duration,
+}
+impl<'a, W> Renderer<'a, W> {
+    fn render_span(&self, out: &mut String, index: usize, depth: usize) {
+        let span = &self.spans[index];
+        let indent = "  ".repeat(depth);
+        let is_error = span
+            .tags()
+            .iter()
+            .any(|t| t.name() == "error" && *t.value() == TagValue::Boolean(true));
+        let duration = elapsed(span.start_time(), span.finish_time());
+
+        let bar = self.bar(span);
+        out.push('[');
+        out.push_str(&self.paint(if is_error { RED } else { GREEN }, &bar));
+        out.push_str("] ");
+        out.push_str(&format!("{:>10} ", format_duration(duration)));
+        out.push_str(&indent);
+        out.push_str(&self.paint(if is_error { RED } else { BOLD }, span.operation_name()));
+        for tag in span.tags() {
+            out.push(' ');
+            out.push_str(&self.paint(DIM, &format_tag(tag)));
+        }
+        out.push('\n');
+
+        for log in span.logs() {
+            let offset = elapsed(self.trace_start, log.time());
+            let fields = log
+                .fields()
+                .iter()
+                .map(|f| format!("{}={}", f.name(), f.value()))
+                .collect::<Vec<_>>()
+                .join(" ");
+            out.push_str(&" ".repeat(self.exporter.bar_width + 2));
+            out.push_str(&format!(
+                " {:>10} ",
+                format!("+{}", format_duration(offset))
+            ));
+            out.push_str(&indent);
+            out.push_str(&self.paint(YELLOW, &format!("  - {}", fields)));
+            out.push('\n');
+        }
+
+        let span_id = span.context().state().span_id();
+        for (i, child) in self.spans.iter().enumerate() {
+            if i != index && child.context().state().parent_span_id() == Some(span_id) {
+                self.render_span(out, i, depth + 1);
+            }
+        }
+    }
+
+    fn bar(&self, span: &FinishedSpan<SpanContextState>) -> String {
+        let width = self.exporter.bar_width;
+        let total = self.trace_duration.as_nanos();
+        let position = |time| {
+            let offset = elapsed(self.trace_start, time).as_nanos();
+            (offset * width as u128 / total) as usize
+        };
+        let start = position(span.start_time()).min(width - 1);
+        let end = position(span.finish_time()).clamp(start + 1, width);
+        (0..width)
+            .map(|i| if start <= i && i < end { '=' } else { '.' })
+            .collect()
+    }
+
+    fn paint(&self, color: &str, s: &str) -> String {
+        if self.exporter.color {
+            format!("{}{}{}", color, s, RESET)
+        } else {
+            s.to_owned()
+        }
+    }
+}
+
+fn elapsed(from: SystemTime, to: SystemTime) -> Duration {
+    to.duration_since(from).unwrap_or_default()
+}
+
+fn format_duration(d: Duration) -> String {
+    if d >= Duration::from_secs(1) {
+        format!("{:.3}s", d.as_secs_f64())
+    } else if d >= Duration::from_millis(1) {
+        format!("{:.3}ms", d.as_secs_f64() * 1000.0)
+    } else {
+        format!("{}us", d.as_micros())
+    }
+}
+
+fn format_tag(tag: &Tag) -> String {
+    match tag.value() {
+        TagValue::String(v) => format!("{}={}", tag.name(), v),
+        TagValue::Boolean(v) => format!("{}={}", tag.name(), v),
+        TagValue::Integer(v) => format!("{}={}", tag.name(), v),
+        TagValue::Float(v) => format!("{}={}", tag.name(), v),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::AllSampler;
+    use crate::state::SpanId;
+    use crate::tag::StdTag;
+    use crate::Tracer;
+    use std::time::UNIX_EPOCH;
+
+    fn at(millis: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn print_works() {
+        let (span_tx, span_rx) = crossbeam_channel::unbounded();
+        let tracer = Tracer::with_sender(AllSampler, span_tx);
+        let mut printer = ConsoleExporter::new(Vec::new()).bar_width(10);
+        {
+            let mut parent = tracer
+                .span("parent")
+                .start_time(at(0))
+                .tag(StdTag::http_method("GET"))
+                .start_with_state(SpanContextState::new(TraceId::new(1), SpanId::new(1)));
+            parent.set_finish_time(|| at(10));
+            let mut child = parent.child("child", |options| {
+                let mut state = SpanContextState::new(TraceId::new(1), SpanId::new(2));
+                state.set_parent_span_id(Some(SpanId::new(1)));
+                options.start_time(at(2)).start_with_state(state)
+            });
+            child.set_tag(StdTag::error);
+            child.log(|log| {
+                log.time(at(3)).error().message("oops");
+            });
+            child.set_finish_time(|| at(7));
+
+            // Not printed until the root span is finished
+            drop(child);
+            printer.export(span_rx.try_iter().collect()).unwrap();
+            assert!(printer.inner_ref().is_empty());
+        }
+        printer.print_received(&span_rx).unwrap();
+
+        let output = String::from_utf8(printer.into_inner()).unwrap();
+        assert_eq!(
+            output,
+            concat!(
+                "trace 00000000000000000000000000000001\n",
+                "[==========]   10.000ms parent http.method=GET\n",
+                "[..=====...]    5.000ms   child error=true\n",
+                "               +3.000ms     - event=error message=oops\n",
+            )
+        );
+    }
+
+    #[test]
+    fn incomplete_traces_are_printed_on_flush() {
+        let (span_tx, span_rx) = crossbeam_channel::unbounded();
+        let tracer = Tracer::with_sender(AllSampler, span_tx);
+        {
+            let mut state = SpanContextState::new(TraceId::new(2), SpanId::new(2));
+            state.set_parent_span_id(Some(SpanId::new(1)));
+            let _span = tracer.span("remote_child").start_with_state(state);
+        }
+
+        let mut printer = ConsoleExporter::new(Vec::new()).color(true);
+        printer.export(span_rx.try_iter().collect()).unwrap();
+        assert!(printer.inner_ref().is_empty());
+        printer.flush().unwrap();
+
+        let output = String::from_utf8(printer.into_inner()).unwrap();
+        assert!(output.starts_with("\x1b[1mtrace 00000000000000000000000000000002\x1b[0m\n"));
+        assert!(output.contains("\x1b[1mremote_child\x1b[0m"));
+    }
+
+    #[test]
+    fn oldest_trace_is_printed_if_max_traces_exceeded() {
+        let (span_tx, span_rx) = crossbeam_channel::unbounded();
+        let tracer = Tracer::with_sender(AllSampler, span_tx);
+        let mut printer = ConsoleExporter::new(Vec::new()).max_traces(1);
+        for (trace_id, name) in [(3, "first"), (4, "second")].iter() {
+            let mut state = SpanContextState::new(TraceId::new(*trace_id), SpanId::new(2));
+            state.set_parent_span_id(Some(SpanId::new(1)));
+            let _span = tracer.span(*name).start_with_state(state);
+        }
+
+        printer.export(span_rx.try_iter().collect()).unwrap();
+        let output = String::from_utf8(printer.inner_ref().clone()).unwrap();
+        assert!(output.starts_with("trace 00000000000000000000000000000003\n"));
+        assert!(output.contains(" first\n"));
+        assert!(!output.contains("second"));
+
+        printer.flush().unwrap();
+        let output = String::from_utf8(printer.into_inner()).unwrap();
+        assert!(output.contains("trace 00000000000000000000000000000004\n"));
+    }
+}
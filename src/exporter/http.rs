@@ -0,0 +1,336 @@
+//! Minimal HTTP/1.1 client used by the exporters.
+use crate::{Error, ErrorKind, Result};
+use std::fmt;
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::str::{self, FromStr};
+use std::time::Duration;
+use trackable::error::ErrorKindExt;
+
+/// `http://` URL.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct HttpUrl {
+    host: String,
+    port: u16,
+    path: String,
+}
+impl fmt::Display for HttpUrl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "http://{}:{}{}", self.host, self.port, self.path)
+    }
+}
+impl FromStr for HttpUrl {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        track_assert!(
+            s.starts_with("http://"),
+            ErrorKind::InvalidInput,
+            "Only `http://` URLs are supported: {:?}",
+            s
+        );
+        let rest = &s["http://".len()..];
+        let (authority, path) = match rest.find('/') {
+            Some(i) => (&rest[..i], &rest[i..]),
+            None => (rest, "/"),
+        };
+        let (host, port) = if authority.starts_with('[') {
+            let end = track_assert_some!(authority.find(']'), ErrorKind::InvalidInput; s);
+            (&authority[..=end], &authority[end + 1..])
+        } else {
+            match authority.rfind(':') {
+                Some(i) => (&authority[..i], &authority[i..]),
+                None => (authority, ""),
+            }
+        };
+        track_assert!(!host.is_empty(), ErrorKind::InvalidInput; s);
+        let port = if port.is_empty() {
+            80
+        } else {
+            track_assert!(port.starts_with(':'), ErrorKind::InvalidInput; s);
+            track!(port[1..]
+                .parse()
+                .map_err(|e| Error::from(ErrorKind::InvalidInput.cause(e))); s)?
+        };
+        Ok(HttpUrl {
+            host: host.to_owned(),
+            port,
+            path: path.to_owned(),
+        })
+    }
+}
+
+/// HTTP response.
+#[derive(Debug, Clone)]
+pub(crate) struct HttpResponse {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+impl HttpResponse {
+    pub(crate) fn status(&self) -> u16 {
+        self.status
+    }
+
+    pub(crate) fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    pub(crate) fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub(crate) fn body(&self) -> &[u8] {
+        &self.body
+    }
+}
+
+/// Sends a `POST` request and waits for the response.
+///
+/// The connection is closed after each request.
+pub(crate) fn post(
+    url: &HttpUrl,
+    content_type: &str,
+    headers: &[(String, String)],
+    body: &[u8],
+    timeout: Duration,
+) -> Result<HttpResponse> {
+    let mut stream = track!(connect(url, timeout))?;
+    track!(stream.set_read_timeout(Some(timeout)).map_err(Error::from))?;
+    track!(stream.set_write_timeout(Some(timeout)).map_err(Error::from))?;
+
+    let mut request = format!(
+        "POST {} HTTP/1.1\r\nHost: {}:{}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n",
+        url.path,
+        url.host,
+        url.port,
+        content_type,
+        body.len()
+    );
+    for (name, value) in headers {
+        request.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    request.push_str("\r\n");
+    track!(stream.write_all(request.as_bytes()).map_err(Error::from))?;
+    track!(stream.write_all(body).map_err(Error::from))?;
+    track!(stream.flush().map_err(Error::from))?;
+
+    let mut response = Vec::new();
+    track!(stream.read_to_end(&mut response).map_err(Error::from))?;
+    track!(parse_response(&response))
+}
+
+fn connect(url: &HttpUrl, timeout: Duration) -> Result<TcpStream> {
+    let host = url.host.trim_start_matches('[').trim_end_matches(']');
+    let addrs = track!((host, url.port).to_socket_addrs().map_err(Error::from); url)?;
+    let mut last_error = None;
+    for addr in addrs {
+        match TcpStream::connect_timeout(&addr, timeout) {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_error = Some(e),
+        }
+    }
+    match last_error {
+        Some(e) => Err(track!(Error::from(e); url)),
+        None => track_panic!(ErrorKind::Other, "Cannot resolve host: {}", url),
+    }
+}
+
+fn parse_response(response: &[u8]) -> Result<HttpResponse> {
+    let header_end = track_assert_some!(
+        response.windows(4).position(|w| w == b"\r\n\r\n"),
+        ErrorKind::Other,
+        "Incomplete HTTP response"
+    );
+    let head =
+        track!(str::from_utf8(&response[..header_end])
+            .map_err(|e| Error::from(ErrorKind::Other.cause(e))))?;
+    let mut lines = head.split("\r\n");
+
+    let status_line = lines.next().expect("never fails");
+    let mut tokens = status_line.splitn(3, ' ');
+    let version = tokens.next().expect("never fails");
+    track_assert!(version.starts_with("HTTP/1."), ErrorKind::Other; status_line);
+    let status = track_assert_some!(
+        tokens.next().and_then(|s| s.parse().ok()),
+        ErrorKind::Other;
+        status_line
+    );
+
+    let mut headers = Vec::new();
+    for line in lines {
+        let i = track_assert_some!(line.find(':'), ErrorKind::Other; line);
+        headers.push((line[..i].to_owned(), line[i + 1..].trim().to_owned()));
+    }
+    let mut response = HttpResponse {
+        status,
+        headers,
+        body: response[header_end + 4..].to_vec(),
+    };
+    if response
+        .header("Transfer-Encoding")
+        .is_some_and(|v| v.eq_ignore_ascii_case("chunked"))
+    {
+        response.body = track!(decode_chunked(&response.body))?;
+    }
+    Ok(response)
+}
+
+fn decode_chunked(mut body: &[u8]) -> Result<Vec<u8>> {
+    let mut decoded = Vec::new();
+    loop {
+        let line_end = track_assert_some!(
+            body.windows(2).position(|w| w == b"\r\n"),
+            ErrorKind::Other,
+            "Incomplete chunk"
+        );
+        let size = str::from_utf8(&body[..line_end])
+            .ok()
+            .and_then(|s| usize::from_str_radix(s.split(';').next().unwrap_or("").trim(), 16).ok());
+        let size = track_assert_some!(size, ErrorKind::Other, "Invalid chunk size");
+        body = &body[line_end + 2..];
+        if size == 0 {
+            return Ok(decoded);
+        }
+        track_assert!(body.len() >= size, ErrorKind::Other, "Incomplete chunk");
+        decoded.extend_from_slice(&body[..size]);
+        body = body.get(size + 2..).unwrap_or(&[]);
+    }
+}
+
+/// Local HTTP server for testing.
+#[cfg(test)]
+pub(crate) mod test_server {
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::sync::mpsc::{self, Receiver};
+    use std::thread;
+
+    /// A request received by the server.
+    #[derive(Debug)]
+    pub(crate) struct Request {
+        pub(crate) request_line: String,
+        pub(crate) headers: Vec<(String, String)>,
+        pub(crate) body: Vec<u8>,
+    }
+    impl Request {
+        pub(crate) fn header(&self, name: &str) -> Option<&str> {
+            self.headers
+                .iter()
+                .find(|(k, _)| k.eq_ignore_ascii_case(name))
+                .map(|(_, v)| v.as_str())
+        }
+    }
+
+    /// Spawns a server which responds to each request with the given status codes in order,
+    /// and returns the base URL of the server (e.g., `http://127.0.0.1:1234`).
+    pub(crate) fn spawn(statuses: Vec<u16>) -> (String, Receiver<Request>) {
+        spawn_with_header(statuses, "")
+    }
+
+    /// Same as `spawn` except that each response also has the given header line
+    /// (e.g., `Retry-After: 10`).
+    pub(crate) fn spawn_with_header(
+        statuses: Vec<u16>,
+        header: &'static str,
+    ) -> (String, Receiver<Request>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            for status in statuses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut buf = Vec::new();
+                let mut byte = [0];
+                while !buf.ends_with(b"\r\n\r\n") {
+                    stream.read_exact(&mut byte).unwrap();
+                    buf.push(byte[0]);
+                }
+                let head = String::from_utf8(buf).unwrap();
+                let mut lines = head.trim_end().split("\r\n");
+                let request_line = lines.next().unwrap().to_owned();
+                let headers = lines
+                    .map(|line| {
+                        let i = line.find(':').unwrap();
+                        (line[..i].to_owned(), line[i + 1..].trim().to_owned())
+                    })
+                    .collect::<Vec<_>>();
+                let mut request = Request {
+                    request_line,
+                    headers,
+                    body: Vec::new(),
+                };
+                let len = request
+                    .header("Content-Length")
+                    .map_or(0, |v| v.parse().unwrap());
+                request.body = vec![0; len];
+                stream.read_exact(&mut request.body).unwrap();
+                let _ = tx.send(request);
+
+                let mut response = format!(
+                    "HTTP/1.1 {} Status\r\nContent-Length: 0\r\nConnection: close\r\n",
+                    status
+                );
+                if !header.is_empty() {
+                    response.push_str(header);
+                    response.push_str("\r\n");
+                }
+                response.push_str("\r\n");
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+        (url, rx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_url_works() {
+        let url: HttpUrl = "http://localhost:9411/api/v2/spans".parse().unwrap();
+        assert_eq!(url.to_string(), "http://localhost:9411/api/v2/spans");
+
+        let url: HttpUrl = "http://[::1]/".parse().unwrap();
+        assert_eq!(url.to_string(), "http://[::1]:80/");
+
+        assert!("https://localhost/".parse::<HttpUrl>().is_err());
+        assert!("http://localhost:foo/".parse::<HttpUrl>().is_err());
+    }
+
+    #[test]
+    fn parse_response_works() {
+        let response = parse_response(
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nfoo\r\n2\r\nba\r\n0\r\n\r\n",
+        )
+        .unwrap();
+        assert!(response.is_success());
+        assert_eq!(response.header("transfer-encoding"), Some("chunked"));
+        assert_eq!(response.body(), b"fooba");
+    }
+
+    #[test]
+    fn post_works() {
+        let (url, requests) = test_server::spawn(vec![202]);
+        let url: HttpUrl = format!("{}/foo", url).parse().unwrap();
+        let response = post(
+            &url,
+            "text/plain",
+            &[("X-Foo".to_owned(), "bar".to_owned())],
+            b"hello",
+            Duration::from_secs(10),
+        )
+        .unwrap();
+        assert_eq!(response.status(), 202);
+
+        let request = requests.recv().unwrap();
+        assert_eq!(request.request_line, "POST /foo HTTP/1.1");
+        assert_eq!(request.header("content-type"), Some("text/plain"));
+        assert_eq!(request.header("x-foo"), Some("bar"));
+        assert_eq!(request.body, b"hello");
+    }
+}
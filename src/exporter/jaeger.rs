@@ -0,0 +1,457 @@
+use super::thrift::{CompactWriter, Type, MESSAGE_TYPE_ONEWAY};
+use super::{unix_micros, Exporter};
+use crate::log::Log;
+use crate::span::{FinishedSpan, SpanReference};
+use crate::state::SpanContextState;
+use crate::tag::{Tag, TagValue};
+use crate::{Error, ErrorKind, Result};
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket};
+use trackable::error::ErrorKindExt;
+
+/// The default address of the Jaeger agent (`emitBatch` over the compact Thrift protocol).
+pub const DEFAULT_JAEGER_AGENT_ADDR: &str = "127.0.0.1:6831";
+
+/// The default maximum size of a UDP packet sent to the Jaeger agent.
+pub const DEFAULT_JAEGER_MAX_PACKET_SIZE: usize = 65000;
+
+/// The upper bound of the size of a message excluding the process and the spans.
+///
+/// It consists of the message header (13 bytes), the field headers (3 bytes),
+/// the list header (at most 6 bytes) and the stop fields (2 bytes).
+const MESSAGE_OVERHEAD: usize = 24;
+
+/// The name of the tag which records the debug identifier of a debug trace root.
+const DEBUG_ID_TAG: &str = "jaeger-debug-id";
+
+/// Exporter which sends spans to a [Jaeger][jaeger] agent over UDP.
+///
+/// Spans are encoded as `emitBatch` messages of the compact Thrift protocol.
+/// A batch which does not fit in a packet is split into multiple packets.
+///
+/// `TagValue`s are mapped to the Jaeger tag types
+/// (`String` to `STRING`, `Boolean` to `BOOL`, `Integer` to `LONG` and `Float` to `DOUBLE`),
+/// and `Log`s are mapped to the Jaeger logs whose fields are `STRING` tags.
+/// The debug identifier of a span (see `SpanContextState::debug_id`) is recorded
+/// as the `jaeger-debug-id` tag.
+///
+/// [jaeger]: https://www.jaegertracing.io/
+///
+/// # Examples
+///
+/// ```
+/// use rustracing::exporter::JaegerExporter;
+/// use rustracing::tag::Tag;
+///
+/// let exporter = JaegerExporter::new("my-service")
+///     .agent_addr("127.0.0.1:6831")
+///     .unwrap()
+///     .process_tag(Tag::new("hostname", "foo"));
+/// ```
+#[derive(Debug)]
+pub struct JaegerExporter {
+    service_name: String,
+    process_tags: Vec<Tag>,
+    agent_addr: SocketAddr,
+    max_packet_size: usize,
+    socket: Option<UdpSocket>,
+}
+impl JaegerExporter {
+    /// Makes a new `JaegerExporter` instance.
+    ///
+    /// `service_name` is used as the `serviceName` of the process of the exported spans.
+    pub fn new<N: Into<String>>(service_name: N) -> Self {
+        JaegerExporter {
+            service_name: service_name.into(),
+            process_tags: Vec::new(),
+            agent_addr: DEFAULT_JAEGER_AGENT_ADDR.parse().expect("never fails"),
+            max_packet_size: DEFAULT_JAEGER_MAX_PACKET_SIZE,
+            socket: None,
+        }
+    }
+
+    /// Sets the address of the Jaeger agent.
+    ///
+    /// The default value is `DEFAULT_JAEGER_AGENT_ADDR`.
+    ///
+    /// # Errors
+    ///
+    /// If `addr` cannot be resolved,
+    /// it will return an error with the kind `ErrorKind::InvalidInput`.
+    pub fn agent_addr<A: ToSocketAddrs>(mut self, addr: A) -> Result<Self> {
+        let addr = track!(addr
+            .to_socket_addrs()
+            .map_err(|e| Error::from(ErrorKind::InvalidInput.cause(e))))?
+        .next();
+        self.agent_addr = track_assert_some!(addr, ErrorKind::InvalidInput);
+        self.socket = None;
+        Ok(self)
+    }
+
+    /// Sets the maximum size of a UDP packet.
+    ///
+    /// The default value is `DEFAULT_JAEGER_MAX_PACKET_SIZE`.
+    pub fn max_packet_size(mut self, size: usize) -> Self {
+        self.max_packet_size = size;
+        self
+    }
+
+    /// Adds a tag to the process of the exported spans.
+    pub fn process_tag(mut self, tag: Tag) -> Self {
+        self.process_tags.push(tag);
+        self
+    }
+
+    /// Encodes the given spans as `emitBatch` messages.
+    ///
+    /// Each message fits within the maximum packet size.
+    /// The spans which are too large to fit in a packet by themselves are not included,
+    /// and the number of them is returned as the second element of the result.
+    fn encode(&self, spans: &[FinishedSpan<SpanContextState>]) -> (Vec<Vec<u8>>, usize) {
+        let process = self.encode_process();
+        let capacity = self
+            .max_packet_size
+            .saturating_sub(MESSAGE_OVERHEAD + process.len());
+
+        let mut packets = Vec::new();
+        let mut oversized = 0;
+        let mut batch: Vec<Vec<u8>> = Vec::new();
+        let mut batch_size = 0;
+        for span in spans {
+            let span = encode_span(span);
+            if span.len() > capacity {
+                oversized += 1;
+                continue;
+            }
+            if batch_size + span.len() > capacity {
+                packets.push(encode_message(&process, &batch));
+                batch.clear();
+                batch_size = 0;
+            }
+            batch_size += span.len();
+            batch.push(span);
+        }
+        if !batch.is_empty() {
+            packets.push(encode_message(&process, &batch));
+        }
+        (packets, oversized)
+    }
+
+    fn encode_process(&self) -> Vec<u8> {
+        let mut w = CompactWriter::new();
+        w.struct_begin();
+        w.string_field(1, &self.service_name);
+        if !self.process_tags.is_empty() {
+            write_tags(&mut w, 2, self.process_tags.iter());
+        }
+        w.struct_end();
+        w.into_bytes()
+    }
+
+    fn socket(&mut self) -> Result<&UdpSocket> {
+        if self.socket.is_none() {
+            let local_addr = if self.agent_addr.is_ipv4() {
+                SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0))
+            } else {
+                SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0))
+            };
+            let socket = track!(UdpSocket::bind(local_addr).map_err(Error::from))?;
+            self.socket = Some(socket);
+        }
+        Ok(self.socket.as_ref().expect("never fails"))
+    }
+}
+impl Exporter<SpanContextState> for JaegerExporter {
+    fn export(&mut self, spans: Vec<FinishedSpan<SpanContextState>>) -> Result<()> {
+        let (packets, oversized) = self.encode(&spans);
+        let agent_addr = self.agent_addr;
+        let socket = track!(self.socket())?;
+        for packet in packets {
+            track!(socket.send_to(&packet, agent_addr).map_err(Error::from))?;
+        }
+        track_assert_eq!(
+            oversized,
+            0,
+            ErrorKind::InvalidInput,
+            "Too large spans are dropped"
+        );
+        Ok(())
+    }
+}
+
+fn encode_message(process: &[u8], spans: &[Vec<u8>]) -> Vec<u8> {
+    let mut w = CompactWriter::new();
+    w.message_begin("emitBatch", MESSAGE_TYPE_ONEWAY, 0);
+    w.struct_begin(); // emitBatch_args
+    w.field_begin(Type::Struct, 1);
+    w.struct_begin(); // Batch
+    w.field_begin(Type::Struct, 1);
+    w.write_raw(process);
+    w.field_begin(Type::List, 2);
+    w.list_begin(Type::Struct, spans.len());
+    for span in spans {
+        w.write_raw(span);
+    }
+    w.struct_end();
+    w.struct_end();
+    w.into_bytes()
+}
+
+fn encode_span(span: &FinishedSpan<SpanContextState>) -> Vec<u8> {
+    let state = span.context().state();
+    let mut w = CompactWriter::new();
+    w.struct_begin();
+    w.i64_field(1, state.trace_id().low() as i64);
+    w.i64_field(2, state.trace_id().high() as i64);
+    w.i64_field(3, state.span_id().to_u64() as i64);
+    w.i64_field(4, state.parent_span_id().map_or(0, |id| id.to_u64() as i64));
+    w.string_field(5, span.operation_name());
+    // References to invalid contexts (e.g., a debug root) do not identify any span.
+    let references = span
+        .references()
+        .iter()
+        .filter(|r| r.span().is_valid())
+        .collect::<Vec<_>>();
+    if !references.is_empty() {
+        w.field_begin(Type::List, 6);
+        w.list_begin(Type::Struct, references.len());
+        for reference in references {
+            write_reference(&mut w, reference);
+        }
+    }
+    let mut flags = 0;
+    if state.is_sampled() {
+        flags |= 1;
+    }
+    if state.is_debug() {
+        flags |= 2;
+    }
+    w.i32_field(7, flags);
+
+    let start = unix_micros(span.start_time());
+    let finish = unix_micros(span.finish_time());
+    w.i64_field(8, start as i64);
+    w.i64_field(9, finish.saturating_sub(start) as i64);
+    let debug_id = state
+        .debug_id()
+        .map(|id| Tag::new(DEBUG_ID_TAG, id.to_owned()));
+    if !span.tags().is_empty() || debug_id.is_some() {
+        let tags = span
+            .tags()
+            .iter()
+            .chain(debug_id.as_ref())
+            .collect::<Vec<_>>();
+        write_tags(&mut w, 10, tags.into_iter());
+    }
+    if !span.logs().is_empty() {
+        w.field_begin(Type::List, 11);
+        w.list_begin(Type::Struct, span.logs().len());
+        for log in span.logs() {
+            write_log(&mut w, log);
+        }
+    }
+    w.struct_end();
+    w.into_bytes()
+}
+
+fn write_reference(w: &mut CompactWriter, reference: &SpanReference<SpanContextState>) {
+    let state = reference.span();
+    w.struct_begin();
+    w.i32_field(1, if reference.is_child_of() { 0 } else { 1 });
+    w.i64_field(2, state.trace_id().low() as i64);
+    w.i64_field(3, state.trace_id().high() as i64);
+    w.i64_field(4, state.span_id().to_u64() as i64);
+    w.struct_end();
+}
+
+fn write_tags<'a, I>(w: &mut CompactWriter, field_id: i16, tags: I)
+where
+    I: ExactSizeIterator<Item = &'a Tag>,
+{
+    w.field_begin(Type::List, field_id);
+    w.list_begin(Type::Struct, tags.len());
+    for tag in tags {
+        w.struct_begin();
+        w.string_field(1, tag.name());
+        match tag.value() {
+            TagValue::String(v) => {
+                w.i32_field(2, 0);
+                w.string_field(3, v);
+            }
+            TagValue::Float(v) => {
+                w.i32_field(2, 1);
+                w.double_field(4, *v);
+            }
+            TagValue::Boolean(v) => {
+                w.i32_field(2, 2);
+                w.bool_field(5, *v);
+            }
+            TagValue::Integer(v) => {
+                w.i32_field(2, 3);
+                w.i64_field(6, *v);
+            }
+        }
+        w.struct_end();
+    }
+}
+
+fn write_log(w: &mut CompactWriter, log: &Log) {
+    w.struct_begin();
+    w.i64_field(1, unix_micros(log.time()) as i64);
+    w.field_begin(Type::List, 2);
+    w.list_begin(Type::Struct, log.fields().len());
+    for field in log.fields() {
+        w.struct_begin();
+        w.string_field(1, field.name());
+        w.i32_field(2, 0);
+        w.string_field(3, field.value());
+        w.struct_end();
+    }
+    w.struct_end();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_span::TestSpan;
+    use super::*;
+    use crate::sampler::AllSampler;
+    use crate::span::SpanContext;
+    use crate::state::{SpanId, TraceId};
+    use crate::Tracer;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    fn finished_spans(n: usize) -> Vec<FinishedSpan<SpanContextState>> {
+        (0..n)
+            .map(|i| {
+                TestSpan {
+                    span_id: i as u64 + 1,
+                    ..TestSpan::default()
+                }
+                .finish()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn encode_span_works() {
+        let (span_tx, span_rx) = crossbeam_channel::unbounded();
+        let tracer = Tracer::with_sender(AllSampler, span_tx);
+        {
+            let state = SpanContextState::new(TraceId::new(1), SpanId::new(2));
+            let mut span = tracer
+                .span("a")
+                .start_time(UNIX_EPOCH)
+                .tag(Tag::new("b", true))
+                .tag(Tag::new("f", 0.5))
+                .tag(Tag::new("i", -1))
+                .start_with_state(state);
+            span.log(|log| {
+                log.time(UNIX_EPOCH + Duration::from_micros(1))
+                    .field(("k", "v"));
+            });
+            span.set_finish_time(|| UNIX_EPOCH + Duration::from_micros(2));
+        }
+        let span = span_rx.try_recv().unwrap();
+        assert_eq!(
+            encode_span(&span),
+            [
+                &[0x16, 0x02, 0x16, 0x00, 0x16, 0x04, 0x16, 0x00][..], // ids
+                &[0x18, 0x01, b'a'],                                   // operation name
+                &[0x25, 0x02, 0x16, 0x00, 0x16, 0x04],                 // flags, start, duration
+                &[0x19, 0x3c],                                         // tags
+                &[0x18, 0x01, b'b', 0x15, 0x04, 0x31, 0x00],
+                &[0x18, 0x01, b'f', 0x15, 0x02, 0x27],
+                &0.5f64.to_le_bytes(),
+                &[0x00],
+                &[0x18, 0x01, b'i', 0x15, 0x06, 0x46, 0x01, 0x00],
+                &[0x19, 0x1c, 0x16, 0x02, 0x19, 0x1c], // logs
+                &[0x18, 0x01, b'k', 0x15, 0x00, 0x18, 0x01, b'v', 0x00],
+                &[0x00, 0x00],
+            ]
+            .concat()
+        );
+    }
+
+    #[test]
+    fn debug_id_is_encoded_as_tag() {
+        let (span_tx, span_rx) = crossbeam_channel::unbounded();
+        let tracer = Tracer::with_sender(AllSampler, span_tx);
+        {
+            let parent = SpanContext::new(SpanContextState::debug_root("x"), Vec::new());
+            let _span = tracer.span("a").child_of(&parent).start();
+        }
+        let bytes = encode_span(&span_rx.try_recv().unwrap());
+        let tag = [
+            &[0x18, 0x0f][..],
+            b"jaeger-debug-id",
+            &[0x15, 0x00, 0x18, 0x01, b'x', 0x00],
+        ]
+        .concat();
+        assert!(bytes.windows(tag.len()).any(|w| w == &tag[..]));
+    }
+
+    #[test]
+    fn extracted_debug_root_is_not_referenced() {
+        let mut headers = std::collections::HashMap::new();
+        headers.insert("jaeger-debug-id".to_owned(), "x".to_owned());
+        let parent = SpanContext::<SpanContextState>::extract_from_http_header(
+            &crate::propagation::Jaeger::new(&headers),
+        )
+        .unwrap()
+        .unwrap();
+
+        let (span_tx, span_rx) = crossbeam_channel::unbounded();
+        let tracer = Tracer::with_sender(AllSampler, span_tx);
+        {
+            let _span = tracer.span("a").child_of(&parent).start();
+        }
+        let span = span_rx.try_recv().unwrap();
+        assert_eq!(span.references().len(), 1);
+
+        // The operation name is directly followed by the flags (i.e., no references).
+        let bytes = encode_span(&span);
+        let fields = [0x18, 0x01, b'a', 0x25];
+        assert!(bytes.windows(fields.len()).any(|w| w == fields));
+        assert!(span.context().state().is_valid());
+    }
+
+    #[test]
+    fn batches_are_split() {
+        let agent = UdpSocket::bind("127.0.0.1:0").unwrap();
+        agent
+            .set_read_timeout(Some(Duration::from_secs(10)))
+            .unwrap();
+        let spans = finished_spans(20);
+        let span_size = encode_span(&spans[0]).len();
+
+        let exporter = JaegerExporter::new("foo")
+            .agent_addr(agent.local_addr().unwrap())
+            .unwrap();
+        let process_size = exporter.encode_process().len();
+        let mut exporter =
+            exporter.max_packet_size(MESSAGE_OVERHEAD + process_size + span_size * 8);
+        exporter.export(spans).unwrap();
+
+        let mut buf = [0; 65536];
+        let mut counts = Vec::new();
+        for _ in 0..3 {
+            let size = agent.recv(&mut buf).unwrap();
+            assert!(size <= exporter.max_packet_size);
+            assert_eq!(&buf[..4], &[0x82, 0x81, 0x00, 0x09]);
+            assert_eq!(&buf[4..13], b"emitBatch");
+            // The list header of the spans
+            counts.push(buf[13 + 2 + process_size + 1] >> 4);
+        }
+        assert_eq!(counts, [8, 8, 4]);
+    }
+
+    #[test]
+    fn too_large_spans_are_dropped() {
+        let agent = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let mut exporter = JaegerExporter::new("foo")
+            .agent_addr(agent.local_addr().unwrap())
+            .unwrap()
+            .max_packet_size(30);
+        let e = exporter.export(finished_spans(1)).err();
+        assert_eq!(e.map(|e| *e.kind()), Some(ErrorKind::InvalidInput));
+    }
+}
@@ -0,0 +1,135 @@
+//! Minimal JSON writer used by the exporters.
+use std::fmt::Write;
+
+/// Writer of a JSON object.
+#[derive(Debug)]
+pub(crate) struct ObjectWriter<'a> {
+    out: &'a mut String,
+    first: bool,
+}
+impl<'a> ObjectWriter<'a> {
+    pub(crate) fn new(out: &'a mut String) -> Self {
+        out.push('{');
+        ObjectWriter { out, first: true }
+    }
+
+    /// Writes `key` and returns the buffer to which the value should be written.
+    pub(crate) fn key(&mut self, key: &str) -> &mut String {
+        if !self.first {
+            self.out.push(',');
+        }
+        self.first = false;
+        write_string(self.out, key);
+        self.out.push(':');
+        self.out
+    }
+
+    pub(crate) fn string(&mut self, key: &str, value: &str) -> &mut Self {
+        write_string(self.key(key), value);
+        self
+    }
+
+    pub(crate) fn u64(&mut self, key: &str, value: u64) -> &mut Self {
+        let _ = write!(self.key(key), "{}", value);
+        self
+    }
+
+    pub(crate) fn i64(&mut self, key: &str, value: i64) -> &mut Self {
+        let _ = write!(self.key(key), "{}", value);
+        self
+    }
+
+    pub(crate) fn bool(&mut self, key: &str, value: bool) -> &mut Self {
+        let _ = write!(self.key(key), "{}", value);
+        self
+    }
+
+    pub(crate) fn finish(self) {
+        self.out.push('}');
+    }
+}
+
+/// Writer of a JSON array.
+#[derive(Debug)]
+pub(crate) struct ArrayWriter<'a> {
+    out: &'a mut String,
+    first: bool,
+}
+impl<'a> ArrayWriter<'a> {
+    pub(crate) fn new(out: &'a mut String) -> Self {
+        out.push('[');
+        ArrayWriter { out, first: true }
+    }
+
+    /// Returns the buffer to which the next element should be written.
+    pub(crate) fn element(&mut self) -> &mut String {
+        if !self.first {
+            self.out.push(',');
+        }
+        self.first = false;
+        self.out
+    }
+
+    pub(crate) fn finish(self) {
+        self.out.push(']');
+    }
+}
+
+/// Writes `s` as a JSON string literal.
+pub(crate) fn write_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// Writes `n` as a JSON number.
+///
+/// Since JSON cannot represent NaN and infinities, they are written as `null`.
+pub(crate) fn write_f64(out: &mut String, n: f64) {
+    if n.is_finite() {
+        let _ = write!(out, "{:?}", n);
+    } else {
+        out.push_str("null");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_writer_works() {
+        let mut out = String::new();
+        let mut object = ObjectWriter::new(&mut out);
+        object
+            .string("s", "a\"b\\c\n\u{1}")
+            .u64("u", 1)
+            .i64("i", -2)
+            .bool("b", true);
+        write_f64(object.key("f"), 0.5);
+        write_f64(object.key("nan"), f64::NAN);
+        {
+            let mut array = ArrayWriter::new(object.key("a"));
+            write_string(array.element(), "x");
+            write_string(array.element(), "y");
+            array.finish();
+        }
+        object.finish();
+        assert_eq!(
+            out,
+            r#"{"s":"a\"b\\c\n\u0001","u":1,"i":-2,"b":true,"f":0.5,"nan":null,"a":["x","y"]}"#
+        );
+    }
+}
@@ -0,0 +1,350 @@
+use super::json::{ArrayWriter, ObjectWriter};
+use super::{unix_micros, write_json_tag_value, Exporter};
+use crate::span::FinishedSpan;
+use crate::state::SpanContextState;
+use crate::{Error, Result};
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, ErrorKind as IoErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// The default number of old files kept by `JsonLinesExporter`.
+pub const DEFAULT_JSONL_MAX_OLD_FILES: usize = 5;
+
+/// Exporter which writes spans to a file in the [JSON Lines][jsonl] format.
+///
+/// Each line is a JSON object that represents a `FinishedSpan` like the following:
+///
+/// ```json
+/// {"trace_id":"0af7651916cd43dd8448eb211c80319c","span_id":"b7ad6b7169203331",
+///  "parent_span_id":"00f067aa0ba902b7","operation_name":"foo",
+///  "start_time_unix_micros":1000000,"finish_time_unix_micros":1000250,
+///  "tags":{"http.status_code":200},
+///  "logs":[{"time_unix_micros":1000100,"fields":{"event":"sent"}}],
+///  "references":[{"type":"child_of","trace_id":"0af7651916cd43dd8448eb211c80319c","span_id":"00f067aa0ba902b7"}],
+///  "baggage":{"user":"foo"}}
+/// ```
+///
+/// (The line breaks in the above example are only for readability.)
+///
+/// The file is rotated when its size exceeds the limit or when the rotation interval has elapsed.
+/// The rotated files are renamed to `${path}.1`, `${path}.2`, ... (`${path}.1` is the newest one),
+/// and the ones that exceed the configured number are removed.
+///
+/// [jsonl]: https://jsonlines.org/
+///
+/// # Examples
+///
+/// ```no_run
+/// use rustracing::exporter::JsonLinesExporter;
+/// use std::time::Duration;
+///
+/// let exporter = JsonLinesExporter::new("/var/log/spans.jsonl")
+///     .unwrap()
+///     .max_file_size(100 * 1024 * 1024)
+///     .rotation_interval(Duration::from_secs(24 * 60 * 60))
+///     .max_old_files(7);
+/// ```
+#[derive(Debug)]
+pub struct JsonLinesExporter {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    file_size: u64,
+    opened_at: Instant,
+    max_file_size: Option<u64>,
+    rotation_interval: Option<Duration>,
+    max_old_files: usize,
+}
+impl JsonLinesExporter {
+    /// Makes a new `JsonLinesExporter` instance.
+    ///
+    /// If the file `path` already exists, spans are appended to it.
+    ///
+    /// By default, the file is not rotated.
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let (file, file_size) = track!(open(&path))?;
+        Ok(JsonLinesExporter {
+            path,
+            writer: BufWriter::new(file),
+            file_size,
+            opened_at: Instant::now(),
+            max_file_size: None,
+            rotation_interval: None,
+            max_old_files: DEFAULT_JSONL_MAX_OLD_FILES,
+        })
+    }
+
+    /// Rotates the file when its size would exceed `size` bytes.
+    pub fn max_file_size(mut self, size: u64) -> Self {
+        self.max_file_size = Some(size);
+        self
+    }
+
+    /// Rotates the file when `interval` has elapsed since it was opened.
+    pub fn rotation_interval(mut self, interval: Duration) -> Self {
+        self.rotation_interval = Some(interval);
+        self
+    }
+
+    /// Sets the number of old (rotated) files to be kept.
+    ///
+    /// The default value is `DEFAULT_JSONL_MAX_OLD_FILES`.
+    pub fn max_old_files(mut self, n: usize) -> Self {
+        self.max_old_files = n;
+        self
+    }
+
+    /// Returns the path of the current file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Encodes the given span as a JSON object (without the trailing newline).
+    pub fn encode(span: &FinishedSpan<SpanContextState>) -> String {
+        let state = span.context().state();
+        let mut out = String::new();
+        let mut object = ObjectWriter::new(&mut out);
+        object
+            .string("trace_id", &state.trace_id().to_string())
+            .string("span_id", &state.span_id().to_string());
+        if let Some(parent) = state.parent_span_id() {
+            object.string("parent_span_id", &parent.to_string());
+        }
+        object
+            .string("operation_name", span.operation_name())
+            .u64("start_time_unix_micros", unix_micros(span.start_time()))
+            .u64("finish_time_unix_micros", unix_micros(span.finish_time()));
+
+        {
+            let mut tags = ObjectWriter::new(object.key("tags"));
+            for tag in span.tags() {
+                write_json_tag_value(tags.key(tag.name()), tag.value());
+            }
+            tags.finish();
+        }
+        {
+            let mut logs = ArrayWriter::new(object.key("logs"));
+            for log in span.logs() {
+                let mut entry = ObjectWriter::new(logs.element());
+                entry.u64("time_unix_micros", unix_micros(log.time()));
+                let mut fields = ObjectWriter::new(entry.key("fields"));
+                for field in log.fields() {
+                    fields.string(field.name(), field.value());
+                }
+                fields.finish();
+                entry.finish();
+            }
+            logs.finish();
+        }
+        {
+            let mut references = ArrayWriter::new(object.key("references"));
+            for reference in span.references().iter().filter(|r| r.span().is_valid()) {
+                let ref_type = if reference.is_child_of() {
+                    "child_of"
+                } else {
+                    "follows_from"
+                };
+                let mut entry = ObjectWriter::new(references.element());
+                entry
+                    .string("type", ref_type)
+                    .string("trace_id", &reference.span().trace_id().to_string())
+                    .string("span_id", &reference.span().span_id().to_string());
+                entry.finish();
+            }
+            references.finish();
+        }
+        {
+            let mut baggage = ObjectWriter::new(object.key("baggage"));
+            for item in span.context().baggage_items() {
+                baggage.string(item.name(), item.value());
+            }
+            baggage.finish();
+        }
+        object.finish();
+        out
+    }
+
+    fn needs_rotation(&self, line_size: u64) -> bool {
+        if self.file_size == 0 {
+            return false;
+        }
+        let too_large = self
+            .max_file_size
+            .is_some_and(|max| self.file_size + line_size > max);
+        let too_old = self
+            .rotation_interval
+            .is_some_and(|interval| self.opened_at.elapsed() >= interval);
+        too_large || too_old
+    }
+
+    fn rotate(&mut self) -> Result<()> {
+        track!(self.writer.flush().map_err(Error::from))?;
+        if self.max_old_files == 0 {
+            track!(remove_if_exists(&self.path))?;
+        } else {
+            track!(remove_if_exists(&self.rotated_path(self.max_old_files)))?;
+            for i in (1..self.max_old_files).rev() {
+                track!(rename_if_exists(
+                    &self.rotated_path(i),
+                    &self.rotated_path(i + 1)
+                ))?;
+            }
+            track!(fs::rename(&self.path, self.rotated_path(1)).map_err(Error::from))?;
+        }
+
+        let (file, file_size) = track!(open(&self.path))?;
+        self.writer = BufWriter::new(file);
+        self.file_size = file_size;
+        self.opened_at = Instant::now();
+        Ok(())
+    }
+
+    fn rotated_path(&self, i: usize) -> PathBuf {
+        let mut path = OsString::from(self.path.as_os_str());
+        path.push(format!(".{}", i));
+        PathBuf::from(path)
+    }
+}
+impl Exporter<SpanContextState> for JsonLinesExporter {
+    fn export(&mut self, spans: Vec<FinishedSpan<SpanContextState>>) -> Result<()> {
+        for span in &spans {
+            let mut line = Self::encode(span);
+            line.push('\n');
+            if self.needs_rotation(line.len() as u64) {
+                track!(self.rotate())?;
+            }
+            track!(self.writer.write_all(line.as_bytes()).map_err(Error::from))?;
+            self.file_size += line.len() as u64;
+        }
+        track!(self.flush())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        track!(self.writer.flush().map_err(Error::from))
+    }
+}
+
+fn open(path: &Path) -> Result<(File, u64)> {
+    let file = track!(OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(Error::from); path)?;
+    let size = track!(file.metadata().map_err(Error::from))?.len();
+    Ok((file, size))
+}
+
+fn remove_if_exists(path: &Path) -> Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != IoErrorKind::NotFound => Err(track!(Error::from(e); path)),
+        _ => Ok(()),
+    }
+}
+
+fn rename_if_exists(from: &Path, to: &Path) -> Result<()> {
+    match fs::rename(from, to) {
+        Err(e) if e.kind() != IoErrorKind::NotFound => Err(track!(Error::from(e); from, to)),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_span::TestSpan;
+    use super::*;
+    use crate::span::{BaggageItem, SpanContext};
+    use crate::state::{SpanId, TraceId};
+    use crate::tag::Tag;
+    use std::time::UNIX_EPOCH;
+
+    fn finished_spans(n: usize) -> Vec<FinishedSpan<SpanContextState>> {
+        let parent = SpanContext::new(
+            SpanContextState::new(TraceId::new(1), SpanId::new(2)),
+            vec![BaggageItem::new("user", "foo")],
+        );
+        (0..n)
+            .map(|_| {
+                TestSpan {
+                    span_id: 3,
+                    parent_span_id: Some(2),
+                    child_of: vec![parent.clone()],
+                    start_micros: 10,
+                    finish_micros: 20,
+                    tags: vec![Tag::new("n", 1.5)],
+                    logs: vec![(15, vec![("event", "sent")])],
+                    ..TestSpan::default()
+                }
+                .finish()
+            })
+            .collect()
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "rustracing-{}-{}-{}",
+            name,
+            std::process::id(),
+            UNIX_EPOCH.elapsed().unwrap().as_nanos()
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn encode_works() {
+        let span = &finished_spans(1)[0];
+        assert_eq!(
+            JsonLinesExporter::encode(span),
+            concat!(
+                r#"{"trace_id":"00000000000000000000000000000001","span_id":"0000000000000003","#,
+                r#""parent_span_id":"0000000000000002","operation_name":"foo","#,
+                r#""start_time_unix_micros":10,"finish_time_unix_micros":20,"tags":{"n":1.5},"#,
+                r#""logs":[{"time_unix_micros":15,"fields":{"event":"sent"}}],"#,
+                r#""references":[{"type":"child_of","#,
+                r#""trace_id":"00000000000000000000000000000001","span_id":"0000000000000002"}],"#,
+                r#""baggage":{"user":"foo"}}"#
+            )
+        );
+    }
+
+    #[test]
+    fn rotation_by_size_works() {
+        let dir = temp_dir("size");
+        let path = dir.join("spans.jsonl");
+        let line_size = JsonLinesExporter::encode(&finished_spans(1)[0]).len() as u64 + 1;
+
+        let mut exporter = JsonLinesExporter::new(&path)
+            .unwrap()
+            .max_file_size(line_size * 2)
+            .max_old_files(2);
+        exporter.export(finished_spans(7)).unwrap();
+
+        let lines = |p: &Path| fs::read_to_string(p).unwrap().lines().count();
+        assert_eq!(lines(&path), 1);
+        assert_eq!(lines(&dir.join("spans.jsonl.1")), 2);
+        assert_eq!(lines(&dir.join("spans.jsonl.2")), 2);
+        assert!(!dir.join("spans.jsonl.3").exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn rotation_by_time_works() {
+        let dir = temp_dir("time");
+        let path = dir.join("spans.jsonl");
+
+        let mut exporter = JsonLinesExporter::new(&path)
+            .unwrap()
+            .rotation_interval(Duration::from_millis(0));
+        exporter.export(finished_spans(3)).unwrap();
+
+        let lines = |p: &Path| fs::read_to_string(p).unwrap().lines().count();
+        assert_eq!(lines(&path), 1);
+        assert_eq!(lines(&dir.join("spans.jsonl.1")), 1);
+        assert_eq!(lines(&dir.join("spans.jsonl.2")), 1);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
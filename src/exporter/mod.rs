@@ -0,0 +1,202 @@
+//! Exporters of finished spans.
+//!
+//! An exporter sends finished spans to a tracing backend (or writes them somewhere).
+//! It is usually driven by a [`Reporter`](../reporter/struct.Reporter.html)
+//! that receives the spans from a `SpanReceiver` and hands them over in batches.
+pub use self::chrome::{ChromeTraceExporter, ChromeTraceGrouping, DEFAULT_CHROME_MAX_TRACKS};
+pub use self::console::{ConsoleExporter, DEFAULT_CONSOLE_BAR_WIDTH, DEFAULT_CONSOLE_MAX_TRACES};
+pub use self::jaeger::{JaegerExporter, DEFAULT_JAEGER_AGENT_ADDR, DEFAULT_JAEGER_MAX_PACKET_SIZE};
+pub use self::jsonl::{JsonLinesExporter, DEFAULT_JSONL_MAX_OLD_FILES};
+pub use self::otlp::{OtlpExporter, DEFAULT_OTLP_ENDPOINT};
+pub use self::zipkin::{ZipkinExporter, DEFAULT_ZIPKIN_ENDPOINT};
+
+use crate::log::Log;
+use crate::span::FinishedSpan;
+use crate::tag::TagValue;
+use crate::Result;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+mod chrome;
+mod console;
+mod http;
+mod jaeger;
+mod json;
+mod jsonl;
+mod otlp;
+mod protobuf;
+mod thrift;
+mod zipkin;
+
+/// This trait allows for exporting batches of finished spans.
+pub trait Exporter<T> {
+    /// Exports the given batch of spans.
+    fn export(&mut self, spans: Vec<FinishedSpan<T>>) -> Result<()>;
+
+    /// Flushes the spans buffered in this exporter (if any).
+    ///
+    /// The default implementation does nothing.
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Shuts down this exporter.
+    ///
+    /// No span will be exported after this method is called.
+    ///
+    /// The default implementation calls `flush()`.
+    fn shutdown(&mut self) -> Result<()> {
+        self.flush()
+    }
+}
+impl<T, E: Exporter<T> + ?Sized> Exporter<T> for Box<E> {
+    fn export(&mut self, spans: Vec<FinishedSpan<T>>) -> Result<()> {
+        (**self).export(spans)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        (**self).flush()
+    }
+
+    fn shutdown(&mut self) -> Result<()> {
+        (**self).shutdown()
+    }
+}
+impl<T, E: Exporter<T> + ?Sized> Exporter<T> for &mut E {
+    fn export(&mut self, spans: Vec<FinishedSpan<T>>) -> Result<()> {
+        (**self).export(spans)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        (**self).flush()
+    }
+
+    fn shutdown(&mut self) -> Result<()> {
+        (**self).shutdown()
+    }
+}
+
+/// Returns the number of microseconds elapsed since the UNIX epoch.
+fn unix_micros(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_micros() as u64)
+}
+
+/// Returns the number of nanoseconds elapsed since the UNIX epoch.
+fn unix_nanos(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos() as u64)
+}
+
+fn format_tag_value(value: &TagValue) -> String {
+    match value {
+        TagValue::String(v) => v.to_string(),
+        TagValue::Boolean(v) => v.to_string(),
+        TagValue::Integer(v) => v.to_string(),
+        TagValue::Float(v) => v.to_string(),
+    }
+}
+
+/// Writes a tag value as a JSON value of the corresponding type.
+fn write_json_tag_value(out: &mut String, value: &TagValue) {
+    match value {
+        TagValue::String(v) => json::write_string(out, v),
+        TagValue::Boolean(v) => out.push_str(if *v { "true" } else { "false" }),
+        TagValue::Integer(v) => out.push_str(&v.to_string()),
+        TagValue::Float(v) => json::write_f64(out, *v),
+    }
+}
+
+/// Formats a log as a single line string.
+///
+/// If the log consists of only an `event` field, its value is returned as is.
+/// Otherwise, the fields are formatted as `name=value` pairs separated by spaces.
+fn format_log(log: &Log) -> String {
+    match log.fields() {
+        [field] if field.name() == "event" => field.value().to_owned(),
+        fields => fields
+            .iter()
+            .map(|f| format!("{}={}", f.name(), f.value()))
+            .collect::<Vec<_>>()
+            .join(" "),
+    }
+}
+
+/// Helpers for the tests of the exporters.
+#[cfg(test)]
+mod test_span {
+    use crate::sampler::AllSampler;
+    use crate::span::{FinishedSpan, SpanContext};
+    use crate::state::{SpanContextState, SpanId, TraceId};
+    use crate::tag::Tag;
+    use crate::Tracer;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    /// Specification of a finished span.
+    ///
+    /// Each test overrides only the fields it checks, e.g.,
+    /// `TestSpan { operation_name: "bar", ..TestSpan::default() }.finish()`.
+    pub(crate) struct TestSpan {
+        pub(crate) operation_name: &'static str,
+        pub(crate) trace_id: u128,
+        pub(crate) span_id: u64,
+        pub(crate) parent_span_id: Option<u64>,
+        pub(crate) child_of: Vec<SpanContext<SpanContextState>>,
+        pub(crate) follows_from: Vec<SpanContext<SpanContextState>>,
+        pub(crate) start_micros: u64,
+        pub(crate) finish_micros: u64,
+        pub(crate) tags: Vec<Tag>,
+        pub(crate) logs: Vec<(u64, Vec<(&'static str, &'static str)>)>,
+    }
+    impl Default for TestSpan {
+        fn default() -> Self {
+            TestSpan {
+                operation_name: "foo",
+                trace_id: 1,
+                span_id: 1,
+                parent_span_id: None,
+                child_of: Vec::new(),
+                follows_from: Vec::new(),
+                start_micros: 0,
+                finish_micros: 1,
+                tags: Vec::new(),
+                logs: Vec::new(),
+            }
+        }
+    }
+    impl TestSpan {
+        /// Starts and finishes the span.
+        pub(crate) fn finish(self) -> FinishedSpan<SpanContextState> {
+            let (span_tx, span_rx) = crossbeam_channel::bounded(1);
+            let tracer = Tracer::with_sender(AllSampler, span_tx);
+            let mut state =
+                SpanContextState::new(TraceId::new(self.trace_id), SpanId::new(self.span_id));
+            state.set_parent_span_id(self.parent_span_id.map(SpanId::new));
+            {
+                let mut options = tracer
+                    .span(self.operation_name)
+                    .start_time(UNIX_EPOCH + Duration::from_micros(self.start_micros));
+                for context in &self.child_of {
+                    options = options.child_of(context);
+                }
+                for context in &self.follows_from {
+                    options = options.follows_from(context);
+                }
+                for tag in self.tags {
+                    options = options.tag(tag);
+                }
+                let mut span = options.start_with_state(state);
+                for (micros, fields) in self.logs {
+                    span.log(|log| {
+                        log.time(UNIX_EPOCH + Duration::from_micros(micros));
+                        for field in fields {
+                            log.field(field);
+                        }
+                    });
+                }
+                let finish_micros = self.finish_micros;
+                span.set_finish_time(|| UNIX_EPOCH + Duration::from_micros(finish_micros));
+            }
+            span_rx.try_recv().expect("never fails")
+        }
+    }
+}
@@ -0,0 +1,483 @@
+use super::http::{self, HttpUrl};
+use super::protobuf::ProtoWriter;
+use super::{unix_nanos, Exporter};
+use crate::log::Log;
+use crate::span::{FinishedSpan, SpanReference};
+use crate::state::SpanContextState;
+use crate::tag::{Tag, TagValue};
+use crate::{Error, ErrorKind, Result};
+use std::thread;
+use std::time::{Duration, Instant};
+use trackable::error::ErrorKindExt;
+
+/// The default endpoint of `OtlpExporter`.
+pub const DEFAULT_OTLP_ENDPOINT: &str = "http://127.0.0.1:4318/v1/traces";
+
+const SPAN_KIND_INTERNAL: u64 = 1;
+const SPAN_KIND_SERVER: u64 = 2;
+const SPAN_KIND_CLIENT: u64 = 3;
+const SPAN_KIND_PRODUCER: u64 = 4;
+const SPAN_KIND_CONSUMER: u64 = 5;
+const STATUS_CODE_ERROR: u64 = 2;
+const TRACE_FLAGS_SAMPLED: u32 = 0x01;
+
+/// Exporter which sends spans to an [OpenTelemetry][otlp] collector using OTLP/HTTP (binary protobuf).
+///
+/// The fields of a `FinishedSpan` are mapped to the OTLP `Span` as follows:
+///
+/// - Tags: `attributes` (the `span.kind` tag is mapped to `kind`, and the `error=true` tag also sets the error `status`)
+/// - Logs: `events` (the value of the `event` field is used as the name of an event if it exists)
+/// - References: `parent_span_id` and `links`
+///   (all the references except the parent become links with the `opentracing.ref_type` attribute,
+///   and the ones to contexts without valid identifiers, such as `SpanContextState::debug_root`, are omitted)
+///
+/// If the collector responds with the status code `429` or `503`, the request is retried.
+/// Transport errors (e.g., a refused connection or a timeout) are retried as well,
+/// since they are usually caused by a temporarily unavailable collector.
+/// The total time spent on an export is bounded by `max_elapsed_time`.
+///
+/// [otlp]: https://opentelemetry.io/docs/specs/otlp/
+///
+/// # Examples
+///
+/// ```
+/// use rustracing::exporter::OtlpExporter;
+///
+/// let exporter = OtlpExporter::new("my-service")
+///     .endpoint("http://otel-collector:4318/v1/traces")
+///     .unwrap()
+///     .header("Authorization", "Bearer foo");
+/// ```
+#[derive(Debug, Clone)]
+pub struct OtlpExporter {
+    resource_attributes: Vec<Tag>,
+    url: HttpUrl,
+    headers: Vec<(String, String)>,
+    timeout: Duration,
+    max_retries: usize,
+    retry_backoff: Duration,
+    max_elapsed_time: Duration,
+}
+impl OtlpExporter {
+    /// Makes a new `OtlpExporter` instance.
+    ///
+    /// `service_name` is used as the `service.name` attribute of the resource.
+    pub fn new<N: Into<String>>(service_name: N) -> Self {
+        OtlpExporter {
+            resource_attributes: vec![Tag::new("service.name", service_name.into())],
+            url: DEFAULT_OTLP_ENDPOINT.parse().expect("never fails"),
+            headers: Vec::new(),
+            timeout: Duration::from_secs(10),
+            max_retries: 3,
+            retry_backoff: Duration::from_millis(100),
+            max_elapsed_time: Duration::from_secs(10),
+        }
+    }
+
+    /// Sets the URL to which the spans are posted.
+    ///
+    /// The default value is `DEFAULT_OTLP_ENDPOINT`.
+    ///
+    /// # Errors
+    ///
+    /// If `url` is not a valid `http://` URL,
+    /// it will return an error with the kind `ErrorKind::InvalidInput`.
+    pub fn endpoint(mut self, url: &str) -> Result<Self> {
+        self.url = track!(url.parse())?;
+        Ok(self)
+    }
+
+    /// Adds an attribute to the resource of the exported spans.
+    pub fn resource_attribute(mut self, attribute: Tag) -> Self {
+        self.resource_attributes
+            .retain(|x| x.name() != attribute.name());
+        self.resource_attributes.push(attribute);
+        self
+    }
+
+    /// Adds a header to the requests (e.g., for authentication).
+    pub fn header<N, V>(mut self, name: N, value: V) -> Self
+    where
+        N: Into<String>,
+        V: Into<String>,
+    {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Sets the timeout of a request.
+    ///
+    /// The default value is 10 seconds.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets the maximum number of retries of a request.
+    ///
+    /// The default value is `3`.
+    pub fn max_retries(mut self, n: usize) -> Self {
+        self.max_retries = n;
+        self
+    }
+
+    /// Sets the delay before the first retry.
+    ///
+    /// The delay is doubled at each retry (up to `max_elapsed_time`).
+    /// If the response has a `Retry-After` header (in seconds), its value is used instead.
+    ///
+    /// The default value is 100 milliseconds.
+    pub fn retry_backoff(mut self, backoff: Duration) -> Self {
+        self.retry_backoff = backoff;
+        self
+    }
+
+    /// Sets the upper bound of the total time spent on exporting a batch, including retries.
+    ///
+    /// Each delay (including the one requested by `Retry-After`) is capped by the time remaining
+    /// until this bound, and no more retries are made once the time since the first attempt exceeds it.
+    ///
+    /// The default value is 10 seconds.
+    pub fn max_elapsed_time(mut self, max_elapsed_time: Duration) -> Self {
+        self.max_elapsed_time = max_elapsed_time;
+        self
+    }
+
+    /// Encodes the given spans as an OTLP `ExportTraceServiceRequest` message.
+    pub fn encode(&self, spans: &[FinishedSpan<SpanContextState>]) -> Vec<u8> {
+        let mut w = ProtoWriter::new();
+        w.message_field(1, |w| {
+            // ResourceSpans
+            w.message_field(1, |w| {
+                // Resource
+                for attribute in &self.resource_attributes {
+                    w.message_field(1, |w| write_key_value(w, attribute));
+                }
+            });
+            w.message_field(2, |w| {
+                // ScopeSpans
+                w.message_field(1, |w| {
+                    // InstrumentationScope
+                    w.string_field(1, env!("CARGO_PKG_NAME"));
+                    w.string_field(2, env!("CARGO_PKG_VERSION"));
+                });
+                for span in spans {
+                    w.message_field(2, |w| write_span(w, span));
+                }
+            });
+        });
+        w.into_bytes()
+    }
+}
+impl Exporter<SpanContextState> for OtlpExporter {
+    fn export(&mut self, spans: Vec<FinishedSpan<SpanContextState>>) -> Result<()> {
+        if spans.is_empty() {
+            return Ok(());
+        }
+        let body = self.encode(&spans);
+        let started = Instant::now();
+        let mut backoff = self.retry_backoff;
+        let mut retries = 0;
+        loop {
+            let result = track!(http::post(
+                &self.url,
+                "application/x-protobuf",
+                &self.headers,
+                &body,
+                self.timeout
+            ));
+            let (e, delay) = match result {
+                Ok(response) if response.is_success() => return Ok(()),
+                Ok(response) => {
+                    let e = Error::from(ErrorKind::Other.cause(format!(
+                        "OTLP collector returned an error: status={}",
+                        response.status()
+                    )));
+                    if response.status() != 429 && response.status() != 503 {
+                        return Err(track!(e));
+                    }
+                    let delay = response
+                        .header("Retry-After")
+                        .and_then(|v| v.parse().ok())
+                        .map_or(backoff, Duration::from_secs);
+                    (e, delay)
+                }
+                Err(e) => (e, backoff),
+            };
+
+            let remaining = self
+                .max_elapsed_time
+                .checked_sub(started.elapsed())
+                .unwrap_or_default();
+            if retries >= self.max_retries || remaining == Duration::from_secs(0) {
+                return Err(track!(e; retries));
+            }
+            thread::sleep(delay.min(remaining));
+            backoff = backoff.saturating_mul(2).min(self.max_elapsed_time);
+            retries += 1;
+        }
+    }
+}
+
+fn write_span(w: &mut ProtoWriter, span: &FinishedSpan<SpanContextState>) {
+    let state = span.context().state();
+    w.bytes_field(1, &state.trace_id().to_u128().to_be_bytes());
+    w.bytes_field(2, &state.span_id().to_u64().to_be_bytes());
+    if !state.trace_state().is_empty() {
+        w.string_field(3, &state.trace_state().to_string());
+    }
+    if let Some(parent) = state.parent_span_id() {
+        w.bytes_field(4, &parent.to_u64().to_be_bytes());
+    }
+    w.string_field(5, span.operation_name());
+
+    let kind = span
+        .tags()
+        .iter()
+        .find(|t| t.name() == "span.kind")
+        .and_then(|t| span_kind(t.value()));
+    w.uint64_field(6, kind.unwrap_or(SPAN_KIND_INTERNAL));
+    w.fixed64_field(7, unix_nanos(span.start_time()));
+    w.fixed64_field(8, unix_nanos(span.finish_time()));
+    for tag in span.tags() {
+        if tag.name() == "span.kind" && kind.is_some() {
+            continue;
+        }
+        w.message_field(9, |w| write_key_value(w, tag));
+    }
+    for log in span.logs() {
+        w.message_field(11, |w| write_event(w, log));
+    }
+
+    let mut parent_found = false;
+    for reference in span.references() {
+        let context = reference.span();
+        if !context.is_valid() {
+            continue;
+        }
+        if !parent_found && Some(context.span_id()) == state.parent_span_id() {
+            parent_found = true;
+            continue;
+        }
+        w.message_field(13, |w| write_link(w, reference));
+    }
+
+    let is_error = span
+        .tags()
+        .iter()
+        .any(|t| t.name() == "error" && *t.value() == TagValue::Boolean(true));
+    if is_error {
+        w.message_field(15, |w| w.uint64_field(3, STATUS_CODE_ERROR));
+    }
+    if state.is_sampled() {
+        w.fixed32_field(16, TRACE_FLAGS_SAMPLED);
+    }
+}
+
+fn write_event(w: &mut ProtoWriter, log: &Log) {
+    w.fixed64_field(1, unix_nanos(log.time()));
+    let name = log
+        .fields()
+        .iter()
+        .find(|f| f.name() == "event")
+        .map_or("log", |f| f.value());
+    w.string_field(2, name);
+    for field in log.fields() {
+        w.message_field(3, |w| {
+            w.string_field(1, field.name());
+            w.message_field(2, |w| w.string_field(1, field.value()));
+        });
+    }
+}
+
+fn write_link(w: &mut ProtoWriter, reference: &SpanReference<SpanContextState>) {
+    let context = reference.span();
+    w.bytes_field(1, &context.trace_id().to_u128().to_be_bytes());
+    w.bytes_field(2, &context.span_id().to_u64().to_be_bytes());
+    if !context.trace_state().is_empty() {
+        w.string_field(3, &context.trace_state().to_string());
+    }
+    let ref_type = if reference.is_child_of() {
+        "child_of"
+    } else {
+        "follows_from"
+    };
+    w.message_field(4, |w| {
+        write_key_value(w, &Tag::new("opentracing.ref_type", ref_type));
+    });
+}
+
+fn write_key_value(w: &mut ProtoWriter, tag: &Tag) {
+    w.string_field(1, tag.name());
+    w.message_field(2, |w| match tag.value() {
+        TagValue::String(v) => w.string_field(1, v),
+        TagValue::Boolean(v) => w.bool_field(2, *v),
+        TagValue::Integer(v) => w.int64_field(3, *v),
+        TagValue::Float(v) => w.double_field(4, *v),
+    });
+}
+
+fn span_kind(value: &TagValue) -> Option<u64> {
+    match value {
+        TagValue::String(kind) => match kind.as_ref() {
+            "server" => Some(SPAN_KIND_SERVER),
+            "client" => Some(SPAN_KIND_CLIENT),
+            "producer" => Some(SPAN_KIND_PRODUCER),
+            "consumer" => Some(SPAN_KIND_CONSUMER),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::http::test_server;
+    use super::super::protobuf::decode::{values, Value};
+    use super::super::test_span::TestSpan;
+    use super::*;
+    use crate::span::SpanContext;
+    use crate::state::{SpanId, TraceId};
+    use crate::tag::StdTag;
+
+    fn finished_span() -> FinishedSpan<SpanContextState> {
+        let parent = SpanContextState::new(TraceId::new(1), SpanId::new(2));
+        let other = SpanContextState::new(TraceId::new(3), SpanId::new(4));
+        TestSpan {
+            span_id: 5,
+            parent_span_id: Some(2),
+            child_of: vec![SpanContext::new(parent, Vec::new())],
+            follows_from: vec![SpanContext::new(other, Vec::new())],
+            start_micros: 1_000_000,
+            finish_micros: 2_000_000,
+            tags: vec![
+                StdTag::span_kind("server"),
+                Tag::new("n", 10),
+                StdTag::error(),
+            ],
+            logs: vec![(1_000_000, vec![("event", "error"), ("message", "oops")])],
+            ..TestSpan::default()
+        }
+        .finish()
+    }
+
+    #[test]
+    fn encode_works() {
+        let exporter = OtlpExporter::new("bar");
+        let request = exporter.encode(&[finished_span()]);
+
+        let resource_spans = &values(&request, 1)[0];
+        let resource = &values(resource_spans.bytes(), 1)[0];
+        let attribute = &values(resource.bytes(), 1)[0];
+        assert_eq!(values(attribute.bytes(), 1)[0].string(), "service.name");
+
+        let scope_spans = &values(resource_spans.bytes(), 2)[0];
+        let span = values(scope_spans.bytes(), 2)[0].bytes().to_vec();
+        assert_eq!(values(&span, 1)[0].bytes(), &1u128.to_be_bytes()[..]);
+        assert_eq!(values(&span, 2)[0].bytes(), &5u64.to_be_bytes()[..]);
+        assert_eq!(values(&span, 4)[0].bytes(), &2u64.to_be_bytes()[..]);
+        assert_eq!(values(&span, 5)[0].string(), "foo");
+        assert_eq!(values(&span, 6), [Value::Varint(SPAN_KIND_SERVER)]);
+        assert_eq!(values(&span, 7), [Value::Fixed64(1_000_000_000)]);
+        assert_eq!(values(&span, 8), [Value::Fixed64(2_000_000_000)]);
+
+        let attributes = values(&span, 9)
+            .iter()
+            .map(|kv| values(kv.bytes(), 1)[0].string().to_owned())
+            .collect::<Vec<_>>();
+        assert_eq!(attributes, ["error", "n"]);
+
+        let events = values(&span, 11);
+        assert_eq!(events.len(), 1);
+        assert_eq!(values(events[0].bytes(), 2)[0].string(), "error");
+
+        let links = values(&span, 13);
+        assert_eq!(links.len(), 1);
+        assert_eq!(
+            values(links[0].bytes(), 2)[0].bytes(),
+            &4u64.to_be_bytes()[..]
+        );
+
+        let status = &values(&span, 15)[0];
+        assert_eq!(
+            values(status.bytes(), 3),
+            [Value::Varint(STATUS_CODE_ERROR)]
+        );
+    }
+
+    #[test]
+    fn debug_root_is_not_linked() {
+        let parent = SpanContext::new(SpanContextState::debug_root("x"), Vec::new());
+        let span = TestSpan {
+            child_of: vec![parent],
+            ..TestSpan::default()
+        }
+        .finish();
+        assert_eq!(span.references().len(), 1);
+
+        let exporter = OtlpExporter::new("bar");
+        let request = exporter.encode(&[span]);
+        let resource_spans = &values(&request, 1)[0];
+        let scope_spans = &values(resource_spans.bytes(), 2)[0];
+        let span = values(scope_spans.bytes(), 2)[0].bytes().to_vec();
+        assert!(values(&span, 4).is_empty());
+        assert!(values(&span, 13).is_empty());
+    }
+
+    #[test]
+    fn export_retries() {
+        let (url, requests) = test_server::spawn(vec![503, 429, 200, 503, 400]);
+        let mut exporter = OtlpExporter::new("bar")
+            .endpoint(&format!("{}/v1/traces", url))
+            .unwrap()
+            .retry_backoff(Duration::from_millis(1));
+
+        exporter.export(vec![finished_span()]).unwrap();
+        for _ in 0..3 {
+            let request = requests.recv().unwrap();
+            assert_eq!(request.request_line, "POST /v1/traces HTTP/1.1");
+            assert_eq!(
+                request.header("content-type"),
+                Some("application/x-protobuf")
+            );
+        }
+
+        // Non-retryable status
+        assert!(exporter.export(vec![finished_span()]).is_err());
+        assert_eq!(requests.iter().count(), 2);
+    }
+
+    #[test]
+    fn retry_after_is_capped_by_max_elapsed_time() {
+        let (url, requests) =
+            test_server::spawn_with_header(vec![503, 503, 503], "Retry-After: 3600");
+        let mut exporter = OtlpExporter::new("bar")
+            .endpoint(&format!("{}/v1/traces", url))
+            .unwrap()
+            .max_elapsed_time(Duration::from_millis(50));
+
+        let started = Instant::now();
+        assert!(exporter.export(vec![finished_span()]).is_err());
+        assert!(started.elapsed() < Duration::from_secs(10));
+        assert_eq!(requests.try_iter().count(), 2);
+    }
+
+    #[test]
+    fn transport_errors_are_retried_within_max_elapsed_time() {
+        let addr = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let mut exporter = OtlpExporter::new("bar")
+            .endpoint(&format!("http://{}/v1/traces", addr))
+            .unwrap()
+            .max_retries(100)
+            .retry_backoff(Duration::MAX)
+            .max_elapsed_time(Duration::from_millis(50));
+
+        let started = Instant::now();
+        assert!(exporter.export(vec![finished_span()]).is_err());
+        assert!(started.elapsed() >= Duration::from_millis(50));
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+}
@@ -0,0 +1,196 @@
+//! Minimal writer of the [Protocol Buffers][protobuf] wire format used by the exporters.
+//!
+//! [protobuf]: https://protobuf.dev/programming-guides/encoding/
+
+const WIRE_VARINT: u32 = 0;
+const WIRE_FIXED64: u32 = 1;
+const WIRE_LEN: u32 = 2;
+const WIRE_FIXED32: u32 = 5;
+
+/// Writer of the Protocol Buffers wire format.
+#[derive(Debug, Default)]
+pub(crate) struct ProtoWriter {
+    buf: Vec<u8>,
+}
+impl ProtoWriter {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn into_bytes(self) -> Vec<u8> {
+        self.buf
+    }
+
+    pub(crate) fn uint64_field(&mut self, field: u32, value: u64) {
+        self.write_key(field, WIRE_VARINT);
+        self.write_varint(value);
+    }
+
+    pub(crate) fn int64_field(&mut self, field: u32, value: i64) {
+        self.uint64_field(field, value as u64);
+    }
+
+    pub(crate) fn bool_field(&mut self, field: u32, value: bool) {
+        self.uint64_field(field, u64::from(value));
+    }
+
+    pub(crate) fn fixed64_field(&mut self, field: u32, value: u64) {
+        self.write_key(field, WIRE_FIXED64);
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub(crate) fn fixed32_field(&mut self, field: u32, value: u32) {
+        self.write_key(field, WIRE_FIXED32);
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub(crate) fn double_field(&mut self, field: u32, value: f64) {
+        self.write_key(field, WIRE_FIXED64);
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub(crate) fn bytes_field(&mut self, field: u32, value: &[u8]) {
+        self.write_key(field, WIRE_LEN);
+        self.write_varint(value.len() as u64);
+        self.buf.extend_from_slice(value);
+    }
+
+    pub(crate) fn string_field(&mut self, field: u32, value: &str) {
+        self.bytes_field(field, value.as_bytes());
+    }
+
+    /// Writes an embedded message encoded by `f`.
+    pub(crate) fn message_field<F>(&mut self, field: u32, f: F)
+    where
+        F: FnOnce(&mut ProtoWriter),
+    {
+        let mut message = ProtoWriter::new();
+        f(&mut message);
+        self.bytes_field(field, &message.buf);
+    }
+
+    fn write_key(&mut self, field: u32, wire_type: u32) {
+        self.write_varint(u64::from((field << 3) | wire_type));
+    }
+
+    fn write_varint(&mut self, mut n: u64) {
+        while n >= 0x80 {
+            self.buf.push((n as u8) | 0x80);
+            n >>= 7;
+        }
+        self.buf.push(n as u8);
+    }
+}
+
+/// Decoder of the Protocol Buffers wire format for testing.
+#[cfg(test)]
+pub(crate) mod decode {
+    use std::convert::TryInto;
+
+    /// Decoded field value.
+    #[derive(Debug, Clone, PartialEq)]
+    pub(crate) enum Value {
+        Varint(u64),
+        Fixed64(u64),
+        Bytes(Vec<u8>),
+        Fixed32(u32),
+    }
+    impl Value {
+        pub(crate) fn bytes(&self) -> &[u8] {
+            match self {
+                Value::Bytes(b) => b,
+                _ => panic!("Not a length-delimited value: {:?}", self),
+            }
+        }
+
+        pub(crate) fn string(&self) -> &str {
+            std::str::from_utf8(self.bytes()).unwrap()
+        }
+    }
+
+    /// Decodes the fields of a message.
+    pub(crate) fn fields(mut bytes: &[u8]) -> Vec<(u32, Value)> {
+        let mut fields = Vec::new();
+        while !bytes.is_empty() {
+            let key = varint(&mut bytes);
+            let value = match key & 0x7 {
+                0 => Value::Varint(varint(&mut bytes)),
+                1 => {
+                    let (v, rest) = bytes.split_at(8);
+                    bytes = rest;
+                    Value::Fixed64(u64::from_le_bytes(v.try_into().unwrap()))
+                }
+                2 => {
+                    let len = varint(&mut bytes) as usize;
+                    let (v, rest) = bytes.split_at(len);
+                    bytes = rest;
+                    Value::Bytes(v.to_vec())
+                }
+                5 => {
+                    let (v, rest) = bytes.split_at(4);
+                    bytes = rest;
+                    Value::Fixed32(u32::from_le_bytes(v.try_into().unwrap()))
+                }
+                t => panic!("Unknown wire type: {}", t),
+            };
+            fields.push(((key >> 3) as u32, value));
+        }
+        fields
+    }
+
+    /// Returns the values of the field `field` of a message.
+    pub(crate) fn values(bytes: &[u8], field: u32) -> Vec<Value> {
+        fields(bytes)
+            .into_iter()
+            .filter(|(f, _)| *f == field)
+            .map(|(_, v)| v)
+            .collect()
+    }
+
+    fn varint(bytes: &mut &[u8]) -> u64 {
+        let mut n = 0;
+        for i in 0.. {
+            let b = bytes[i];
+            n |= u64::from(b & 0x7f) << (7 * i);
+            if b < 0x80 {
+                *bytes = &bytes[i + 1..];
+                break;
+            }
+        }
+        n
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::decode::{fields, Value};
+    use super::*;
+
+    #[test]
+    fn proto_writer_works() {
+        let mut w = ProtoWriter::new();
+        w.uint64_field(1, 150);
+        w.int64_field(2, -1);
+        w.string_field(3, "foo");
+        w.message_field(4, |w| {
+            w.bool_field(1, true);
+            w.fixed32_field(2, 7);
+        });
+        w.fixed64_field(5, 8);
+        let bytes = w.into_bytes();
+        assert_eq!(&bytes[..3], &[0x08, 0x96, 0x01]);
+        assert_eq!(
+            fields(&bytes),
+            vec![
+                (1, Value::Varint(150)),
+                (2, Value::Varint(u64::MAX)),
+                (3, Value::Bytes(b"foo".to_vec())),
+                (
+                    4,
+                    Value::Bytes(vec![0x08, 0x01, 0x15, 0x07, 0x00, 0x00, 0x00])
+                ),
+                (5, Value::Fixed64(8)),
+            ]
+        );
+    }
+}
@@ -0,0 +1,169 @@
+//! Minimal writer of the [Thrift compact protocol][compact] used by the exporters.
+//!
+//! [compact]: https://github.com/apache/thrift/blob/master/doc/specs/thrift-compact-protocol.md
+
+/// Thrift compact protocol element type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Type {
+    I32 = 5,
+    I64 = 6,
+    Double = 7,
+    Binary = 8,
+    List = 9,
+    Struct = 12,
+}
+
+/// Thrift message type `ONEWAY`.
+pub(crate) const MESSAGE_TYPE_ONEWAY: u8 = 4;
+
+const PROTOCOL_ID: u8 = 0x82;
+const VERSION: u8 = 1;
+const BOOLEAN_TRUE: u8 = 1;
+const BOOLEAN_FALSE: u8 = 2;
+
+/// Writer of the Thrift compact protocol.
+#[derive(Debug, Default)]
+pub(crate) struct CompactWriter {
+    buf: Vec<u8>,
+    last_field_id: i16,
+    last_field_ids: Vec<i16>,
+}
+impl CompactWriter {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn into_bytes(self) -> Vec<u8> {
+        self.buf
+    }
+
+    pub(crate) fn message_begin(&mut self, name: &str, message_type: u8, seq_id: i32) {
+        self.buf.push(PROTOCOL_ID);
+        self.buf.push((message_type << 5) | VERSION);
+        self.write_varint(u64::from(seq_id as u32));
+        self.write_binary(name.as_bytes());
+    }
+
+    pub(crate) fn struct_begin(&mut self) {
+        self.last_field_ids.push(self.last_field_id);
+        self.last_field_id = 0;
+    }
+
+    pub(crate) fn struct_end(&mut self) {
+        self.buf.push(0); // STOP
+        self.last_field_id = self.last_field_ids.pop().unwrap_or(0);
+    }
+
+    pub(crate) fn field_begin(&mut self, ty: Type, id: i16) {
+        self.write_field_header(ty as u8, id);
+    }
+
+    pub(crate) fn list_begin(&mut self, elem_ty: Type, size: usize) {
+        let elem_ty = elem_ty as u8;
+        if size < 15 {
+            self.buf.push(((size as u8) << 4) | elem_ty);
+        } else {
+            self.buf.push(0xf0 | elem_ty);
+            self.write_varint(size as u64);
+        }
+    }
+
+    pub(crate) fn bool_field(&mut self, id: i16, value: bool) {
+        let ty = if value { BOOLEAN_TRUE } else { BOOLEAN_FALSE };
+        self.write_field_header(ty, id);
+    }
+
+    pub(crate) fn i32_field(&mut self, id: i16, value: i32) {
+        self.field_begin(Type::I32, id);
+        self.write_i32(value);
+    }
+
+    pub(crate) fn i64_field(&mut self, id: i16, value: i64) {
+        self.field_begin(Type::I64, id);
+        self.write_i64(value);
+    }
+
+    pub(crate) fn double_field(&mut self, id: i16, value: f64) {
+        self.field_begin(Type::Double, id);
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub(crate) fn string_field(&mut self, id: i16, value: &str) {
+        self.field_begin(Type::Binary, id);
+        self.write_binary(value.as_bytes());
+    }
+
+    /// Appends the bytes which have already been encoded by another `CompactWriter`.
+    pub(crate) fn write_raw(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+
+    pub(crate) fn write_i32(&mut self, n: i32) {
+        self.write_varint(u64::from(((n << 1) ^ (n >> 31)) as u32));
+    }
+
+    pub(crate) fn write_i64(&mut self, n: i64) {
+        self.write_varint(((n << 1) ^ (n >> 63)) as u64);
+    }
+
+    pub(crate) fn write_binary(&mut self, bytes: &[u8]) {
+        self.write_varint(bytes.len() as u64);
+        self.buf.extend_from_slice(bytes);
+    }
+
+    fn write_field_header(&mut self, ty: u8, id: i16) {
+        let delta = i32::from(id) - i32::from(self.last_field_id);
+        if 0 < delta && delta <= 15 {
+            self.buf.push(((delta as u8) << 4) | ty);
+        } else {
+            self.buf.push(ty);
+            self.write_i32(i32::from(id));
+        }
+        self.last_field_id = id;
+    }
+
+    fn write_varint(&mut self, mut n: u64) {
+        while n >= 0x80 {
+            self.buf.push((n as u8) | 0x80);
+            n >>= 7;
+        }
+        self.buf.push(n as u8);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compact_writer_works() {
+        let mut w = CompactWriter::new();
+        w.message_begin("emitBatch", MESSAGE_TYPE_ONEWAY, 0);
+        w.struct_begin();
+        w.i64_field(1, -1);
+        w.string_field(2, "foo");
+        w.bool_field(3, true);
+        w.i32_field(20, 300);
+        w.field_begin(Type::List, 21);
+        w.list_begin(Type::Struct, 1);
+        w.struct_begin();
+        w.double_field(1, 1.5);
+        w.struct_end();
+        w.struct_end();
+        assert_eq!(
+            w.into_bytes(),
+            [
+                &[0x82, 0x81, 0x00, 0x09][..],
+                b"emitBatch",
+                &[0x16, 0x01], // i64 -1
+                &[0x18, 0x03, b'f', b'o', b'o'],
+                &[0x11],                   // bool true
+                &[0x05, 0x28, 0xd8, 0x04], // long form field header, i32 300
+                &[0x19, 0x1c],             // list<struct> of size 1
+                &[0x17, 0, 0, 0, 0, 0, 0, 0xf8, 0x3f, 0x00],
+                &[0x00],
+            ]
+            .concat()
+        );
+    }
+}
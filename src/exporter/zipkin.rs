@@ -0,0 +1,268 @@
+use super::http::{self, HttpUrl};
+use super::json::{ArrayWriter, ObjectWriter};
+use super::{format_log, format_tag_value, unix_micros, Exporter};
+use crate::span::FinishedSpan;
+use crate::state::SpanContextState;
+use crate::tag::{Tag, TagValue};
+use crate::{ErrorKind, Result};
+use std::time::Duration;
+
+/// The default endpoint of `ZipkinExporter`.
+pub const DEFAULT_ZIPKIN_ENDPOINT: &str = "http://127.0.0.1:9411/api/v2/spans";
+
+/// Exporter which sends spans to a [Zipkin][zipkin] compatible backend using the v2 JSON API.
+///
+/// The fields of a `FinishedSpan` are mapped as follows:
+///
+/// - The `span.kind` tag: `kind` (if the value is one of `client`, `server`, `producer` and `consumer`)
+/// - The `peer.service`, `peer.ipv4`, `peer.ipv6` and `peer.port` tags: `remoteEndpoint`
+/// - Other tags: `tags`
+/// - Logs: `annotations`
+///
+/// [zipkin]: https://zipkin.io/zipkin-api/
+///
+/// # Examples
+///
+/// ```
+/// use rustracing::exporter::ZipkinExporter;
+///
+/// let exporter = ZipkinExporter::new("my-service")
+///     .endpoint("http://zipkin:9411/api/v2/spans")
+///     .unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct ZipkinExporter {
+    service_name: String,
+    url: HttpUrl,
+    timeout: Duration,
+}
+impl ZipkinExporter {
+    /// Makes a new `ZipkinExporter` instance.
+    ///
+    /// `service_name` is used as the `serviceName` of the `localEndpoint` of the exported spans.
+    pub fn new<N: Into<String>>(service_name: N) -> Self {
+        ZipkinExporter {
+            service_name: service_name.into(),
+            url: DEFAULT_ZIPKIN_ENDPOINT.parse().expect("never fails"),
+            timeout: Duration::from_secs(10),
+        }
+    }
+
+    /// Sets the URL to which the spans are posted.
+    ///
+    /// The default value is `DEFAULT_ZIPKIN_ENDPOINT`.
+    ///
+    /// # Errors
+    ///
+    /// If `url` is not a valid `http://` URL,
+    /// it will return an error with the kind `ErrorKind::InvalidInput`.
+    pub fn endpoint(mut self, url: &str) -> Result<Self> {
+        self.url = track!(url.parse())?;
+        Ok(self)
+    }
+
+    /// Sets the timeout of a request.
+    ///
+    /// The default value is 10 seconds.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Encodes the given spans as a Zipkin v2 JSON array.
+    pub fn encode(&self, spans: &[FinishedSpan<SpanContextState>]) -> String {
+        let mut out = String::new();
+        let mut array = ArrayWriter::new(&mut out);
+        for span in spans {
+            self.encode_span(array.element(), span);
+        }
+        array.finish();
+        out
+    }
+
+    fn encode_span(&self, out: &mut String, span: &FinishedSpan<SpanContextState>) {
+        let state = span.context().state();
+        let mut object = ObjectWriter::new(out);
+        object
+            .string("traceId", &state.trace_id().to_compact_string())
+            .string("id", &state.span_id().to_string());
+        if let Some(parent) = state.parent_span_id() {
+            object.string("parentId", &parent.to_string());
+        }
+        object.string("name", span.operation_name());
+
+        let kind = span
+            .tags()
+            .iter()
+            .find(|t| t.name() == "span.kind")
+            .and_then(|t| zipkin_kind(t.value()));
+        if let Some(kind) = kind {
+            object.string("kind", kind);
+        }
+
+        let start = unix_micros(span.start_time());
+        let finish = unix_micros(span.finish_time());
+        object
+            .u64("timestamp", start)
+            .u64("duration", finish.saturating_sub(start).max(1));
+        if state.is_debug() {
+            object.bool("debug", true);
+        }
+
+        {
+            let mut endpoint = ObjectWriter::new(object.key("localEndpoint"));
+            endpoint.string("serviceName", &self.service_name);
+            endpoint.finish();
+        }
+        if span.tags().iter().any(is_remote_endpoint_tag) {
+            let mut endpoint = ObjectWriter::new(object.key("remoteEndpoint"));
+            for tag in span.tags().iter().filter(|t| is_remote_endpoint_tag(t)) {
+                match (tag.name(), tag.value()) {
+                    ("peer.port", TagValue::Integer(port)) => {
+                        endpoint.i64("port", *port);
+                    }
+                    ("peer.service", value) => {
+                        endpoint.string("serviceName", &format_tag_value(value));
+                    }
+                    ("peer.ipv4", value) => {
+                        endpoint.string("ipv4", &format_tag_value(value));
+                    }
+                    (_, value) => {
+                        endpoint.string("ipv6", &format_tag_value(value));
+                    }
+                }
+            }
+            endpoint.finish();
+        }
+
+        if !span.logs().is_empty() {
+            let mut annotations = ArrayWriter::new(object.key("annotations"));
+            for log in span.logs() {
+                let mut annotation = ObjectWriter::new(annotations.element());
+                annotation
+                    .u64("timestamp", unix_micros(log.time()))
+                    .string("value", &format_log(log));
+                annotation.finish();
+            }
+            annotations.finish();
+        }
+
+        let is_tag =
+            |t: &&Tag| !(is_remote_endpoint_tag(t) || (t.name() == "span.kind" && kind.is_some()));
+        if span.tags().iter().any(|t| is_tag(&t)) {
+            let mut tags = ObjectWriter::new(object.key("tags"));
+            for tag in span.tags().iter().filter(is_tag) {
+                tags.string(tag.name(), &format_tag_value(tag.value()));
+            }
+            tags.finish();
+        }
+        object.finish();
+    }
+}
+impl Exporter<SpanContextState> for ZipkinExporter {
+    fn export(&mut self, spans: Vec<FinishedSpan<SpanContextState>>) -> Result<()> {
+        if spans.is_empty() {
+            return Ok(());
+        }
+        let body = self.encode(&spans);
+        let response = track!(http::post(
+            &self.url,
+            "application/json",
+            &[],
+            body.as_bytes(),
+            self.timeout
+        ))?;
+        track_assert!(
+            response.is_success(),
+            ErrorKind::Other,
+            "Zipkin returned an error: status={}, body={:?}",
+            response.status(),
+            String::from_utf8_lossy(response.body())
+        );
+        Ok(())
+    }
+}
+
+fn zipkin_kind(value: &TagValue) -> Option<&'static str> {
+    match value {
+        TagValue::String(kind) => match kind.as_ref() {
+            "client" => Some("CLIENT"),
+            "server" => Some("SERVER"),
+            "producer" => Some("PRODUCER"),
+            "consumer" => Some("CONSUMER"),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn is_remote_endpoint_tag(tag: &Tag) -> bool {
+    match tag.name() {
+        "peer.service" | "peer.ipv4" | "peer.ipv6" => true,
+        "peer.port" => matches!(tag.value(), TagValue::Integer(_)),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::http::test_server;
+    use super::super::test_span::TestSpan;
+    use super::*;
+    use crate::tag::StdTag;
+
+    fn finished_span() -> FinishedSpan<SpanContextState> {
+        TestSpan {
+            operation_name: "get",
+            trace_id: 0x1234,
+            span_id: 0xabcd,
+            parent_span_id: Some(0x1),
+            start_micros: 1_000_000,
+            finish_micros: 1_000_250,
+            tags: vec![
+                StdTag::span_kind("client"),
+                StdTag::peer_ip("127.0.0.1".parse().unwrap()),
+                StdTag::peer_port(80),
+                StdTag::http_status_code(200),
+            ],
+            logs: vec![(1_000_100, vec![("event", "sent")])],
+            ..TestSpan::default()
+        }
+        .finish()
+    }
+
+    #[test]
+    fn encode_works() {
+        let exporter = ZipkinExporter::new("foo");
+        let json = exporter.encode(&[finished_span()]);
+        assert_eq!(
+            json,
+            concat!(
+                r#"[{"traceId":"0000000000001234","id":"000000000000abcd","parentId":"0000000000000001","#,
+                r#""name":"get","kind":"CLIENT","timestamp":1000000,"duration":250,"#,
+                r#""localEndpoint":{"serviceName":"foo"},"#,
+                r#""remoteEndpoint":{"ipv4":"127.0.0.1","port":80},"#,
+                r#""annotations":[{"timestamp":1000100,"value":"sent"}],"#,
+                r#""tags":{"http.status_code":"200"}}]"#
+            )
+        );
+    }
+
+    #[test]
+    fn export_works() {
+        let (url, requests) = test_server::spawn(vec![202, 500]);
+        let mut exporter = ZipkinExporter::new("foo")
+            .endpoint(&format!("{}/api/v2/spans", url))
+            .unwrap();
+
+        exporter.export(vec![finished_span()]).unwrap();
+        let request = requests.recv().unwrap();
+        assert_eq!(request.request_line, "POST /api/v2/spans HTTP/1.1");
+        assert_eq!(request.header("content-type"), Some("application/json"));
+        assert!(request
+            .body
+            .starts_with(b"[{\"traceId\":\"0000000000001234\""));
+
+        assert!(exporter.export(vec![finished_span()]).is_err());
+    }
+}
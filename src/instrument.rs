@@ -0,0 +1,193 @@
+//! Instrumentation of futures.
+//!
+//! # Examples
+//!
+//! ```
+//! use rustracing::instrument::Instrument;
+//! use rustracing::sampler::AllSampler;
+//! use rustracing::state::SpanContextState;
+//! use rustracing::Tracer;
+//!
+//! let (span_tx, span_rx) = crossbeam_channel::bounded(10);
+//! let tracer = Tracer::<_, SpanContextState>::with_sender(AllSampler, span_tx);
+//!
+//! let span = tracer.span("request").start();
+//! let t = tracer.clone();
+//! let future = async move {
+//!     // `request` is active while this future is being polled.
+//!     let _child = t.span("query").start();
+//! }
+//! .instrument(span);
+//! # drop(future);
+//! // Runs `future` with an executor ...
+//! ```
+use crate::scope::SpanScope;
+use crate::span::{Span, SpanHandle};
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// Extension trait for attaching a span to a `Future`.
+pub trait Instrument: Future + Sized {
+    /// Attaches `span` to this future.
+    ///
+    /// The span becomes the active span of the current thread during every `poll`,
+    /// and it will be finished when the future completes or is dropped.
+    fn instrument<T>(self, span: Span<T>) -> Instrumented<Self, T>
+    where
+        T: Clone + 'static,
+    {
+        Instrumented {
+            future: self,
+            handle: span.handle(),
+            span: Some(span),
+        }
+    }
+
+    /// Attaches the span associated with `handle` to this future.
+    ///
+    /// The span becomes the active span of the current thread during every `poll`.
+    /// Unlike `instrument`, the lifetime of the span is not managed by the resulting future.
+    fn instrument_handle<T>(self, handle: SpanHandle<T>) -> Instrumented<Self, T>
+    where
+        T: Clone + 'static,
+    {
+        Instrumented {
+            future: self,
+            handle,
+            span: None,
+        }
+    }
+}
+impl<F: Future> Instrument for F {}
+
+/// A future instrumented with a span.
+///
+/// This is created by the methods of `Instrument` trait.
+#[derive(Debug)]
+#[must_use = "futures do nothing unless polled"]
+pub struct Instrumented<F, T> {
+    future: F,
+    handle: SpanHandle<T>,
+    span: Option<Span<T>>,
+}
+impl<F, T> Instrumented<F, T> {
+    /// Returns the handle of the attached span.
+    pub fn span_handle(&self) -> &SpanHandle<T> {
+        &self.handle
+    }
+}
+impl<F, T> Future for Instrumented<F, T>
+where
+    F: Future,
+    T: Clone + 'static,
+{
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        // SAFETY: `future` is never moved out of `self` (and `Instrumented` has no `Drop` implementation),
+        // so it stays pinned. The other fields are not structurally pinned.
+        let this = unsafe { self.get_unchecked_mut() };
+        let future = unsafe { Pin::new_unchecked(&mut this.future) };
+
+        let scope = SpanScope::enter(&this.handle);
+        let result = future.poll(cx);
+        drop(scope);
+
+        if result.is_ready() {
+            // Finishes the span.
+            this.span = None;
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::AllSampler;
+    use crate::state::SpanContextState;
+    use crate::Tracer;
+    use std::task::{RawWaker, RawWakerVTable, Waker};
+
+    fn noop_waker() -> Waker {
+        fn clone(_: *const ()) -> RawWaker {
+            RawWaker::new(std::ptr::null(), &VTABLE)
+        }
+        fn noop(_: *const ()) {}
+        static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, noop, noop, noop);
+        unsafe { Waker::from_raw(RawWaker::new(std::ptr::null(), &VTABLE)) }
+    }
+
+    /// A future which is pending at the first poll.
+    struct YieldOnce(bool);
+    impl Future for YieldOnce {
+        type Output = ();
+
+        fn poll(mut self: Pin<&mut Self>, _cx: &mut Context) -> Poll<()> {
+            if self.0 {
+                Poll::Ready(())
+            } else {
+                self.0 = true;
+                Poll::Pending
+            }
+        }
+    }
+
+    #[test]
+    fn span_is_active_during_poll() {
+        let (span_tx, span_rx) = crossbeam_channel::bounded(10);
+        let tracer = Tracer::<_, SpanContextState>::with_sender(AllSampler, span_tx);
+
+        let t = tracer.clone();
+        let future = async move {
+            YieldOnce(false).await;
+            let _child = t.span("child").start();
+        };
+        let span = tracer.span("parent").start();
+        let parent_id = span.context().unwrap().state().span_id();
+        let mut future = Box::pin(future.instrument(span));
+
+        let waker = noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        assert!(future.as_mut().poll(&mut cx).is_pending());
+        assert!(tracer.active_span().is_none());
+        assert!(span_rx.try_recv().is_err());
+
+        assert!(future.as_mut().poll(&mut cx).is_ready());
+        assert!(tracer.active_span().is_none());
+
+        let child = span_rx.try_recv().unwrap();
+        assert_eq!(child.operation_name(), "child");
+        assert_eq!(child.context().state().parent_span_id(), Some(parent_id));
+
+        // The span is finished when the future completes.
+        let parent = span_rx.try_recv().unwrap();
+        assert_eq!(parent.operation_name(), "parent");
+    }
+
+    #[test]
+    fn span_is_finished_when_dropped() {
+        let (span_tx, span_rx) = crossbeam_channel::bounded(10);
+        let tracer = Tracer::<_, SpanContextState>::with_sender(AllSampler, span_tx);
+
+        let span = tracer.span("parent").start();
+        let handle = span.handle();
+        let mut future = Box::pin(YieldOnce(false).instrument(span));
+        let waker = noop_waker();
+        assert!(future
+            .as_mut()
+            .poll(&mut Context::from_waker(&waker))
+            .is_pending());
+        drop(future);
+        assert_eq!(span_rx.try_recv().unwrap().operation_name(), "parent");
+
+        // Handles do not finish spans.
+        let mut future = Box::pin(async {}.instrument_handle(handle));
+        assert!(future
+            .as_mut()
+            .poll(&mut Context::from_waker(&waker))
+            .is_ready());
+        assert!(span_rx.try_recv().is_err());
+    }
+}
@@ -52,14 +52,23 @@
 extern crate trackable;
 
 pub use crate::error::{Error, ErrorKind};
-pub use crate::tracer::Tracer;
+pub use crate::tracer::{Tracer, TracerStats};
 
 pub mod carrier;
 pub mod convert;
+pub mod exporter;
+pub mod instrument;
 pub mod log;
+pub mod processor;
+pub mod propagation;
+pub mod reporter;
 pub mod sampler;
+pub mod scope;
 pub mod span;
+pub mod state;
+pub mod store;
 pub mod tag;
+pub mod tail;
 
 mod error;
 mod tracer;
@@ -70,7 +79,7 @@ pub type Result<T> = std::result::Result<T, Error>;
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::sampler::AllSampler;
+    use crate::sampler::{AllSampler, NullSampler};
     use crate::tag::{StdTag, Tag};
     use std::thread;
     use std::time::Duration;
@@ -92,6 +101,19 @@ mod tests {
         assert_eq!(span.operation_name(), "it_works");
     }
 
+    #[test]
+    fn span_does_not_require_clone_state() {
+        #[derive(Debug)]
+        struct NonCloneState;
+
+        let (span_tx, span_rx) = crossbeam_channel::bounded(10);
+        let tracer = Tracer::with_sender(AllSampler, span_tx);
+        {
+            let _span = tracer.span("foo").start_with_state(NonCloneState);
+        }
+        assert_eq!(span_rx.try_recv().unwrap().operation_name(), "foo");
+    }
+
     #[test]
     fn example_code_works() {
         // Creates a tracer
@@ -147,4 +169,30 @@ mod tests {
 
         assert!(span_rx.is_empty());
     }
+
+    #[test]
+    fn tracer_stats_works() {
+        let (span_tx, span_rx) = crossbeam_channel::bounded(2);
+        let tracer = Tracer::with_sender(AllSampler, span_tx);
+        {
+            let span = tracer.span("first").start_with_state(());
+            let _child = span.child("second", |options| options.start_with_state(()));
+            let _ = tracer.span("third").start_with_state(());
+        }
+        let stats = tracer.stats();
+        assert_eq!(stats.started(), 3);
+        assert_eq!(stats.delivered(), 2);
+        assert_eq!(stats.dropped_full(), 1);
+        assert_eq!(stats.dropped(), 1);
+
+        let null_tracer = tracer.clone_with_sampler(NullSampler);
+        let _ = null_tracer.span("fourth").start_with_state(());
+        assert_eq!(tracer.stats().sampled_out(), 1);
+
+        drop(span_rx);
+        let _ = tracer.span("fifth").start_with_state(());
+        let stats = tracer.stats();
+        assert_eq!(stats.started(), 4);
+        assert_eq!(stats.dropped_disconnected(), 1);
+    }
 }
@@ -0,0 +1,213 @@
+//! `SpanProcessor` trait.
+use crate::span::{CandidateSpan, FinishedSpan};
+use crate::tag::Tag;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread;
+
+/// `SpanProcessor` allows for enriching, filtering and transforming spans.
+///
+/// Processors are registered on a `Tracer` by `Tracer::add_processor`
+/// and are invoked in the registration order.
+///
+/// # Examples
+///
+/// ```
+/// use rustracing::processor::SpanProcessor;
+/// use rustracing::sampler::AllSampler;
+/// use rustracing::span::{CandidateSpan, FinishedSpan};
+/// use rustracing::tag::Tag;
+/// use rustracing::Tracer;
+///
+/// struct Enricher;
+/// impl<T> SpanProcessor<T> for Enricher {
+///     fn on_start(&self, _span: &CandidateSpan<T>, tags: &mut Vec<Tag>) {
+///         tags.push(Tag::new("hostname", "foo"));
+///     }
+///
+///     fn on_end(&self, span: &mut FinishedSpan<T>) -> bool {
+///         // Drops health check spans
+///         span.operation_name() != "health_check"
+///     }
+/// }
+///
+/// let (span_tx, span_rx) = crossbeam_channel::bounded(10);
+/// let mut tracer = Tracer::with_sender(AllSampler, span_tx);
+/// tracer.add_processor(Enricher);
+/// {
+///     let _span = tracer.span("health_check").start_with_state(());
+///     let _span = tracer.span("foo").start_with_state(());
+/// }
+///
+/// let span = span_rx.try_recv().unwrap();
+/// assert_eq!(span.operation_name(), "foo");
+/// assert_eq!(span.tags()[0].name(), "hostname");
+/// assert!(span_rx.try_recv().is_err());
+/// ```
+pub trait SpanProcessor<T>: Send + Sync {
+    /// This method is called when a sampled span is started.
+    ///
+    /// The operation name, tags and references of the span are available via `span`.
+    ///
+    /// The tags pushed to `tags` are added to the span.
+    /// The tags explicitly set by the caller take precedence:
+    /// if the span already has a tag with the same name, the pushed one is ignored.
+    ///
+    /// The default implementation does nothing.
+    fn on_start(&self, _span: &CandidateSpan<T>, _tags: &mut Vec<Tag>) {}
+
+    /// This method is called when a sampled span is finished,
+    /// before it is sent to the `SpanReceiver`.
+    ///
+    /// If this method returns `false`, the span is dropped and the subsequent processors are not invoked.
+    ///
+    /// The default implementation does nothing and returns `true`.
+    fn on_end(&self, _span: &mut FinishedSpan<T>) -> bool {
+        true
+    }
+}
+
+/// This processor adds the `thread.id` and `thread.name` tags to spans.
+///
+/// The tags indicate the thread on which each span is started.
+/// `thread.id` is a process-unique integer assigned to each thread by this crate.
+/// `thread.name` is added only if the thread has a name.
+#[derive(Debug, Default, Clone)]
+pub struct ThreadInfoProcessor;
+impl ThreadInfoProcessor {
+    /// Makes a new `ThreadInfoProcessor` instance.
+    pub fn new() -> Self {
+        ThreadInfoProcessor
+    }
+}
+impl<T> SpanProcessor<T> for ThreadInfoProcessor {
+    fn on_start(&self, _span: &CandidateSpan<T>, tags: &mut Vec<Tag>) {
+        tags.push(Tag::new("thread.id", current_thread_id() as i64));
+        if let Some(name) = thread::current().name() {
+            tags.push(Tag::new("thread.name", name.to_owned()));
+        }
+    }
+}
+
+fn current_thread_id() -> u64 {
+    static NEXT_THREAD_ID: AtomicU64 = AtomicU64::new(1);
+    thread_local! {
+        static THREAD_ID: u64 = NEXT_THREAD_ID.fetch_add(1, Ordering::Relaxed);
+    }
+    THREAD_ID.with(|id| *id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::AllSampler;
+    use crate::span::SpanDelivery;
+    use crate::tag::TagValue;
+    use crate::Tracer;
+
+    struct Renamer;
+    impl SpanProcessor<()> for Renamer {
+        fn on_start(&self, span: &CandidateSpan<()>, tags: &mut Vec<Tag>) {
+            tags.push(Tag::new("name", span.operation_name().to_owned()));
+            tags.push(Tag::new("is_root", span.references().is_empty()));
+        }
+
+        fn on_end(&self, span: &mut FinishedSpan<()>) -> bool {
+            let name = format!("renamed_{}", span.operation_name());
+            span.set_operation_name(name);
+            span.tags_mut().retain(|t| t.name() != "secret");
+            true
+        }
+    }
+
+    struct Filter;
+    impl SpanProcessor<()> for Filter {
+        fn on_end(&self, span: &mut FinishedSpan<()>) -> bool {
+            span.operation_name() != "renamed_drop"
+        }
+    }
+
+    #[test]
+    fn processors_work() {
+        let (span_tx, span_rx) = crossbeam_channel::bounded(10);
+        let mut tracer = Tracer::with_sender(AllSampler, span_tx);
+        tracer.add_processor(Renamer);
+        tracer.add_processor(Filter);
+
+        let span = tracer
+            .span("foo")
+            .tag(Tag::new("secret", "xxx"))
+            .tag(Tag::new("name", "explicit"))
+            .start_with_state(());
+        let child = span.child("drop", |options| options.start_with_state(()));
+        assert_eq!(child.finish(), SpanDelivery::Discarded);
+        assert_eq!(span.finish(), SpanDelivery::Delivered);
+
+        let span = span_rx.try_recv().unwrap();
+        assert_eq!(span.operation_name(), "renamed_foo");
+        let tags = span
+            .tags()
+            .iter()
+            .map(|t| (t.name(), t.value().clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            tags,
+            vec![
+                ("is_root", TagValue::Boolean(true)),
+                ("name", TagValue::String("explicit".into())),
+            ]
+        );
+        assert!(span_rx.try_recv().is_err());
+
+        let stats = tracer.stats();
+        assert_eq!(stats.started(), 2);
+        assert_eq!(stats.delivered(), 1);
+        assert_eq!(stats.discarded(), 1);
+    }
+
+    #[test]
+    fn thread_info_processor_works() {
+        let (span_tx, span_rx) = crossbeam_channel::bounded(10);
+        let mut tracer = Tracer::with_sender(AllSampler, span_tx);
+        tracer.add_processor(ThreadInfoProcessor::new());
+
+        let _ = tracer.span("foo").start_with_state(());
+        let _ = tracer.span("foo").start_with_state(());
+        let named = tracer.clone();
+        std::thread::Builder::new()
+            .name("bar".to_owned())
+            .spawn(move || {
+                let _ = named.span("bar").start_with_state(());
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+        std::thread::spawn(move || {
+            let _ = tracer.span("baz").start_with_state(());
+        })
+        .join()
+        .unwrap();
+
+        let tag = |span: &FinishedSpan<()>, name| {
+            span.tags()
+                .iter()
+                .find(|t| t.name() == name)
+                .map(|t| t.value().clone())
+        };
+        let spans = span_rx.try_iter().collect::<Vec<_>>();
+        assert_eq!(spans.len(), 4);
+        let thread_ids = spans
+            .iter()
+            .map(|span| match tag(span, "thread.id") {
+                Some(TagValue::Integer(id)) => id,
+                other => panic!("unexpected thread.id: {:?}", other),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(thread_ids[0], thread_ids[1]);
+        assert_ne!(thread_ids[1], thread_ids[2]);
+        assert_ne!(thread_ids[2], thread_ids[3]);
+        assert_ne!(thread_ids[1], thread_ids[3]);
+
+        assert_eq!(tag(&spans[2], "thread.name"), Some(TagValue::from("bar")));
+        assert_eq!(tag(&spans[3], "thread.name"), None);
+    }
+}
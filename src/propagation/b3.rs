@@ -0,0 +1,449 @@
+use super::{header_value, Codec};
+use crate::carrier::{IterHttpHeaderFields, SetHttpHeaderField, TextMap};
+use crate::span::SpanContext;
+use crate::state::{SpanContextState, SpanId, TraceId};
+use crate::{ErrorKind, Result};
+
+const TRACE_ID: &str = "X-B3-TraceId";
+const SPAN_ID: &str = "X-B3-SpanId";
+const PARENT_SPAN_ID: &str = "X-B3-ParentSpanId";
+const SAMPLED: &str = "X-B3-Sampled";
+const FLAGS: &str = "X-B3-Flags";
+const SINGLE: &str = "b3";
+
+carrier_adapter! {
+    /// Carrier adapter for the [B3][b3] multiple header format.
+    ///
+    /// The span context is propagated by the `X-B3-TraceId`, `X-B3-SpanId`, `X-B3-ParentSpanId`,
+    /// `X-B3-Sampled` and `X-B3-Flags` fields.
+    ///
+    /// If the sampling state is absent, the extracted context is regarded as sampled.
+    /// A context which only has the sampling state (i.e., no identifiers) is extracted
+    /// as `SpanContextState::sampling_only`, so that the decision is honored by the spans started as its children.
+    /// Injecting a context without valid identifiers results in an error with the kind `ErrorKind::InvalidInput`.
+    ///
+    /// [b3]: https://github.com/openzipkin/b3-propagation
+    B3Multi(B3MultiCodec)
+}
+
+carrier_adapter! {
+    /// Carrier adapter for the [B3][b3] single header format.
+    ///
+    /// The span context is propagated by the `b3` field which has the form of
+    /// `{TraceId}-{SpanId}-{SamplingState}-{ParentSpanId}`.
+    ///
+    /// If the sampling state is absent, the extracted context is regarded as sampled.
+    /// A context which only has the sampling state (i.e., no identifiers) is extracted
+    /// as `SpanContextState::sampling_only`, so that the decision is honored by the spans started as its children.
+    /// Injecting a context without valid identifiers results in an error with the kind `ErrorKind::InvalidInput`.
+    ///
+    /// [b3]: https://github.com/openzipkin/b3-propagation
+    B3Single(B3SingleCodec)
+}
+
+impl Codec for B3MultiCodec {
+    fn inject_to_text_map<C: TextMap>(
+        context: &SpanContext<SpanContextState>,
+        carrier: &mut C,
+    ) -> Result<()> {
+        inject_multi(context.state(), |name, value| {
+            carrier.set(name, value);
+            Ok(())
+        })
+    }
+
+    fn extract_from_text_map<C: TextMap>(
+        carrier: &C,
+    ) -> Result<Option<SpanContext<SpanContextState>>> {
+        let state = track!(extract_multi(|name| Ok(carrier.get(name))))?;
+        Ok(state.map(|state| SpanContext::new(state, Vec::new())))
+    }
+
+    fn inject_to_http_header<C: SetHttpHeaderField>(
+        context: &SpanContext<SpanContextState>,
+        carrier: &mut C,
+    ) -> Result<()> {
+        inject_multi(context.state(), |name, value| {
+            track!(carrier.set_http_header_field(name, value))
+        })
+    }
+
+    fn extract_from_http_header<'a, C: IterHttpHeaderFields<'a>>(
+        carrier: &'a C,
+    ) -> Result<Option<SpanContext<SpanContextState>>> {
+        let state = track!(extract_multi(|name| header_value(carrier, name)))?;
+        Ok(state.map(|state| SpanContext::new(state, Vec::new())))
+    }
+}
+
+impl Codec for B3SingleCodec {
+    fn inject_to_text_map<C: TextMap>(
+        context: &SpanContext<SpanContextState>,
+        carrier: &mut C,
+    ) -> Result<()> {
+        let value = track!(format_single(context.state()))?;
+        carrier.set(SINGLE, &value);
+        Ok(())
+    }
+
+    fn extract_from_text_map<C: TextMap>(
+        carrier: &C,
+    ) -> Result<Option<SpanContext<SpanContextState>>> {
+        if let Some(value) = carrier.get(SINGLE) {
+            let state = track!(parse_single(value.trim()))?;
+            Ok(state.map(|state| SpanContext::new(state, Vec::new())))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn inject_to_http_header<C: SetHttpHeaderField>(
+        context: &SpanContext<SpanContextState>,
+        carrier: &mut C,
+    ) -> Result<()> {
+        let value = track!(format_single(context.state()))?;
+        track!(carrier.set_http_header_field(SINGLE, &value))
+    }
+
+    fn extract_from_http_header<'a, C: IterHttpHeaderFields<'a>>(
+        carrier: &'a C,
+    ) -> Result<Option<SpanContext<SpanContextState>>> {
+        if let Some(value) = track!(header_value(carrier, SINGLE))? {
+            let state = track!(parse_single(value))?;
+            Ok(state.map(|state| SpanContext::new(state, Vec::new())))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+fn inject_multi<F>(state: &SpanContextState, mut set: F) -> Result<()>
+where
+    F: FnMut(&str, &str) -> Result<()>,
+{
+    track_assert!(state.is_valid(), ErrorKind::InvalidInput; state);
+    track!(set(TRACE_ID, &state.trace_id().to_compact_string()))?;
+    track!(set(SPAN_ID, &state.span_id().to_string()))?;
+    if let Some(parent) = state.parent_span_id() {
+        track!(set(PARENT_SPAN_ID, &parent.to_string()))?;
+    }
+    if state.is_debug() {
+        // Debug implies an accept decision, so `X-B3-Sampled` is not sent.
+        track!(set(FLAGS, "1"))?;
+    } else {
+        track!(set(SAMPLED, if state.is_sampled() { "1" } else { "0" }))?;
+    }
+    Ok(())
+}
+
+fn extract_multi<'a, F>(get: F) -> Result<Option<SpanContextState>>
+where
+    F: Fn(&str) -> Result<Option<&'a str>>,
+{
+    let mut state = if let Some(value) = track!(get(TRACE_ID))? {
+        let trace_id = track!(parse_trace_id(value))?;
+        let span_id = track_assert_some!(track!(get(SPAN_ID))?, ErrorKind::InvalidInput);
+        let span_id = track!(parse_span_id(span_id))?;
+        let mut state = SpanContextState::new(trace_id, span_id);
+        if let Some(parent) = track!(get(PARENT_SPAN_ID))? {
+            state.set_parent_span_id(Some(track!(parse_span_id(parent))?));
+        }
+        state
+    } else if track!(get(SAMPLED))?.is_some() || track!(get(FLAGS))?.is_some() {
+        // Only the sampling state is present.
+        SpanContextState::sampling_only(true)
+    } else {
+        return Ok(None);
+    };
+    if let Some(sampled) = track!(get(SAMPLED))? {
+        let sampled = match sampled {
+            "1" | "true" => true,
+            "0" | "false" => false,
+            _ => track_panic!(
+                ErrorKind::InvalidInput,
+                "Unknown sampling state: {:?}",
+                sampled
+            ),
+        };
+        state.set_sampled(sampled);
+    }
+    if let Some(flags) = track!(get(FLAGS))? {
+        match flags {
+            "1" => state.set_debug(true),
+            "0" => {}
+            _ => track_panic!(ErrorKind::InvalidInput, "Unknown flags: {:?}", flags),
+        }
+    }
+    Ok(Some(state))
+}
+
+fn format_single(state: &SpanContextState) -> Result<String> {
+    track_assert!(state.is_valid(), ErrorKind::InvalidInput; state);
+    let sampling_state = if state.is_debug() {
+        "d"
+    } else if state.is_sampled() {
+        "1"
+    } else {
+        "0"
+    };
+    let mut value = format!(
+        "{}-{}-{}",
+        state.trace_id().to_compact_string(),
+        state.span_id(),
+        sampling_state
+    );
+    if let Some(parent) = state.parent_span_id() {
+        value.push('-');
+        value.push_str(&parent.to_string());
+    }
+    Ok(value)
+}
+
+fn parse_single(value: &str) -> Result<Option<SpanContextState>> {
+    let fields = value.split('-').collect::<Vec<_>>();
+    if fields.len() == 1 {
+        // Only the sampling state is present.
+        let mut state = SpanContextState::sampling_only(true);
+        match fields[0] {
+            "1" => {}
+            "0" => state.set_sampled(false),
+            "d" => state.set_debug(true),
+            _ => track_panic!(
+                ErrorKind::InvalidInput,
+                "Unknown sampling state: {:?}",
+                value
+            ),
+        }
+        return Ok(Some(state));
+    }
+    track_assert!(fields.len() <= 4, ErrorKind::InvalidInput; value);
+
+    let trace_id = track!(parse_trace_id(fields[0]))?;
+    let span_id = track!(parse_span_id(fields[1]))?;
+    let mut state = SpanContextState::new(trace_id, span_id);
+    if let Some(&sampling_state) = fields.get(2) {
+        match sampling_state {
+            "1" => state.set_sampled(true),
+            "0" => state.set_sampled(false),
+            "d" => state.set_debug(true),
+            _ => track_panic!(
+                ErrorKind::InvalidInput,
+                "Unknown sampling state: {:?}",
+                value
+            ),
+        }
+    }
+    if let Some(parent) = fields.get(3) {
+        state.set_parent_span_id(Some(track!(parse_span_id(parent))?));
+    }
+    Ok(Some(state))
+}
+
+fn parse_trace_id(s: &str) -> Result<TraceId> {
+    track_assert!(s.len() == 16 || s.len() == 32, ErrorKind::InvalidInput; s);
+    let trace_id: TraceId = track!(s.parse())?;
+    track_assert!(trace_id.is_valid(), ErrorKind::InvalidInput; s);
+    Ok(trace_id)
+}
+
+fn parse_span_id(s: &str) -> Result<SpanId> {
+    track_assert_eq!(s.len(), 16, ErrorKind::InvalidInput; s);
+    let span_id: SpanId = track!(s.parse())?;
+    track_assert!(span_id.is_valid(), ErrorKind::InvalidInput; s);
+    Ok(span_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::{AllSampler, ParentBasedSampler};
+    use crate::Tracer;
+    use std::collections::HashMap;
+
+    fn make_headers(fields: &[(&str, &str)]) -> HashMap<String, String> {
+        fields
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn multi_header_works() {
+        let headers = make_headers(&[
+            ("x-b3-traceid", "80f198ee56343ba864fe8b2a57d3eff7"),
+            ("x-b3-spanid", "05e3ac9a4f6e3b90"),
+            ("x-b3-parentspanid", "e457b5a2e4d86bd1"),
+            ("x-b3-sampled", "0"),
+        ]);
+        let context =
+            SpanContext::<SpanContextState>::extract_from_http_header(&B3Multi::new(&headers))
+                .unwrap()
+                .unwrap();
+        let state = context.state();
+        assert_eq!(
+            state.trace_id(),
+            TraceId::new(0x80f198ee56343ba864fe8b2a57d3eff7)
+        );
+        assert_eq!(state.span_id(), SpanId::new(0x05e3ac9a4f6e3b90));
+        assert_eq!(
+            state.parent_span_id(),
+            Some(SpanId::new(0xe457b5a2e4d86bd1))
+        );
+        assert!(!state.is_sampled());
+
+        let mut injected = HashMap::new();
+        context
+            .inject_to_http_header(&mut B3Multi::new(&mut injected))
+            .unwrap();
+        assert_eq!(injected["X-B3-TraceId"], "80f198ee56343ba864fe8b2a57d3eff7");
+        assert_eq!(injected["X-B3-SpanId"], "05e3ac9a4f6e3b90");
+        assert_eq!(injected["X-B3-ParentSpanId"], "e457b5a2e4d86bd1");
+        assert_eq!(injected["X-B3-Sampled"], "0");
+        assert!(!injected.contains_key("X-B3-Flags"));
+    }
+
+    #[test]
+    fn multi_header_debug_works() {
+        let headers = make_headers(&[
+            ("X-B3-TraceId", "64fe8b2a57d3eff7"),
+            ("X-B3-SpanId", "05e3ac9a4f6e3b90"),
+            ("X-B3-Flags", "1"),
+        ]);
+        let context =
+            SpanContext::<SpanContextState>::extract_from_http_header(&B3Multi::new(&headers))
+                .unwrap()
+                .unwrap();
+        assert_eq!(context.state().trace_id(), TraceId::new(0x64fe8b2a57d3eff7));
+        assert!(context.state().is_debug());
+        assert!(context.state().is_sampled());
+
+        let mut injected = HashMap::new();
+        context
+            .inject_to_text_map(&mut B3Multi::new(&mut injected))
+            .unwrap();
+        assert_eq!(injected["X-B3-TraceId"], "64fe8b2a57d3eff7");
+        assert_eq!(injected["X-B3-Flags"], "1");
+        assert!(!injected.contains_key("X-B3-Sampled"));
+    }
+
+    #[test]
+    fn multi_header_rejects_invalid_input() {
+        for fields in &[
+            &[("X-B3-TraceId", "64fe8b2a57d3eff7")][..],
+            &[
+                ("X-B3-TraceId", "64fe8b2a57d3ef"),
+                ("X-B3-SpanId", "05e3ac9a4f6e3b90"),
+            ][..],
+            &[
+                ("X-B3-TraceId", "64fe8b2a57d3eff7"),
+                ("X-B3-SpanId", "05e3ac9a4f6e3b90"),
+                ("X-B3-Sampled", "yes"),
+            ][..],
+        ] {
+            let headers = make_headers(fields);
+            let e =
+                SpanContext::<SpanContextState>::extract_from_http_header(&B3Multi::new(&headers))
+                    .err();
+            assert_eq!(e.map(|e| *e.kind()), Some(ErrorKind::InvalidInput));
+        }
+
+        let headers = make_headers(&[("X-B3-SpanId", "05e3ac9a4f6e3b90")]);
+        let context =
+            SpanContext::<SpanContextState>::extract_from_http_header(&B3Multi::new(&headers))
+                .unwrap();
+        assert!(context.is_none());
+    }
+
+    #[test]
+    fn sampling_only_headers_are_honored() {
+        let sampler = ParentBasedSampler::new(AllSampler);
+        let (span_tx, span_rx) = crossbeam_channel::unbounded();
+        let tracer = Tracer::with_sender(sampler, span_tx);
+
+        let headers = make_headers(&[("X-B3-Sampled", "0")]);
+        let context =
+            SpanContext::<SpanContextState>::extract_from_http_header(&B3Multi::new(&headers))
+                .unwrap()
+                .unwrap();
+        assert!(!context.state().is_valid());
+        assert!(!context.state().is_sampled());
+        let span = tracer.span("denied").child_of(&context).start();
+        assert!(!span.is_sampled());
+
+        let headers = make_headers(&[("b3", "d")]);
+        let context =
+            SpanContext::<SpanContextState>::extract_from_http_header(&B3Single::new(&headers))
+                .unwrap()
+                .unwrap();
+        {
+            let span = tracer.span("debug").child_of(&context).start();
+            let state = span.context().unwrap().state();
+            assert!(state.is_valid());
+            assert!(state.is_debug());
+            assert_eq!(state.parent_span_id(), None);
+        }
+        let span = span_rx.try_recv().unwrap();
+        assert_eq!(span.operation_name(), "debug");
+        assert!(span_rx.try_recv().is_err());
+
+        let e = context
+            .inject_to_http_header(&mut B3Single::new(&mut HashMap::new()))
+            .err();
+        assert_eq!(e.map(|e| *e.kind()), Some(ErrorKind::InvalidInput));
+    }
+
+    #[test]
+    fn single_header_works() {
+        let headers = make_headers(&[(
+            "B3",
+            "80f198ee56343ba864fe8b2a57d3eff7-e457b5a2e4d86bd1-d-05e3ac9a4f6e3b90",
+        )]);
+        let context =
+            SpanContext::<SpanContextState>::extract_from_http_header(&B3Single::new(&headers))
+                .unwrap()
+                .unwrap();
+        let state = context.state();
+        assert_eq!(state.span_id(), SpanId::new(0xe457b5a2e4d86bd1));
+        assert_eq!(
+            state.parent_span_id(),
+            Some(SpanId::new(0x05e3ac9a4f6e3b90))
+        );
+        assert!(state.is_debug());
+
+        let mut injected = HashMap::new();
+        context
+            .inject_to_http_header(&mut B3Single::new(&mut injected))
+            .unwrap();
+        assert_eq!(
+            injected["b3"],
+            "80f198ee56343ba864fe8b2a57d3eff7-e457b5a2e4d86bd1-d-05e3ac9a4f6e3b90"
+        );
+
+        let headers = make_headers(&[("b3", "64fe8b2a57d3eff7-05e3ac9a4f6e3b90")]);
+        let context =
+            SpanContext::<SpanContextState>::extract_from_http_header(&B3Single::new(&headers))
+                .unwrap()
+                .unwrap();
+        assert!(context.state().is_sampled());
+        assert_eq!(context.state().parent_span_id(), None);
+
+        let headers = make_headers(&[("b3", "0")]);
+        let context =
+            SpanContext::<SpanContextState>::extract_from_http_header(&B3Single::new(&headers))
+                .unwrap()
+                .unwrap();
+        assert_eq!(context.state(), &SpanContextState::sampling_only(false));
+
+        for value in &[
+            "x",
+            "64fe8b2a57d3eff7-05e3ac9a4f6e3b90-2",
+            "64fe8b2a57d3eff7",
+        ] {
+            let headers = make_headers(&[("b3", value)]);
+            let e =
+                SpanContext::<SpanContextState>::extract_from_http_header(&B3Single::new(&headers))
+                    .err();
+            assert_eq!(e.map(|e| *e.kind()), Some(ErrorKind::InvalidInput));
+        }
+    }
+}
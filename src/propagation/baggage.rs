@@ -0,0 +1,311 @@
+use super::{percent_decode, percent_encode};
+use crate::span::BaggageItem;
+use crate::{Error, ErrorKind, Result};
+use std::sync::atomic::{AtomicU64, Ordering};
+use trackable::error::ErrorKindExt;
+
+static SKIPPED_MEMBERS: AtomicU64 = AtomicU64::new(0);
+
+/// Codec for the [W3C Baggage][baggage] header value.
+///
+/// `TraceContext` uses this codec to propagate baggage items by the `baggage` field.
+/// It can also be used directly to handle the header with other formats.
+///
+/// # Examples
+///
+/// ```
+/// use rustracing::propagation::W3cBaggage;
+/// use rustracing::span::BaggageItem;
+///
+/// let mut item = BaggageItem::new("user", "foo bar");
+/// item.add_property("ttl=60");
+/// let value = W3cBaggage::encode(&[item, BaggageItem::new("lang", "ja")]).unwrap();
+/// assert_eq!(value, "user=foo%20bar;ttl=60,lang=ja");
+///
+/// let items = W3cBaggage::decode(&value).unwrap();
+/// assert_eq!(items[0].value(), "foo bar");
+/// assert_eq!(items[0].properties(), ["ttl=60"]);
+/// ```
+///
+/// [baggage]: https://www.w3.org/TR/baggage/
+#[derive(Debug)]
+pub struct W3cBaggage;
+impl W3cBaggage {
+    /// The maximum number of list members.
+    pub const MAX_MEMBERS: usize = 180;
+
+    /// The maximum length of a header value in bytes.
+    pub const MAX_BYTES: usize = 8192;
+
+    /// The maximum length of a list member in bytes.
+    pub const MAX_MEMBER_BYTES: usize = 4096;
+
+    /// Encodes `items` as a header value.
+    ///
+    /// # Errors
+    ///
+    /// If an item has a name which is not a token, or a malformed property,
+    /// or the encoded value exceeds the size or count limits,
+    /// it will return an error with the kind `ErrorKind::InvalidInput`.
+    pub fn encode(items: &[BaggageItem]) -> Result<String> {
+        track_assert!(items.len() <= Self::MAX_MEMBERS, ErrorKind::InvalidInput; items.len());
+
+        let mut value = String::new();
+        for item in items {
+            track_assert!(is_token(item.name()), ErrorKind::InvalidInput; item.name());
+            let mut member = format!(
+                "{}={}",
+                item.name(),
+                percent_encode(item.value(), is_baggage_octet)
+            );
+            for property in item.properties() {
+                track!(parse_property(property))?;
+                member.push(';');
+                member.push_str(property.trim());
+            }
+            track_assert!(
+                member.len() <= Self::MAX_MEMBER_BYTES,
+                ErrorKind::InvalidInput;
+                item.name()
+            );
+
+            if !value.is_empty() {
+                value.push(',');
+            }
+            value.push_str(&member);
+        }
+        track_assert!(value.len() <= Self::MAX_BYTES, ErrorKind::InvalidInput; value.len());
+        Ok(value)
+    }
+
+    /// Decodes the header value `s`.
+    ///
+    /// # Errors
+    ///
+    /// If `s` contains a malformed list member, or exceeds the size or count limits,
+    /// it will return an error with the kind `ErrorKind::InvalidInput`.
+    pub fn decode(s: &str) -> Result<Vec<BaggageItem>> {
+        track_assert!(s.len() <= Self::MAX_BYTES, ErrorKind::InvalidInput; s.len());
+
+        let mut items = Vec::new();
+        for member in s.split(',') {
+            let member = trim_ows(member);
+            track_assert!(
+                !member.is_empty(),
+                ErrorKind::InvalidInput,
+                "Empty list member"
+            );
+            track_assert!(
+                member.len() <= Self::MAX_MEMBER_BYTES,
+                ErrorKind::InvalidInput;
+                member.len()
+            );
+            track_assert!(
+                items.len() < Self::MAX_MEMBERS,
+                ErrorKind::InvalidInput,
+                "Too many list members"
+            );
+
+            let mut parts = member.split(';');
+            let (name, value) = track!(parse_key_value(parts.next().expect("never fails")))?;
+            let value = track_assert_some!(value, ErrorKind::InvalidInput; member);
+            track_assert!(value.bytes().all(is_baggage_octet_or_percent), ErrorKind::InvalidInput; member);
+            let value = track!(percent_decode(value))?;
+
+            let mut item = BaggageItem::new(name, &value);
+            for property in parts {
+                track!(parse_property(property))?;
+                item.add_property(trim_ows(property));
+            }
+            items.push(item);
+        }
+        Ok(items)
+    }
+
+    /// Decodes the header value `s`, skipping malformed list members.
+    ///
+    /// Unlike `decode`, this does not fail as a whole: the malformed members are skipped,
+    /// and each of them is reported as an error with the kind `ErrorKind::InvalidInput` in the returned list.
+    /// If the value exceeds the size or count limits, the remaining members are skipped
+    /// and reported as a single error. Empty members (including an empty value) are ignored as the specification allows.
+    ///
+    /// This is used by extraction, where a broken baggage must not affect the propagation of the span context.
+    /// The number of the reported errors is also added to `skipped_members()`.
+    pub fn decode_lossy(s: &str) -> (Vec<BaggageItem>, Vec<Error>) {
+        let mut items = Vec::new();
+        let mut errors = Vec::new();
+        let mut bytes = 0;
+        for member in s.split(',') {
+            bytes += member.len() + 1;
+            if items.len() >= Self::MAX_MEMBERS || bytes > Self::MAX_BYTES + 1 {
+                errors.push(track!(Error::from(
+                    ErrorKind::InvalidInput.cause("Baggage exceeds the size or count limits")
+                )));
+                break;
+            }
+            if trim_ows(member).is_empty() {
+                continue;
+            }
+            match Self::decode(member) {
+                Ok(mut decoded) => items.append(&mut decoded),
+                Err(e) => errors.push(track!(e)),
+            }
+        }
+        SKIPPED_MEMBERS.fetch_add(errors.len() as u64, Ordering::Relaxed);
+        (items, errors)
+    }
+
+    /// Returns the total number of the errors reported by `decode_lossy` in this process.
+    ///
+    /// Since extraction skips malformed baggage members instead of failing,
+    /// this can be used to monitor how often incoming baggage is (partially) dropped.
+    pub fn skipped_members() -> u64 {
+        SKIPPED_MEMBERS.load(Ordering::Relaxed)
+    }
+}
+
+fn parse_property(property: &str) -> Result<()> {
+    let (_, value) = track!(parse_key_value(property))?;
+    if let Some(value) = value {
+        track_assert!(value.bytes().all(is_baggage_octet_or_percent), ErrorKind::InvalidInput; property);
+    }
+    Ok(())
+}
+
+fn parse_key_value(s: &str) -> Result<(&str, Option<&str>)> {
+    let mut kv = s.splitn(2, '=');
+    let key = trim_ows(kv.next().expect("never fails"));
+    track_assert!(is_token(key), ErrorKind::InvalidInput; s);
+    Ok((key, kv.next().map(trim_ows)))
+}
+
+fn trim_ows(s: &str) -> &str {
+    s.trim_matches(|c| c == ' ' || c == '\t')
+}
+
+/// Returns `true` if `s` is a `token` defined in [RFC 7230](https://tools.ietf.org/html/rfc7230#section-3.2.6).
+fn is_token(s: &str) -> bool {
+    !s.is_empty()
+        && s.bytes().all(|b| {
+            b.is_ascii_alphanumeric()
+                || matches!(
+                    b,
+                    b'!' | b'#'
+                        | b'$'
+                        | b'%'
+                        | b'&'
+                        | b'\''
+                        | b'*'
+                        | b'+'
+                        | b'-'
+                        | b'.'
+                        | b'^'
+                        | b'_'
+                        | b'`'
+                        | b'|'
+                        | b'~'
+                )
+        })
+}
+
+/// Returns `true` if `b` can be used in a value without being percent-encoded.
+fn is_baggage_octet(b: u8) -> bool {
+    matches!(b, 0x21 | 0x23..=0x2B | 0x2D..=0x3A | 0x3C..=0x5B | 0x5D..=0x7E) && b != b'%'
+}
+
+fn is_baggage_octet_or_percent(b: u8) -> bool {
+    is_baggage_octet(b) || b == b'%'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_works() {
+        let items =
+            W3cBaggage::decode("key1 = value1, key2=%E3%81%82;prop1 ; prop2 = x,key3=a%2Cb")
+                .unwrap();
+        assert_eq!(items.len(), 3);
+        assert_eq!((items[0].name(), items[0].value()), ("key1", "value1"));
+        assert_eq!((items[1].name(), items[1].value()), ("key2", "あ"));
+        assert_eq!(items[1].properties(), ["prop1", "prop2 = x"]);
+        assert_eq!((items[2].name(), items[2].value()), ("key3", "a,b"));
+    }
+
+    #[test]
+    fn decode_rejects_malformed_input() {
+        for s in &[
+            "",
+            "key1=value1,,key2=value2",
+            "key1",
+            "key 1=value1",
+            "key1=value 1",
+            "key1=%ZZ",
+            "key1=value1;=x",
+        ] {
+            let e = W3cBaggage::decode(s).err();
+            assert_eq!(
+                e.map(|e| *e.kind()),
+                Some(ErrorKind::InvalidInput),
+                "{:?}",
+                s
+            );
+        }
+
+        let too_many = (0..181)
+            .map(|i| format!("k{}=v", i))
+            .collect::<Vec<_>>()
+            .join(",");
+        assert!(W3cBaggage::decode(&too_many).is_err());
+
+        let too_long = format!("k={}", "v".repeat(8192));
+        assert!(W3cBaggage::decode(&too_long).is_err());
+    }
+
+    #[test]
+    fn decode_lossy_works() {
+        let skipped = W3cBaggage::skipped_members();
+        let (items, errors) =
+            W3cBaggage::decode_lossy("key1=value1,,key 2=x,key3=%ZZ, key4=value4");
+        let names = items.iter().map(|i| i.name()).collect::<Vec<_>>();
+        assert_eq!(names, ["key1", "key4"]);
+        assert_eq!(errors.len(), 2);
+        assert!(errors.iter().all(|e| *e.kind() == ErrorKind::InvalidInput));
+        assert!(W3cBaggage::skipped_members() >= skipped + 2);
+
+        let (items, errors) = W3cBaggage::decode_lossy("");
+        assert!(items.is_empty());
+        assert!(errors.is_empty());
+
+        let too_many = (0..200)
+            .map(|i| format!("k{}=v", i))
+            .collect::<Vec<_>>()
+            .join(",");
+        let (items, errors) = W3cBaggage::decode_lossy(&too_many);
+        assert_eq!(items.len(), 180);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(*errors[0].kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn encode_works() {
+        let items = vec![
+            BaggageItem::new("key1", "a b,c;d=e%"),
+            BaggageItem::new("key2", ""),
+        ];
+        let value = W3cBaggage::encode(&items).unwrap();
+        assert_eq!(value, "key1=a%20b%2Cc%3Bd=e%25,key2=");
+
+        let decoded = W3cBaggage::decode(&value).unwrap();
+        assert_eq!(decoded[0].value(), "a b,c;d=e%");
+        assert_eq!(decoded[1].value(), "");
+
+        let e = W3cBaggage::encode(&[BaggageItem::new("key 1", "v")]).err();
+        assert_eq!(e.map(|e| *e.kind()), Some(ErrorKind::InvalidInput));
+
+        let mut item = BaggageItem::new("key1", "v");
+        item.add_property("p=a b");
+        assert!(W3cBaggage::encode(&[item]).is_err());
+    }
+}
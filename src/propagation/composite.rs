@@ -0,0 +1,329 @@
+use super::b3::{B3MultiCodec, B3SingleCodec};
+use super::jaeger::JaegerCodec;
+use super::trace_context::TraceContextCodec;
+use super::Codec;
+use crate::carrier::{
+    ExtractFromHttpHeader, ExtractFromTextMap, InjectToHttpHeader, InjectToTextMap,
+    IterHttpHeaderFields, SetHttpHeaderField, TextMap,
+};
+use crate::span::SpanContext;
+use crate::state::SpanContextState;
+use crate::Result;
+
+/// Propagation format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Format {
+    /// W3C Trace Context (see `TraceContext`).
+    TraceContext,
+
+    /// B3 multiple header format (see `B3Multi`).
+    B3Multi,
+
+    /// B3 single header format (see `B3Single`).
+    B3Single,
+
+    /// Jaeger native format (see `Jaeger`).
+    Jaeger,
+}
+impl Format {
+    /// All the formats supported by `Composite`.
+    pub const ALL: &'static [Format] = &[
+        Format::TraceContext,
+        Format::B3Multi,
+        Format::B3Single,
+        Format::Jaeger,
+    ];
+
+    fn can_inject(self, state: &SpanContextState) -> bool {
+        state.is_valid() || (self == Format::Jaeger && state.is_debug_root())
+    }
+
+    fn inject_to_text_map<C: TextMap>(
+        self,
+        context: &SpanContext<SpanContextState>,
+        carrier: &mut C,
+    ) -> Result<()> {
+        match self {
+            Format::TraceContext => track!(TraceContextCodec::inject_to_text_map(context, carrier)),
+            Format::B3Multi => track!(B3MultiCodec::inject_to_text_map(context, carrier)),
+            Format::B3Single => track!(B3SingleCodec::inject_to_text_map(context, carrier)),
+            Format::Jaeger => track!(JaegerCodec::inject_to_text_map(context, carrier)),
+        }
+    }
+
+    fn extract_from_text_map<C: TextMap>(
+        self,
+        carrier: &C,
+    ) -> Result<Option<SpanContext<SpanContextState>>> {
+        match self {
+            Format::TraceContext => track!(TraceContextCodec::extract_from_text_map(carrier)),
+            Format::B3Multi => track!(B3MultiCodec::extract_from_text_map(carrier)),
+            Format::B3Single => track!(B3SingleCodec::extract_from_text_map(carrier)),
+            Format::Jaeger => track!(JaegerCodec::extract_from_text_map(carrier)),
+        }
+    }
+
+    fn inject_to_http_header<C: SetHttpHeaderField>(
+        self,
+        context: &SpanContext<SpanContextState>,
+        carrier: &mut C,
+    ) -> Result<()> {
+        match self {
+            Format::TraceContext => {
+                track!(TraceContextCodec::inject_to_http_header(context, carrier))
+            }
+            Format::B3Multi => track!(B3MultiCodec::inject_to_http_header(context, carrier)),
+            Format::B3Single => track!(B3SingleCodec::inject_to_http_header(context, carrier)),
+            Format::Jaeger => track!(JaegerCodec::inject_to_http_header(context, carrier)),
+        }
+    }
+
+    fn extract_from_http_header<'a, C: IterHttpHeaderFields<'a>>(
+        self,
+        carrier: &'a C,
+    ) -> Result<Option<SpanContext<SpanContextState>>> {
+        match self {
+            Format::TraceContext => track!(TraceContextCodec::extract_from_http_header(carrier)),
+            Format::B3Multi => track!(B3MultiCodec::extract_from_http_header(carrier)),
+            Format::B3Single => track!(B3SingleCodec::extract_from_http_header(carrier)),
+            Format::Jaeger => track!(JaegerCodec::extract_from_http_header(carrier)),
+        }
+    }
+}
+
+/// Carrier adapter which combines multiple formats.
+///
+/// When injecting, the context is written in all the configured formats.
+/// A context without valid identifiers (e.g., made by `SpanContextState::debug_root`)
+/// is only written in the formats which can represent it, and the other formats are skipped.
+/// When extracting, the configured formats are tried in order and the first context found is returned.
+/// A context without valid identifiers (e.g., the one which only carries the `b3: 0` sampling state)
+/// is returned only if no format yields a valid one.
+/// A valid context takes precedence over the errors of the other formats:
+/// e.g., if the `traceparent` header is malformed but the `uber-trace-id` header is valid,
+/// the context of the latter is returned and the error of the former is discarded.
+/// If no context is found and some of the formats failed, the first error is returned.
+///
+/// # Examples
+///
+/// ```
+/// use rustracing::propagation::{Composite, Format};
+/// use rustracing::span::SpanContext;
+/// use rustracing::state::SpanContextState;
+/// use std::collections::HashMap;
+///
+/// let context = SpanContext::new(SpanContextState::root(), Vec::new());
+///
+/// let mut headers = HashMap::new();
+/// let formats = [Format::TraceContext, Format::Jaeger];
+/// context
+///     .inject_to_http_header(&mut Composite::with_formats(&mut headers, &formats))
+///     .unwrap();
+/// assert!(headers.contains_key("traceparent"));
+/// assert!(headers.contains_key("uber-trace-id"));
+///
+/// headers.remove("traceparent");
+/// let extracted = SpanContext::<SpanContextState>::extract_from_http_header(
+///     &Composite::new(&headers),
+/// ).unwrap().unwrap();
+/// assert_eq!(extracted.state().span_id(), context.state().span_id());
+/// ```
+#[derive(Debug, Clone)]
+pub struct Composite<C> {
+    carrier: C,
+    formats: Vec<Format>,
+}
+impl<C> Composite<C> {
+    /// Makes a new adapter which wraps `carrier` and uses all the formats (i.e., `Format::ALL`).
+    pub fn new(carrier: C) -> Self {
+        Self::with_formats(carrier, Format::ALL)
+    }
+
+    /// Makes a new adapter which wraps `carrier` and uses the given `formats` in order.
+    pub fn with_formats(carrier: C, formats: &[Format]) -> Self {
+        Composite {
+            carrier,
+            formats: formats.to_vec(),
+        }
+    }
+
+    /// Returns the formats used by this adapter.
+    pub fn formats(&self) -> &[Format] {
+        &self.formats
+    }
+
+    /// Returns a reference to the wrapped carrier.
+    pub fn inner_ref(&self) -> &C {
+        &self.carrier
+    }
+
+    /// Returns a mutable reference to the wrapped carrier.
+    pub fn inner_mut(&mut self) -> &mut C {
+        &mut self.carrier
+    }
+
+    /// Takes ownership of the wrapped carrier.
+    pub fn into_inner(self) -> C {
+        self.carrier
+    }
+
+    /// Returns the first context extracted by `f`.
+    ///
+    /// Contexts without valid identifiers (e.g., `b3: 0`) are returned only if no format yields a valid one,
+    /// and the errors are reported only if no format yields a context.
+    fn extract<F>(&self, mut f: F) -> Result<Option<SpanContext<SpanContextState>>>
+    where
+        F: FnMut(Format) -> Result<Option<SpanContext<SpanContextState>>>,
+    {
+        let mut first_invalid = None;
+        let mut first_error = None;
+        for &format in &self.formats {
+            match f(format) {
+                Ok(Some(context)) if context.state().is_valid() => return Ok(Some(context)),
+                Ok(Some(context)) => {
+                    first_invalid.get_or_insert(context);
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    first_error.get_or_insert(e);
+                }
+            }
+        }
+        if first_invalid.is_some() {
+            Ok(first_invalid)
+        } else if let Some(e) = first_error {
+            Err(track!(e))
+        } else {
+            Ok(None)
+        }
+    }
+}
+forward_carrier_traits!(Composite);
+
+impl<C: TextMap> InjectToTextMap<Composite<C>> for SpanContextState {
+    fn inject_to_text_map(context: &SpanContext<Self>, carrier: &mut Composite<C>) -> Result<()> {
+        for &format in &carrier.formats {
+            if !format.can_inject(context.state()) {
+                continue;
+            }
+            track!(format.inject_to_text_map(context, &mut carrier.carrier))?;
+        }
+        Ok(())
+    }
+}
+
+impl<C: TextMap> ExtractFromTextMap<Composite<C>> for SpanContextState {
+    fn extract_from_text_map(carrier: &Composite<C>) -> Result<Option<SpanContext<Self>>> {
+        carrier.extract(|format| format.extract_from_text_map(&carrier.carrier))
+    }
+}
+
+impl<C: SetHttpHeaderField> InjectToHttpHeader<Composite<C>> for SpanContextState {
+    fn inject_to_http_header(
+        context: &SpanContext<Self>,
+        carrier: &mut Composite<C>,
+    ) -> Result<()> {
+        for &format in &carrier.formats {
+            if !format.can_inject(context.state()) {
+                continue;
+            }
+            track!(format.inject_to_http_header(context, &mut carrier.carrier))?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a, C: IterHttpHeaderFields<'a>> ExtractFromHttpHeader<'a, Composite<C>> for SpanContextState {
+    fn extract_from_http_header(carrier: &'a Composite<C>) -> Result<Option<SpanContext<Self>>> {
+        carrier.extract(|format| format.extract_from_http_header(&carrier.carrier))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::span::BaggageItem;
+    use crate::state::{SpanId, TraceId};
+    use crate::ErrorKind;
+    use std::collections::HashMap;
+
+    #[test]
+    fn inject_writes_all_formats() {
+        let state = SpanContextState::new(TraceId::new(1), SpanId::new(2));
+        let context = SpanContext::new(state, vec![BaggageItem::new("foo", "bar")]);
+
+        let mut map = HashMap::new();
+        context
+            .inject_to_text_map(&mut Composite::new(&mut map))
+            .unwrap();
+        assert!(map.contains_key("traceparent"));
+        assert!(map.contains_key("baggage"));
+        assert!(map.contains_key("X-B3-TraceId"));
+        assert!(map.contains_key("b3"));
+        assert!(map.contains_key("uber-trace-id"));
+        assert!(map.contains_key("uberctx-foo"));
+    }
+
+    #[test]
+    fn inject_skips_formats_which_cannot_represent_debug_root() {
+        let context = SpanContext::new(SpanContextState::debug_root("x"), Vec::new());
+
+        let mut map = HashMap::new();
+        context
+            .inject_to_text_map(&mut Composite::new(&mut map))
+            .unwrap();
+        assert_eq!(map.len(), 1);
+        assert_eq!(map["jaeger-debug-id"], "x");
+
+        let mut map = HashMap::new();
+        let e = context
+            .inject_to_text_map(&mut crate::propagation::TraceContext::new(&mut map))
+            .err();
+        assert_eq!(e.map(|e| *e.kind()), Some(ErrorKind::InvalidInput));
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn extract_tries_formats_in_order() {
+        let mut headers = HashMap::new();
+        headers.insert(
+            "b3".to_owned(),
+            "0000000000000001-0000000000000002".to_owned(),
+        );
+        headers.insert("uber-trace-id".to_owned(), "1:3:0:1".to_owned());
+
+        let extract = |formats: &[Format]| {
+            SpanContext::<SpanContextState>::extract_from_http_header(&Composite::with_formats(
+                &headers, formats,
+            ))
+        };
+
+        let context = extract(Format::ALL).unwrap().unwrap();
+        assert_eq!(context.state().span_id(), SpanId::new(2));
+
+        let context = extract(&[Format::Jaeger, Format::B3Single])
+            .unwrap()
+            .unwrap();
+        assert_eq!(context.state().span_id(), SpanId::new(3));
+
+        assert!(extract(&[Format::TraceContext]).unwrap().is_none());
+    }
+
+    #[test]
+    fn extract_reports_error_if_nothing_found() {
+        let mut headers = HashMap::new();
+        headers.insert("traceparent".to_owned(), "invalid".to_owned());
+        headers.insert("uber-trace-id".to_owned(), "1:3:0:1".to_owned());
+
+        let context =
+            SpanContext::<SpanContextState>::extract_from_http_header(&Composite::new(&headers))
+                .unwrap();
+        assert!(context.is_some());
+
+        headers.remove("uber-trace-id");
+        let e =
+            SpanContext::<SpanContextState>::extract_from_http_header(&Composite::new(&headers))
+                .err();
+        assert_eq!(e.map(|e| *e.kind()), Some(ErrorKind::InvalidInput));
+    }
+}
@@ -0,0 +1,247 @@
+use crate::carrier::{ExtractFromBinary, InjectToBinary};
+use crate::span::SpanContext;
+use crate::state::{SpanContextState, SpanId, TraceId};
+use crate::{ErrorKind, Result};
+use std::io::{self, Read, Write};
+
+const VERSION: u8 = 0;
+const FIELD_TRACE_ID: u8 = 0;
+const FIELD_SPAN_ID: u8 = 1;
+const FIELD_TRACE_OPTIONS: u8 = 2;
+const OPTION_SAMPLED: u8 = 0x01;
+
+/// Binary carrier adapter for the [`grpc-trace-bin`][binary-format] format (a.k.a. OpenCensus binary format).
+///
+/// The encoded bytes consist of a version byte followed by the fields,
+/// each of which is prefixed by a field id:
+///
+/// - `0`: trace id (16 bytes)
+/// - `1`: span id (8 bytes)
+/// - `2`: trace options (1 byte; the least significant bit is the sampled flag)
+///
+/// When extracting, a field with an unknown id and all the following bytes are skipped
+/// because they were appended by a newer version of the format.
+/// The trace id and the span id are mandatory,
+/// and injecting a context without valid identifiers results in an error with the kind `ErrorKind::InvalidInput`.
+///
+/// # Examples
+///
+/// ```
+/// use rustracing::propagation::GrpcTraceBin;
+/// use rustracing::span::SpanContext;
+/// use rustracing::state::SpanContextState;
+///
+/// let context = SpanContext::new(SpanContextState::root(), Vec::new());
+///
+/// let mut buf = Vec::new();
+/// context.inject_to_binary(&mut GrpcTraceBin::new(&mut buf)).unwrap();
+/// assert_eq!(buf.len(), 29);
+///
+/// let extracted = SpanContext::<SpanContextState>::extract_from_binary(
+///     &mut GrpcTraceBin::new(&buf[..]),
+/// ).unwrap().unwrap();
+/// assert_eq!(extracted.state().span_id(), context.state().span_id());
+/// ```
+///
+/// [binary-format]: https://github.com/census-instrumentation/opencensus-specs/blob/master/encodings/BinaryEncoding.md
+#[derive(Debug, Clone)]
+pub struct GrpcTraceBin<C> {
+    carrier: C,
+}
+impl<C> GrpcTraceBin<C> {
+    /// The name of the gRPC metadata entry that carries the encoded bytes.
+    pub const METADATA_KEY: &'static str = "grpc-trace-bin";
+
+    /// Makes a new adapter which wraps `carrier`.
+    pub fn new(carrier: C) -> Self {
+        GrpcTraceBin { carrier }
+    }
+
+    /// Returns a reference to the wrapped carrier.
+    pub fn inner_ref(&self) -> &C {
+        &self.carrier
+    }
+
+    /// Returns a mutable reference to the wrapped carrier.
+    pub fn inner_mut(&mut self) -> &mut C {
+        &mut self.carrier
+    }
+
+    /// Takes ownership of the wrapped carrier.
+    pub fn into_inner(self) -> C {
+        self.carrier
+    }
+}
+impl<C: Read> Read for GrpcTraceBin<C> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.carrier.read(buf)
+    }
+}
+impl<C: Write> Write for GrpcTraceBin<C> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.carrier.write(buf)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.carrier.flush()
+    }
+}
+
+impl<C: Write> InjectToBinary<GrpcTraceBin<C>> for SpanContextState {
+    fn inject_to_binary(context: &SpanContext<Self>, carrier: &mut GrpcTraceBin<C>) -> Result<()> {
+        let state = context.state();
+        track_assert!(state.is_valid(), ErrorKind::InvalidInput; state);
+        let mut buf = Vec::with_capacity(29);
+        buf.push(VERSION);
+        buf.push(FIELD_TRACE_ID);
+        buf.extend_from_slice(&state.trace_id().to_u128().to_be_bytes());
+        buf.push(FIELD_SPAN_ID);
+        buf.extend_from_slice(&state.span_id().to_u64().to_be_bytes());
+        buf.push(FIELD_TRACE_OPTIONS);
+        buf.push(if state.is_sampled() {
+            OPTION_SAMPLED
+        } else {
+            0
+        });
+        track!(carrier.write_all(&buf).map_err(crate::Error::from))?;
+        Ok(())
+    }
+}
+
+impl<C: Read> ExtractFromBinary<GrpcTraceBin<C>> for SpanContextState {
+    fn extract_from_binary(carrier: &mut GrpcTraceBin<C>) -> Result<Option<SpanContext<Self>>> {
+        let mut buf = Vec::new();
+        track!(carrier.read_to_end(&mut buf).map_err(crate::Error::from))?;
+        if buf.is_empty() {
+            return Ok(None);
+        }
+
+        // Every version shares the layout of the known fields, so only the presence is checked here.
+        let mut bytes = &buf[1..];
+        let mut trace_id = None;
+        let mut span_id = None;
+        let mut options = 0;
+        let mut last_field = None;
+        while let Some((&field, rest)) = bytes.split_first() {
+            track_assert!(
+                last_field < Some(field),
+                ErrorKind::InvalidInput,
+                "Fields must be in ascending order: {}",
+                field
+            );
+            last_field = Some(field);
+            bytes = rest;
+            match field {
+                FIELD_TRACE_ID => {
+                    let value = track!(take(&mut bytes, 16))?;
+                    let mut id = [0; 16];
+                    id.copy_from_slice(value);
+                    trace_id = Some(TraceId::new(u128::from_be_bytes(id)));
+                }
+                FIELD_SPAN_ID => {
+                    let value = track!(take(&mut bytes, 8))?;
+                    let mut id = [0; 8];
+                    id.copy_from_slice(value);
+                    span_id = Some(SpanId::new(u64::from_be_bytes(id)));
+                }
+                FIELD_TRACE_OPTIONS => {
+                    options = track!(take(&mut bytes, 1))?[0];
+                }
+                _ => {
+                    // The length of an unknown field is unknown, so the rest is skipped.
+                    break;
+                }
+            }
+        }
+
+        let trace_id = track_assert_some!(trace_id, ErrorKind::InvalidInput, "No trace id");
+        let span_id = track_assert_some!(span_id, ErrorKind::InvalidInput, "No span id");
+        track_assert!(
+            trace_id.is_valid(),
+            ErrorKind::InvalidInput,
+            "All-zero trace id"
+        );
+        track_assert!(
+            span_id.is_valid(),
+            ErrorKind::InvalidInput,
+            "All-zero span id"
+        );
+
+        let mut state = SpanContextState::new(trace_id, span_id);
+        state.set_sampled(options & OPTION_SAMPLED != 0);
+        Ok(Some(SpanContext::new(state, Vec::new())))
+    }
+}
+
+fn take<'a>(bytes: &mut &'a [u8], n: usize) -> Result<&'a [u8]> {
+    track_assert!(
+        bytes.len() >= n,
+        ErrorKind::InvalidInput,
+        "Unexpected end of data"
+    );
+    let (value, rest) = bytes.split_at(n);
+    *bytes = rest;
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ENCODED: [u8; 29] = [
+        0, 0, 0x4b, 0xf9, 0x2f, 0x35, 0x77, 0xb3, 0x4d, 0xa6, 0xa3, 0xce, 0x92, 0x9d, 0x0e, 0x0e,
+        0x47, 0x36, 1, 0x00, 0xf0, 0x67, 0xaa, 0x0b, 0xa9, 0x02, 0xb7, 2, 1,
+    ];
+
+    fn extract(bytes: &[u8]) -> Result<Option<SpanContext<SpanContextState>>> {
+        SpanContext::extract_from_binary(&mut GrpcTraceBin::new(bytes))
+    }
+
+    #[test]
+    fn extract_works() {
+        let context = extract(&ENCODED).unwrap().unwrap();
+        let state = context.state();
+        assert_eq!(
+            state.trace_id(),
+            TraceId::new(0x4bf92f3577b34da6a3ce929d0e0e4736)
+        );
+        assert_eq!(state.span_id(), SpanId::new(0x00f067aa0ba902b7));
+        assert!(state.is_sampled());
+
+        let mut buf = Vec::new();
+        context
+            .inject_to_binary(&mut GrpcTraceBin::new(&mut buf))
+            .unwrap();
+        assert_eq!(buf, &ENCODED[..]);
+
+        assert!(extract(&[]).unwrap().is_none());
+    }
+
+    #[test]
+    fn unknown_fields_are_skipped() {
+        let mut bytes = ENCODED.to_vec();
+        bytes[0] = 1;
+        bytes.extend_from_slice(&[3, 0xde, 0xad, 0xbe, 0xef]);
+        let context = extract(&bytes).unwrap().unwrap();
+        assert!(context.state().is_sampled());
+
+        // Without the options field.
+        let mut bytes = ENCODED[..27].to_vec();
+        bytes.extend_from_slice(&[5, 1, 2, 3]);
+        let context = extract(&bytes).unwrap().unwrap();
+        assert!(!context.state().is_sampled());
+    }
+
+    #[test]
+    fn extract_rejects_invalid_input() {
+        let e = extract(&ENCODED[..20]).err();
+        assert_eq!(e.map(|e| *e.kind()), Some(ErrorKind::InvalidInput));
+
+        let e = extract(&ENCODED[18..]).err();
+        assert_eq!(e.map(|e| *e.kind()), Some(ErrorKind::InvalidInput));
+
+        let mut bytes = ENCODED;
+        bytes[2..18].copy_from_slice(&[0; 16]);
+        let e = extract(&bytes).err();
+        assert_eq!(e.map(|e| *e.kind()), Some(ErrorKind::InvalidInput));
+    }
+}
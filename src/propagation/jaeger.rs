@@ -0,0 +1,377 @@
+use super::{header_value, percent_decode, percent_encode, Codec};
+use crate::carrier::{IterHttpHeaderFields, SetHttpHeaderField, TextMap};
+use crate::span::{BaggageItem, SpanContext};
+use crate::state::{SpanContextState, SpanId, TraceId};
+use crate::{ErrorKind, Result};
+use std::str;
+
+const TRACE_CONTEXT: &str = "uber-trace-id";
+const BAGGAGE_PREFIX: &str = "uberctx-";
+const DEBUG_ID: &str = "jaeger-debug-id";
+
+const FLAG_SAMPLED: u8 = 0x01;
+const FLAG_DEBUG: u8 = 0x02;
+
+carrier_adapter! {
+    /// Carrier adapter for the [Jaeger][jaeger] native format.
+    ///
+    /// The span context is propagated by the `uber-trace-id` field which has the form of
+    /// `{trace-id}:{span-id}:{parent-span-id}:{flags}`,
+    /// and each baggage item is propagated by a `uberctx-{name}` field which has the percent-encoded value.
+    ///
+    /// If the `jaeger-debug-id` field is present, the extracted context is marked as debug
+    /// (and thus sampled). If the field is present without `uber-trace-id`,
+    /// the extracted context only carries the debug identifier (see `SpanContextState::debug_root`):
+    /// a span started as its child becomes the root of a new debug trace.
+    /// Such a context is injected as the `jaeger-debug-id` field alone,
+    /// while injecting any other context without valid identifiers results in an error.
+    ///
+    /// Baggage items are extracted from text maps by traversing `TextMap::entries`,
+    /// so custom carriers must override that method to receive `uberctx-{name}` fields.
+    ///
+    /// [jaeger]: https://www.jaegertracing.io/docs/latest/client-libraries/#propagation-format
+    Jaeger(JaegerCodec)
+}
+
+impl Codec for JaegerCodec {
+    fn inject_to_text_map<C: TextMap>(
+        context: &SpanContext<SpanContextState>,
+        carrier: &mut C,
+    ) -> Result<()> {
+        inject(context, |name, value| {
+            carrier.set(name, value);
+            Ok(())
+        })
+    }
+
+    fn extract_from_text_map<C: TextMap>(
+        carrier: &C,
+    ) -> Result<Option<SpanContext<SpanContextState>>> {
+        let mut baggage_items = Vec::new();
+        for (name, value) in carrier.entries() {
+            if let Some(item) = track!(baggage_item(name, value))? {
+                baggage_items.push(item);
+            }
+        }
+        track!(extract(
+            carrier.get(TRACE_CONTEXT),
+            carrier.get(DEBUG_ID),
+            baggage_items
+        ))
+    }
+
+    fn inject_to_http_header<C: SetHttpHeaderField>(
+        context: &SpanContext<SpanContextState>,
+        carrier: &mut C,
+    ) -> Result<()> {
+        inject(context, |name, value| {
+            track!(carrier.set_http_header_field(name, value))
+        })
+    }
+
+    fn extract_from_http_header<'a, C: IterHttpHeaderFields<'a>>(
+        carrier: &'a C,
+    ) -> Result<Option<SpanContext<SpanContextState>>> {
+        let mut baggage_items = Vec::new();
+        for (name, value) in carrier.fields() {
+            if let Ok(value) = str::from_utf8(value) {
+                if let Some(item) = track!(baggage_item(name, value.trim()))? {
+                    baggage_items.push(item);
+                }
+            }
+        }
+        track!(extract(
+            track!(header_value(carrier, TRACE_CONTEXT))?,
+            track!(header_value(carrier, DEBUG_ID))?,
+            baggage_items
+        ))
+    }
+}
+
+fn inject<F>(context: &SpanContext<SpanContextState>, mut set: F) -> Result<()>
+where
+    F: FnMut(&str, &str) -> Result<()>,
+{
+    let state = context.state();
+    if let Some(debug_id) = state.debug_id().filter(|_| state.is_debug_root()) {
+        track!(set(DEBUG_ID, debug_id))?;
+    } else {
+        track_assert!(state.is_valid(), ErrorKind::InvalidInput; state);
+        track!(set(TRACE_CONTEXT, &format_trace_context(state)))?;
+    }
+
+    for item in context.baggage_items() {
+        let name = format!("{}{}", BAGGAGE_PREFIX, item.name());
+        let value = percent_encode(item.value(), |b| {
+            b.is_ascii_alphanumeric() || matches!(b, b'-' | b'.' | b'_' | b'~')
+        });
+        track!(set(&name, &value))?;
+    }
+    Ok(())
+}
+
+fn format_trace_context(state: &SpanContextState) -> String {
+    let parent_span_id = state.parent_span_id().map_or(0, SpanId::to_u64);
+    let mut flags = 0;
+    if state.is_sampled() {
+        flags |= FLAG_SAMPLED;
+    }
+    if state.is_debug() {
+        flags |= FLAG_DEBUG;
+    }
+    format!(
+        "{}:{}:{:x}:{:x}",
+        state.trace_id().to_compact_string(),
+        state.span_id(),
+        parent_span_id,
+        flags
+    )
+}
+
+fn extract(
+    trace_context: Option<&str>,
+    debug_id: Option<&str>,
+    baggage_items: Vec<BaggageItem>,
+) -> Result<Option<SpanContext<SpanContextState>>> {
+    let state = match (trace_context, debug_id) {
+        (Some(value), debug_id) => {
+            let mut state = track!(parse_trace_context(value))?;
+            if debug_id.is_some() {
+                state.set_debug(true);
+            }
+            state
+        }
+        (None, Some(debug_id)) => SpanContextState::debug_root(debug_id.trim()),
+        (None, None) => return Ok(None),
+    };
+    Ok(Some(SpanContext::new(state, baggage_items)))
+}
+
+fn parse_trace_context(value: &str) -> Result<SpanContextState> {
+    // Some clients send the value in the URL encoded form (e.g., `%3A` instead of `:`).
+    let value = track!(percent_decode(value.trim()))?;
+    let fields = value.split(':').collect::<Vec<_>>();
+    track_assert_eq!(fields.len(), 4, ErrorKind::InvalidInput; value);
+
+    let trace_id: TraceId = track!(fields[0].parse())?;
+    let span_id: SpanId = track!(fields[1].parse())?;
+    let parent_span_id: SpanId = track!(fields[2].parse())?;
+    let flags = track_assert_some!(
+        u8::from_str_radix(fields[3], 16).ok(),
+        ErrorKind::InvalidInput;
+        value
+    );
+    track_assert!(trace_id.is_valid(), ErrorKind::InvalidInput; value);
+    track_assert!(span_id.is_valid(), ErrorKind::InvalidInput; value);
+
+    let mut state = SpanContextState::new(trace_id, span_id);
+    if parent_span_id.is_valid() {
+        state.set_parent_span_id(Some(parent_span_id));
+    }
+    state.set_sampled(flags & FLAG_SAMPLED != 0);
+    if flags & FLAG_DEBUG != 0 {
+        state.set_debug(true);
+    }
+    Ok(state)
+}
+
+fn baggage_item(name: &str, value: &str) -> Result<Option<BaggageItem>> {
+    let prefix_len = BAGGAGE_PREFIX.len();
+    if name.len() > prefix_len
+        && name.is_char_boundary(prefix_len)
+        && name[..prefix_len].eq_ignore_ascii_case(BAGGAGE_PREFIX)
+    {
+        let value = track!(percent_decode(value))?;
+        Ok(Some(BaggageItem::new(&name[prefix_len..], &value)))
+    } else {
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::AllSampler;
+    use crate::Tracer;
+    use std::collections::{BTreeMap, HashMap};
+
+    #[test]
+    fn extract_works() {
+        let mut headers = HashMap::new();
+        headers.insert(
+            "Uber-Trace-Id".to_owned(),
+            "6309ab92c95468edea0dc1a9772ae2dc%3A409423a204bc17a8%3A0%3A1".to_owned(),
+        );
+        headers.insert("uberctx-user".to_owned(), "foo%20bar".to_owned());
+        headers.insert("Uberctx-Lang".to_owned(), "ja".to_owned());
+        headers.insert("x-other".to_owned(), "value".to_owned());
+        let context =
+            SpanContext::<SpanContextState>::extract_from_http_header(&Jaeger::new(&headers))
+                .unwrap()
+                .unwrap();
+        let state = context.state();
+        assert_eq!(
+            state.trace_id(),
+            TraceId::new(0x6309ab92c95468edea0dc1a9772ae2dc)
+        );
+        assert_eq!(state.span_id(), SpanId::new(0x409423a204bc17a8));
+        assert_eq!(state.parent_span_id(), None);
+        assert!(state.is_sampled());
+        assert!(!state.is_debug());
+
+        let items = context.baggage_items();
+        assert_eq!(items.len(), 2);
+        assert_eq!((items[0].name(), items[0].value()), ("Lang", "ja"));
+        assert_eq!((items[1].name(), items[1].value()), ("user", "foo bar"));
+    }
+
+    #[test]
+    fn debug_id_forces_sampling() {
+        let mut map = BTreeMap::new();
+        map.insert("uber-trace-id".to_owned(), "1:2:0:0".to_owned());
+        map.insert("jaeger-debug-id".to_owned(), "abc".to_owned());
+        let context =
+            SpanContext::<SpanContextState>::extract_from_text_map(&Jaeger::new(&mut map))
+                .unwrap()
+                .unwrap();
+        assert!(context.state().is_debug());
+        assert!(context.state().is_sampled());
+        assert_eq!(context.state().trace_id(), TraceId::new(1));
+
+        map.remove("uber-trace-id");
+        let context =
+            SpanContext::<SpanContextState>::extract_from_text_map(&Jaeger::new(&mut map))
+                .unwrap()
+                .unwrap();
+        assert!(context.state().is_debug());
+        assert!(context.state().is_debug_root());
+        assert_eq!(context.state().debug_id(), Some("abc"));
+
+        map.remove("jaeger-debug-id");
+        let context =
+            SpanContext::<SpanContextState>::extract_from_text_map(&Jaeger::new(&mut map)).unwrap();
+        assert!(context.is_none());
+    }
+
+    #[test]
+    fn debug_id_only_starts_new_trace() {
+        let mut map = BTreeMap::new();
+        map.insert("jaeger-debug-id".to_owned(), "abc".to_owned());
+        let context =
+            SpanContext::<SpanContextState>::extract_from_text_map(&Jaeger::new(&mut map))
+                .unwrap()
+                .unwrap();
+
+        let (span_tx, span_rx) = crossbeam_channel::bounded(10);
+        let tracer = Tracer::with_sender(AllSampler, span_tx);
+        {
+            let root = tracer.span("root").child_of(&context).start();
+            let _child = tracer.span("child").child_of(&root).start();
+        }
+        let child = span_rx.try_recv().unwrap();
+        let root = span_rx.try_recv().unwrap();
+
+        let state = root.context().state();
+        assert_eq!(state.parent_span_id(), None);
+        assert!(state.trace_id().is_valid());
+        assert!(state.is_debug());
+        assert!(state.is_sampled());
+        assert_eq!(state.debug_id(), Some("abc"));
+
+        let state = child.context().state();
+        assert_eq!(state.trace_id(), root.context().state().trace_id());
+        assert_eq!(
+            state.parent_span_id(),
+            Some(root.context().state().span_id())
+        );
+        assert!(state.is_debug());
+        assert_eq!(state.debug_id(), None);
+
+        let mut map = BTreeMap::new();
+        context
+            .inject_to_text_map(&mut Jaeger::new(&mut map))
+            .unwrap();
+        assert_eq!(map.get("uber-trace-id"), None);
+        assert_eq!(map["jaeger-debug-id"], "abc");
+    }
+
+    #[test]
+    fn custom_text_map_carries_baggage() {
+        #[derive(Default)]
+        struct Fields(Vec<(String, String)>);
+        impl TextMap for Fields {
+            fn set(&mut self, key: &str, value: &str) {
+                self.0.push((key.to_owned(), value.to_owned()));
+            }
+            fn get(&self, key: &str) -> Option<&str> {
+                self.0
+                    .iter()
+                    .find(|(k, _)| k == key)
+                    .map(|(_, v)| v.as_str())
+            }
+            fn entries(&self) -> Box<dyn Iterator<Item = (&str, &str)> + '_> {
+                Box::new(self.0.iter().map(|(k, v)| (k.as_str(), v.as_str())))
+            }
+        }
+
+        let state = SpanContextState::new(TraceId::new(1), SpanId::new(2));
+        let context = SpanContext::new(state, vec![BaggageItem::new("user", "foo")]);
+        let mut fields = Fields::default();
+        context
+            .inject_to_text_map(&mut Jaeger::new(&mut fields))
+            .unwrap();
+        assert_eq!(fields.get("uberctx-user"), Some("foo"));
+
+        let extracted =
+            SpanContext::<SpanContextState>::extract_from_text_map(&Jaeger::new(&mut fields))
+                .unwrap()
+                .unwrap();
+        assert_eq!(extracted.state(), context.state());
+        let items = extracted.baggage_items();
+        assert_eq!(items.len(), 1);
+        assert_eq!((items[0].name(), items[0].value()), ("user", "foo"));
+    }
+
+    #[test]
+    fn inject_works() {
+        let mut state = SpanContextState::new(TraceId::new(0xabc), SpanId::new(0x10));
+        state.set_parent_span_id(Some(SpanId::new(0x20)));
+        state.set_debug(true);
+        let context = SpanContext::new(state, vec![BaggageItem::new("user", "foo bar/baz")]);
+
+        let mut map = BTreeMap::new();
+        context
+            .inject_to_text_map(&mut Jaeger::new(&mut map))
+            .unwrap();
+        assert_eq!(
+            map["uber-trace-id"],
+            "0000000000000abc:0000000000000010:20:3"
+        );
+        assert_eq!(map["uberctx-user"], "foo%20bar%2Fbaz");
+
+        let extracted =
+            SpanContext::<SpanContextState>::extract_from_text_map(&Jaeger::new(&mut map))
+                .unwrap()
+                .unwrap();
+        assert_eq!(extracted.state(), context.state());
+        assert_eq!(extracted.baggage_items()[0].value(), "foo bar/baz");
+    }
+
+    #[test]
+    fn extract_rejects_invalid_input() {
+        for value in &["1:2:0", "0:2:0:1", "1:0:0:1", "1:2:0:x", "1:2:0:1%"] {
+            let mut map = BTreeMap::new();
+            map.insert("uber-trace-id".to_owned(), value.to_string());
+            let e = SpanContext::<SpanContextState>::extract_from_text_map(&Jaeger::new(&mut map))
+                .err();
+            assert_eq!(e.map(|e| *e.kind()), Some(ErrorKind::InvalidInput));
+        }
+
+        let mut map = BTreeMap::new();
+        map.insert("uber-trace-id".to_owned(), "1:2:0:1".to_owned());
+        map.insert("uberctx-foo".to_owned(), "%ZZ".to_owned());
+        let e =
+            SpanContext::<SpanContextState>::extract_from_text_map(&Jaeger::new(&mut map)).err();
+        assert_eq!(e.map(|e| *e.kind()), Some(ErrorKind::InvalidInput));
+    }
+}
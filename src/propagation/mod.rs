@@ -0,0 +1,259 @@
+//! Built-in propagation formats for `SpanContextState`.
+//!
+//! Each format is provided as a carrier adapter that wraps an underlying carrier
+//! (e.g., `HashMap<String, String>`).
+//! `SpanContextState` implements the traits defined in the `carrier` module for those adapters,
+//! so the format is selected by wrapping the carrier passed to
+//! `SpanContext::inject_to_*` and `SpanContext::extract_from_*` methods.
+//!
+//! # Examples
+//!
+//! ```
+//! use rustracing::propagation::TraceContext;
+//! use rustracing::span::SpanContext;
+//! use rustracing::state::SpanContextState;
+//! use std::collections::HashMap;
+//!
+//! let context = SpanContext::new(SpanContextState::root(), Vec::new());
+//!
+//! let mut headers = HashMap::new();
+//! context.inject_to_http_header(&mut TraceContext::new(&mut headers)).unwrap();
+//! assert!(headers.contains_key("traceparent"));
+//!
+//! let extracted = SpanContext::<SpanContextState>::extract_from_http_header(
+//!     &TraceContext::new(&headers),
+//! ).unwrap().unwrap();
+//! assert_eq!(extracted.state().trace_id(), context.state().trace_id());
+//! ```
+pub use self::b3::{B3Multi, B3Single};
+pub use self::baggage::W3cBaggage;
+pub use self::composite::{Composite, Format};
+pub use self::grpc::GrpcTraceBin;
+pub use self::jaeger::Jaeger;
+pub use self::trace_context::TraceContext;
+
+use crate::carrier::{IterHttpHeaderFields, SetHttpHeaderField, TextMap};
+use crate::span::SpanContext;
+use crate::state::SpanContextState;
+use crate::{Error, ErrorKind, Result};
+use std::str;
+use trackable::error::ErrorKindExt;
+
+/// Implementation of a format over arbitrary carriers.
+trait Codec {
+    fn inject_to_text_map<C: TextMap>(
+        context: &SpanContext<SpanContextState>,
+        carrier: &mut C,
+    ) -> Result<()>;
+
+    fn extract_from_text_map<C: TextMap>(
+        carrier: &C,
+    ) -> Result<Option<SpanContext<SpanContextState>>>;
+
+    fn inject_to_http_header<C: SetHttpHeaderField>(
+        context: &SpanContext<SpanContextState>,
+        carrier: &mut C,
+    ) -> Result<()>;
+
+    fn extract_from_http_header<'a, C: IterHttpHeaderFields<'a>>(
+        carrier: &'a C,
+    ) -> Result<Option<SpanContext<SpanContextState>>>;
+}
+
+/// Implements the carrier traits for an adapter type by delegating to its `carrier` field.
+macro_rules! forward_carrier_traits {
+    ($name:ident) => {
+        impl<C: $crate::carrier::TextMap> $crate::carrier::TextMap for $name<C> {
+            fn set(&mut self, key: &str, value: &str) {
+                self.carrier.set(key, value)
+            }
+            fn get(&self, key: &str) -> Option<&str> {
+                self.carrier.get(key)
+            }
+            fn entries(&self) -> Box<dyn Iterator<Item = (&str, &str)> + '_> {
+                self.carrier.entries()
+            }
+        }
+        impl<C> $crate::carrier::SetHttpHeaderField for $name<C>
+        where
+            C: $crate::carrier::SetHttpHeaderField,
+        {
+            fn set_http_header_field(&mut self, name: &str, value: &str) -> $crate::Result<()> {
+                self.carrier.set_http_header_field(name, value)
+            }
+        }
+        impl<'a, C> $crate::carrier::IterHttpHeaderFields<'a> for $name<C>
+        where
+            C: $crate::carrier::IterHttpHeaderFields<'a>,
+        {
+            type Fields = C::Fields;
+
+            fn fields(&'a self) -> Self::Fields {
+                self.carrier.fields()
+            }
+        }
+    };
+}
+
+/// Defines a carrier adapter type which delegates to the given codec,
+/// and implements the carrier traits for it.
+macro_rules! carrier_adapter {
+    ($(#[$attr:meta])* $name:ident($codec:ident)) => {
+        $(#[$attr])*
+        #[derive(Debug, Clone)]
+        pub struct $name<C> {
+            carrier: C,
+        }
+        impl<C> $name<C> {
+            /// Makes a new adapter which wraps `carrier`.
+            pub fn new(carrier: C) -> Self {
+                $name { carrier }
+            }
+
+            /// Returns a reference to the wrapped carrier.
+            pub fn inner_ref(&self) -> &C {
+                &self.carrier
+            }
+
+            /// Returns a mutable reference to the wrapped carrier.
+            pub fn inner_mut(&mut self) -> &mut C {
+                &mut self.carrier
+            }
+
+            /// Takes ownership of the wrapped carrier.
+            pub fn into_inner(self) -> C {
+                self.carrier
+            }
+        }
+        forward_carrier_traits!($name);
+
+        #[derive(Debug)]
+        pub(super) struct $codec;
+
+        impl<C> $crate::carrier::InjectToTextMap<$name<C>> for $crate::state::SpanContextState
+        where
+            C: $crate::carrier::TextMap,
+        {
+            fn inject_to_text_map(
+                context: &$crate::span::SpanContext<Self>,
+                carrier: &mut $name<C>,
+            ) -> $crate::Result<()> {
+                track!(<$codec as $crate::propagation::Codec>::inject_to_text_map(
+                    context,
+                    &mut carrier.carrier
+                ))
+            }
+        }
+        impl<C> $crate::carrier::ExtractFromTextMap<$name<C>> for $crate::state::SpanContextState
+        where
+            C: $crate::carrier::TextMap,
+        {
+            fn extract_from_text_map(
+                carrier: &$name<C>,
+            ) -> $crate::Result<Option<$crate::span::SpanContext<Self>>> {
+                track!(<$codec as $crate::propagation::Codec>::extract_from_text_map(
+                    &carrier.carrier
+                ))
+            }
+        }
+        impl<C> $crate::carrier::InjectToHttpHeader<$name<C>> for $crate::state::SpanContextState
+        where
+            C: $crate::carrier::SetHttpHeaderField,
+        {
+            fn inject_to_http_header(
+                context: &$crate::span::SpanContext<Self>,
+                carrier: &mut $name<C>,
+            ) -> $crate::Result<()> {
+                track!(<$codec as $crate::propagation::Codec>::inject_to_http_header(
+                    context,
+                    &mut carrier.carrier
+                ))
+            }
+        }
+        impl<'a, C> $crate::carrier::ExtractFromHttpHeader<'a, $name<C>>
+            for $crate::state::SpanContextState
+        where
+            C: $crate::carrier::IterHttpHeaderFields<'a>,
+        {
+            fn extract_from_http_header(
+                carrier: &'a $name<C>,
+            ) -> $crate::Result<Option<$crate::span::SpanContext<Self>>> {
+                track!(<$codec as $crate::propagation::Codec>::extract_from_http_header(
+                    &carrier.carrier
+                ))
+            }
+        }
+    };
+}
+
+mod b3;
+mod baggage;
+mod composite;
+mod grpc;
+mod jaeger;
+mod trace_context;
+
+/// Returns the values of the HTTP header fields named `name` (case-insensitive).
+fn header_values<'a, C>(carrier: &'a C, name: &'a str) -> impl Iterator<Item = &'a [u8]> + 'a
+where
+    C: IterHttpHeaderFields<'a>,
+{
+    carrier
+        .fields()
+        .filter(move |(n, _)| n.eq_ignore_ascii_case(name))
+        .map(|(_, v)| v)
+}
+
+/// Returns the first value of the HTTP header fields named `name` as a string.
+fn header_value<'a, C>(carrier: &'a C, name: &str) -> Result<Option<&'a str>>
+where
+    C: IterHttpHeaderFields<'a>,
+{
+    let field = carrier.fields().find(|(n, _)| n.eq_ignore_ascii_case(name));
+    if let Some((_, value)) = field {
+        let value = track!(
+            str::from_utf8(value).map_err(|e| Error::from(ErrorKind::InvalidInput.cause(e)))
+        )?;
+        Ok(Some(value.trim()))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Percent-encodes the bytes of `s` except for those that satisfy `is_unreserved`.
+fn percent_encode<F>(s: &str, is_unreserved: F) -> String
+where
+    F: Fn(u8) -> bool,
+{
+    let mut encoded = String::with_capacity(s.len());
+    for b in s.bytes() {
+        if is_unreserved(b) {
+            encoded.push(char::from(b));
+        } else {
+            encoded.push_str(&format!("%{:02X}", b));
+        }
+    }
+    encoded
+}
+
+/// Decodes the percent-encoded string `s`.
+///
+/// If `s` contains a malformed escape sequence or the decoded bytes are not UTF-8,
+/// it will return an error with the kind `ErrorKind::InvalidInput`.
+fn percent_decode(s: &str) -> Result<String> {
+    let mut decoded = Vec::with_capacity(s.len());
+    let mut bytes = s.bytes();
+    while let Some(b) = bytes.next() {
+        if b == b'%' {
+            let hex_value = |b: u8| char::from(b).to_digit(16);
+            let high =
+                track_assert_some!(bytes.next().and_then(hex_value), ErrorKind::InvalidInput; s);
+            let low =
+                track_assert_some!(bytes.next().and_then(hex_value), ErrorKind::InvalidInput; s);
+            decoded.push((high * 16 + low) as u8);
+        } else {
+            decoded.push(b);
+        }
+    }
+    track!(String::from_utf8(decoded).map_err(|e| Error::from(ErrorKind::InvalidInput.cause(e))))
+}
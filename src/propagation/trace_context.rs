@@ -0,0 +1,316 @@
+use super::{header_value, header_values, Codec, W3cBaggage};
+use crate::carrier::{IterHttpHeaderFields, SetHttpHeaderField, TextMap};
+use crate::span::SpanContext;
+use crate::state::{SpanContextState, TraceState};
+use crate::{ErrorKind, Result};
+use std::str;
+
+const TRACEPARENT: &str = "traceparent";
+const TRACESTATE: &str = "tracestate";
+const BAGGAGE: &str = "baggage";
+
+carrier_adapter! {
+    /// Carrier adapter for the [W3C Trace Context][trace-context] format.
+    ///
+    /// The span context is propagated by the `traceparent` and `tracestate` fields,
+    /// and the baggage items are propagated by the [`baggage`][baggage] field (see `W3cBaggage`).
+    ///
+    /// When extracting, a malformed `traceparent` results in an error with the kind `ErrorKind::InvalidInput`,
+    /// while a malformed `tracestate` is discarded and malformed `baggage` members are skipped
+    /// so that they do not affect the propagation of the span context, as the specifications require.
+    /// When injecting, a context without valid identifiers (e.g., made by `SpanContextState::debug_root`)
+    /// results in an error with the kind `ErrorKind::InvalidInput`.
+    /// The skipped members are counted by `W3cBaggage::skipped_members()`.
+    ///
+    /// [trace-context]: https://www.w3.org/TR/trace-context/
+    /// [baggage]: https://www.w3.org/TR/baggage/
+    TraceContext(TraceContextCodec)
+}
+
+impl Codec for TraceContextCodec {
+    fn inject_to_text_map<C: TextMap>(
+        context: &SpanContext<SpanContextState>,
+        carrier: &mut C,
+    ) -> Result<()> {
+        let state = context.state();
+        carrier.set(TRACEPARENT, &track!(format_traceparent(state))?);
+        if !state.trace_state().is_empty() {
+            carrier.set(TRACESTATE, &state.trace_state().to_string());
+        }
+        if !context.baggage_items().is_empty() {
+            carrier.set(
+                BAGGAGE,
+                &track!(W3cBaggage::encode(context.baggage_items()))?,
+            );
+        }
+        Ok(())
+    }
+
+    fn extract_from_text_map<C: TextMap>(
+        carrier: &C,
+    ) -> Result<Option<SpanContext<SpanContextState>>> {
+        let traceparent = if let Some(value) = carrier.get(TRACEPARENT) {
+            value
+        } else {
+            return Ok(None);
+        };
+        let mut state = track!(parse_traceparent(traceparent.trim()))?;
+        if let Some(tracestate) = carrier.get(TRACESTATE) {
+            state.set_trace_state(tracestate.parse().unwrap_or_default());
+        }
+        let baggage_items = carrier
+            .get(BAGGAGE)
+            .map(|baggage| W3cBaggage::decode_lossy(baggage).0)
+            .unwrap_or_default();
+        Ok(Some(SpanContext::new(state, baggage_items)))
+    }
+
+    fn inject_to_http_header<C: SetHttpHeaderField>(
+        context: &SpanContext<SpanContextState>,
+        carrier: &mut C,
+    ) -> Result<()> {
+        let state = context.state();
+        let traceparent = track!(format_traceparent(state))?;
+        track!(carrier.set_http_header_field(TRACEPARENT, &traceparent))?;
+        if !state.trace_state().is_empty() {
+            track!(carrier.set_http_header_field(TRACESTATE, &state.trace_state().to_string()))?;
+        }
+        if !context.baggage_items().is_empty() {
+            let baggage = track!(W3cBaggage::encode(context.baggage_items()))?;
+            track!(carrier.set_http_header_field(BAGGAGE, &baggage))?;
+        }
+        Ok(())
+    }
+
+    fn extract_from_http_header<'a, C: IterHttpHeaderFields<'a>>(
+        carrier: &'a C,
+    ) -> Result<Option<SpanContext<SpanContextState>>> {
+        let traceparent = if let Some(value) = track!(header_value(carrier, TRACEPARENT))? {
+            value
+        } else {
+            return Ok(None);
+        };
+        let mut state = track!(parse_traceparent(traceparent))?;
+
+        // Multiple `tracestate` fields are combined as a single comma separated list.
+        let tracestate = header_values(carrier, TRACESTATE)
+            .map(str::from_utf8)
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .ok()
+            .and_then(|values| values.join(",").parse::<TraceState>().ok());
+        if let Some(tracestate) = tracestate {
+            state.set_trace_state(tracestate);
+        }
+
+        let mut baggage_items = Vec::new();
+        for baggage in header_values(carrier, BAGGAGE) {
+            if let Ok(baggage) = str::from_utf8(baggage) {
+                baggage_items.extend(W3cBaggage::decode_lossy(baggage).0);
+            }
+        }
+        baggage_items.truncate(W3cBaggage::MAX_MEMBERS);
+        Ok(Some(SpanContext::new(state, baggage_items)))
+    }
+}
+
+fn format_traceparent(state: &SpanContextState) -> Result<String> {
+    track_assert!(state.is_valid(), ErrorKind::InvalidInput; state);
+    let flags = if state.is_sampled() { 1 } else { 0 };
+    Ok(format!(
+        "00-{}-{}-{:02x}",
+        state.trace_id(),
+        state.span_id(),
+        flags
+    ))
+}
+
+fn parse_traceparent(s: &str) -> Result<SpanContextState> {
+    track_assert!(s.is_ascii(), ErrorKind::InvalidInput; s);
+    track_assert!(s.len() >= 55, ErrorKind::InvalidInput; s);
+
+    let version = &s[0..2];
+    track_assert!(is_lower_hex(version), ErrorKind::InvalidInput; s);
+    track_assert_ne!(version, "ff", ErrorKind::InvalidInput; s);
+    if version == "00" {
+        track_assert_eq!(s.len(), 55, ErrorKind::InvalidInput; s);
+    } else {
+        // Future versions may append fields, but those are unknown to us.
+        track_assert!(s.len() == 55 || s.as_bytes()[55] == b'-', ErrorKind::InvalidInput; s);
+    }
+
+    let bytes = s.as_bytes();
+    track_assert!(
+        bytes[2] == b'-' && bytes[35] == b'-' && bytes[52] == b'-',
+        ErrorKind::InvalidInput;
+        s
+    );
+    let trace_id = &s[3..35];
+    let span_id = &s[36..52];
+    let flags = &s[53..55];
+    track_assert!(
+        is_lower_hex(trace_id) && is_lower_hex(span_id) && is_lower_hex(flags),
+        ErrorKind::InvalidInput;
+        s
+    );
+
+    let trace_id = track!(trace_id.parse())?;
+    let span_id = track!(span_id.parse())?;
+    let flags = u8::from_str_radix(flags, 16).expect("never fails");
+    let mut state = SpanContextState::new(trace_id, span_id);
+    track_assert!(
+        state.trace_id().is_valid(),
+        ErrorKind::InvalidInput,
+        "All-zero trace id"
+    );
+    track_assert!(
+        state.span_id().is_valid(),
+        ErrorKind::InvalidInput,
+        "All-zero parent id"
+    );
+    state.set_sampled(flags & 0x01 != 0);
+    Ok(state)
+}
+
+fn is_lower_hex(s: &str) -> bool {
+    s.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::span::BaggageItem;
+    use crate::state::{SpanId, TraceId};
+    use std::collections::{BTreeMap, HashMap};
+
+    fn extract(headers: &[(&str, &str)]) -> Result<Option<SpanContext<SpanContextState>>> {
+        let headers = headers
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect::<HashMap<_, _>>();
+        SpanContext::extract_from_http_header(&TraceContext::new(&headers))
+    }
+
+    #[test]
+    fn extract_works() {
+        let context = extract(&[
+            (
+                "Traceparent",
+                "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01",
+            ),
+            ("tracestate", "rojo=00f067aa0ba902b7,congo=t61rcWkgMzE"),
+        ])
+        .unwrap()
+        .unwrap();
+        let state = context.state();
+        assert_eq!(
+            state.trace_id(),
+            TraceId::new(0x0af7651916cd43dd8448eb211c80319c)
+        );
+        assert_eq!(state.span_id(), SpanId::new(0xb7ad6b7169203331));
+        assert!(state.is_sampled());
+        assert_eq!(state.trace_state().get("rojo"), Some("00f067aa0ba902b7"));
+        assert_eq!(state.trace_state().get("congo"), Some("t61rcWkgMzE"));
+
+        let context = extract(&[
+            (
+                "traceparent",
+                "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-00",
+            ),
+            ("tracestate", "INVALID"),
+        ])
+        .unwrap()
+        .unwrap();
+        assert!(!context.state().is_sampled());
+        assert!(context.state().trace_state().is_empty());
+
+        // Future versions
+        let context = extract(&[(
+            "traceparent",
+            "cc-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01-what-the-future-will-be-like",
+        )])
+        .unwrap();
+        assert!(context.is_some());
+
+        assert!(extract(&[]).unwrap().is_none());
+    }
+
+    #[test]
+    fn extract_rejects_invalid_traceparent() {
+        for traceparent in &[
+            "ff-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01",
+            "00-00000000000000000000000000000000-b7ad6b7169203331-01",
+            "00-0af7651916cd43dd8448eb211c80319c-0000000000000000-01",
+            "00-0AF7651916CD43DD8448EB211C80319C-b7ad6b7169203331-01",
+            "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01-",
+            "00-0af7651916cd43dd8448eb211c80319c-b7ad6b716920333-01",
+            "cc-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01.",
+        ] {
+            let e = extract(&[("traceparent", traceparent)]).err();
+            assert_eq!(e.map(|e| *e.kind()), Some(ErrorKind::InvalidInput));
+        }
+    }
+
+    #[test]
+    fn inject_works() {
+        let mut state = SpanContextState::new(
+            TraceId::new(0x0af7651916cd43dd8448eb211c80319c),
+            SpanId::new(0xb7ad6b7169203331),
+        );
+        state.set_trace_state("congo=t61rcWkgMzE".parse().unwrap());
+        let context = SpanContext::new(state, vec![BaggageItem::new("user", "foo bar")]);
+
+        let mut map = BTreeMap::new();
+        context
+            .inject_to_text_map(&mut TraceContext::new(&mut map))
+            .unwrap();
+        assert_eq!(
+            map.get("traceparent").map(|s| s.as_str()),
+            Some("00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01")
+        );
+        assert_eq!(
+            map.get("tracestate").map(|s| s.as_str()),
+            Some("congo=t61rcWkgMzE")
+        );
+        assert_eq!(
+            map.get("baggage").map(|s| s.as_str()),
+            Some("user=foo%20bar")
+        );
+
+        let extracted =
+            SpanContext::<SpanContextState>::extract_from_text_map(&TraceContext::new(&mut map))
+                .unwrap()
+                .unwrap();
+        assert_eq!(extracted.state(), context.state());
+        assert_eq!(extracted.baggage_items()[0].value(), "foo bar");
+    }
+
+    #[test]
+    fn invalid_baggage_does_not_break_extraction() {
+        let skipped = W3cBaggage::skipped_members();
+        let traceparent = "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01";
+        let context = extract(&[("traceparent", traceparent), ("baggage", "foo=bar,,baz")])
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            context.state().trace_id(),
+            TraceId::new(0x0af7651916cd43dd8448eb211c80319c)
+        );
+        assert_eq!(context.baggage_items().len(), 1);
+        assert_eq!(context.baggage_items()[0].name(), "foo");
+
+        let context = extract(&[("traceparent", traceparent), ("baggage", "%%%")])
+            .unwrap()
+            .unwrap();
+        assert!(context.baggage_items().is_empty());
+        assert!(W3cBaggage::skipped_members() >= skipped + 2);
+
+        let mut map = BTreeMap::new();
+        map.insert("traceparent".to_owned(), traceparent.to_owned());
+        map.insert("baggage".to_owned(), String::new());
+        let context =
+            SpanContext::<SpanContextState>::extract_from_text_map(&TraceContext::new(&mut map))
+                .unwrap()
+                .unwrap();
+        assert!(context.baggage_items().is_empty());
+    }
+}
@@ -0,0 +1,432 @@
+//! Background reporter of finished spans.
+//!
+//! # Examples
+//!
+//! ```
+//! use rustracing::exporter::Exporter;
+//! use rustracing::reporter::ReporterBuilder;
+//! use rustracing::sampler::AllSampler;
+//! use rustracing::span::FinishedSpan;
+//! use rustracing::Tracer;
+//! use std::time::Duration;
+//!
+//! struct PrintExporter;
+//! impl<T: std::fmt::Debug> Exporter<T> for PrintExporter {
+//!     fn export(&mut self, spans: Vec<FinishedSpan<T>>) -> rustracing::Result<()> {
+//!         for span in spans {
+//!             println!("# SPAN: {:?}", span);
+//!         }
+//!         Ok(())
+//!     }
+//! }
+//!
+//! let (span_tx, span_rx) = crossbeam_channel::bounded(10);
+//! let tracer = Tracer::with_sender(AllSampler, span_tx);
+//! let reporter = ReporterBuilder::new(span_rx, PrintExporter)
+//!     .max_batch_size(100)
+//!     .max_batch_age(Duration::from_secs(1))
+//!     .spawn();
+//! {
+//!     let _span = tracer.span("foo").start_with_state(());
+//! }
+//! reporter.shutdown(Duration::from_secs(5)).unwrap();
+//! ```
+use crate::exporter::Exporter;
+use crate::span::{FinishedSpan, SpanReceiver};
+use crate::{Error, ErrorKind, Result};
+use crossbeam_channel::{self as channel, Receiver, RecvTimeoutError, Sender};
+use std::fmt;
+use std::mem;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use trackable::error::ErrorKindExt;
+
+/// The default value of the maximum number of spans in a batch.
+pub const DEFAULT_MAX_BATCH_SIZE: usize = 512;
+
+/// The default value of the maximum age of a batch.
+pub const DEFAULT_MAX_BATCH_AGE: Duration = Duration::from_secs(5);
+
+type ErrorHandler = Box<dyn FnMut(Error) + Send + 'static>;
+
+/// `Reporter` builder.
+pub struct ReporterBuilder<T, E> {
+    span_rx: SpanReceiver<T>,
+    exporter: E,
+    max_batch_size: usize,
+    max_batch_age: Duration,
+    error_handler: ErrorHandler,
+}
+impl<T, E> ReporterBuilder<T, E>
+where
+    T: Send + 'static,
+    E: Exporter<T> + Send + 'static,
+{
+    /// Makes a new `ReporterBuilder` instance.
+    pub fn new(span_rx: SpanReceiver<T>, exporter: E) -> Self {
+        ReporterBuilder {
+            span_rx,
+            exporter,
+            max_batch_size: DEFAULT_MAX_BATCH_SIZE,
+            max_batch_age: DEFAULT_MAX_BATCH_AGE,
+            error_handler: Box::new(|_| {}),
+        }
+    }
+
+    /// Sets the maximum number of spans in a batch.
+    ///
+    /// A batch is exported as soon as it reaches this size.
+    ///
+    /// The default value is `DEFAULT_MAX_BATCH_SIZE`.
+    /// A value of `0` is treated as `1`.
+    pub fn max_batch_size(mut self, size: usize) -> Self {
+        self.max_batch_size = size.max(1);
+        self
+    }
+
+    /// Sets the maximum age of a batch.
+    ///
+    /// A batch is exported when this duration has elapsed since its first span was received,
+    /// even if it has not reached the maximum size.
+    ///
+    /// The default value is `DEFAULT_MAX_BATCH_AGE`.
+    pub fn max_batch_age(mut self, age: Duration) -> Self {
+        self.max_batch_age = age;
+        self
+    }
+
+    /// Sets the function to be called when the background export of a batch fails.
+    ///
+    /// Errors which occur during `Reporter::flush` or `Reporter::shutdown` are
+    /// returned to the callers instead.
+    ///
+    /// By default, such errors are discarded.
+    pub fn error_handler<F>(mut self, f: F) -> Self
+    where
+        F: FnMut(Error) + Send + 'static,
+    {
+        self.error_handler = Box::new(f);
+        self
+    }
+
+    /// Spawns a background thread that reports the spans received from `span_rx`,
+    /// and returns the handle of it.
+    pub fn spawn(self) -> Reporter {
+        let (command_tx, command_rx) = channel::unbounded();
+        let worker = Worker {
+            span_rx: self.span_rx,
+            command_rx,
+            exporter: self.exporter,
+            batch: Vec::new(),
+            batch_deadline: None,
+            max_batch_size: self.max_batch_size,
+            max_batch_age: self.max_batch_age,
+            error_handler: self.error_handler,
+        };
+        let handle = thread::spawn(move || worker.run());
+        Reporter {
+            command_tx,
+            handle: Some(handle),
+        }
+    }
+}
+impl<T, E: fmt::Debug> fmt::Debug for ReporterBuilder<T, E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReporterBuilder")
+            .field("exporter", &self.exporter)
+            .field("max_batch_size", &self.max_batch_size)
+            .field("max_batch_age", &self.max_batch_age)
+            .finish()
+    }
+}
+
+/// Reporter which exports the finished spans on a background thread.
+///
+/// The reporter receives spans from a `SpanReceiver`, groups them into batches,
+/// and hands each batch to an `Exporter`.
+///
+/// The background thread stops when all the corresponding `SpanSender`s are dropped,
+/// or when `shutdown` is called.
+/// Dropping a `Reporter` also requests the thread to stop but does not wait for it,
+/// so `shutdown` should be called before the process exits to avoid losing spans.
+#[derive(Debug)]
+pub struct Reporter {
+    command_tx: Sender<Command>,
+    handle: Option<JoinHandle<()>>,
+}
+impl Reporter {
+    /// Exports all the spans received so far and flushes the exporter.
+    ///
+    /// This method blocks until the flush completes.
+    pub fn flush(&self) -> Result<()> {
+        let (reply_tx, reply_rx) = channel::bounded(1);
+        track!(self
+            .command_tx
+            .send(Command::Flush(reply_tx))
+            .map_err(|_| Error::from(ErrorKind::Other.cause("Reporter thread has stopped"))))?;
+        let result = track!(reply_rx
+            .recv()
+            .map_err(|_| Error::from(ErrorKind::Other.cause("Reporter thread has stopped"))))?;
+        track!(result)
+    }
+
+    /// Exports all the pending spans, shuts down the exporter and stops the background thread.
+    ///
+    /// If the shutdown does not complete within `timeout`,
+    /// this method returns an error with the kind `ErrorKind::Other`
+    /// (the thread continues shutting down in the background).
+    pub fn shutdown(mut self, timeout: Duration) -> Result<()> {
+        let (reply_tx, reply_rx) = channel::bounded(1);
+        let handle = self.handle.take().expect("never fails");
+        if self.command_tx.send(Command::Shutdown(reply_tx)).is_err() {
+            // The thread has already stopped.
+            track_assert!(
+                handle.join().is_ok(),
+                ErrorKind::Other,
+                "Reporter thread panicked"
+            );
+            return Ok(());
+        }
+        match reply_rx.recv_timeout(timeout) {
+            Ok(result) => {
+                track_assert!(
+                    handle.join().is_ok(),
+                    ErrorKind::Other,
+                    "Reporter thread panicked"
+                );
+                track!(result)
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                track_panic!(ErrorKind::Other, "Shutdown timed out: {:?}", timeout)
+            }
+            Err(RecvTimeoutError::Disconnected) => {
+                track_assert!(
+                    handle.join().is_ok(),
+                    ErrorKind::Other,
+                    "Reporter thread panicked"
+                );
+                Ok(())
+            }
+        }
+    }
+}
+impl Drop for Reporter {
+    fn drop(&mut self) {
+        if self.handle.is_some() {
+            let (reply_tx, _) = channel::bounded(1);
+            let _ = self.command_tx.send(Command::Shutdown(reply_tx));
+        }
+    }
+}
+
+enum Command {
+    Flush(Sender<Result<()>>),
+    Shutdown(Sender<Result<()>>),
+}
+
+struct Worker<T, E> {
+    span_rx: SpanReceiver<T>,
+    command_rx: Receiver<Command>,
+    exporter: E,
+    batch: Vec<FinishedSpan<T>>,
+    batch_deadline: Option<Instant>,
+    max_batch_size: usize,
+    max_batch_age: Duration,
+    error_handler: ErrorHandler,
+}
+impl<T, E: Exporter<T>> Worker<T, E> {
+    fn run(mut self) {
+        loop {
+            let timer = self.batch_deadline.map_or_else(channel::never, channel::at);
+            channel::select! {
+                recv(self.span_rx) -> span => match span {
+                    Ok(span) => self.push(span),
+                    Err(_) => {
+                        let _ = self.shutdown();
+                        return;
+                    }
+                },
+                recv(self.command_rx) -> command => match command {
+                    Ok(Command::Flush(reply_tx)) => {
+                        let _ = reply_tx.send(self.flush());
+                    }
+                    Ok(Command::Shutdown(reply_tx)) => {
+                        let _ = reply_tx.send(self.shutdown());
+                        return;
+                    }
+                    Err(_) => {
+                        let _ = self.shutdown();
+                        return;
+                    }
+                },
+                recv(timer) -> _ => {
+                    if let Err(e) = self.export_batch() {
+                        (self.error_handler)(e);
+                    }
+                }
+            }
+        }
+    }
+
+    fn push(&mut self, span: FinishedSpan<T>) {
+        if self.batch.is_empty() {
+            self.batch_deadline = Some(Instant::now() + self.max_batch_age);
+        }
+        self.batch.push(span);
+        if self.batch.len() >= self.max_batch_size {
+            if let Err(e) = self.export_batch() {
+                (self.error_handler)(e);
+            }
+        }
+    }
+
+    fn export_batch(&mut self) -> Result<()> {
+        self.batch_deadline = None;
+        if self.batch.is_empty() {
+            return Ok(());
+        }
+        let batch = mem::take(&mut self.batch);
+        track!(self.exporter.export(batch))
+    }
+
+    /// Exports the spans which have been queued by the time this method is called.
+    ///
+    /// The spans queued during the call are left to the next iteration,
+    /// so that this terminates even if the producers never stop.
+    fn drain(&mut self) -> Result<()> {
+        let mut result = Ok(());
+        for _ in 0..self.span_rx.len() {
+            let span = if let Ok(span) = self.span_rx.try_recv() {
+                span
+            } else {
+                break;
+            };
+            self.batch.push(span);
+            if self.batch.len() >= self.max_batch_size {
+                result = result.and(track!(self.export_batch()));
+            }
+        }
+        result.and(track!(self.export_batch()))
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        track!(self.drain())?;
+        track!(self.exporter.flush())
+    }
+
+    fn shutdown(&mut self) -> Result<()> {
+        let result = track!(self.drain());
+        result.and(track!(self.exporter.shutdown()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::AllSampler;
+    use crate::Tracer;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Debug, Default, Clone)]
+    struct TestExporter {
+        batches: Arc<Mutex<Vec<Vec<String>>>>,
+        shutdown: Arc<Mutex<bool>>,
+    }
+    impl Exporter<()> for TestExporter {
+        fn export(&mut self, spans: Vec<FinishedSpan<()>>) -> Result<()> {
+            let names = spans
+                .iter()
+                .map(|s| s.operation_name().to_owned())
+                .collect();
+            self.batches.lock().unwrap().push(names);
+            Ok(())
+        }
+
+        fn shutdown(&mut self) -> Result<()> {
+            *self.shutdown.lock().unwrap() = true;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn batches_by_size() {
+        let (span_tx, span_rx) = crossbeam_channel::bounded(10);
+        let tracer = Tracer::with_sender(AllSampler, span_tx);
+        let exporter = TestExporter::default();
+        let reporter = ReporterBuilder::new(span_rx, exporter.clone())
+            .max_batch_size(2)
+            .max_batch_age(Duration::from_secs(60))
+            .spawn();
+        for name in &["a", "b", "c"] {
+            let _ = tracer.span(*name).start_with_state(());
+        }
+        reporter.flush().unwrap();
+        assert_eq!(
+            *exporter.batches.lock().unwrap(),
+            vec![vec!["a", "b"], vec!["c"]]
+        );
+
+        reporter.shutdown(Duration::from_secs(10)).unwrap();
+        assert!(*exporter.shutdown.lock().unwrap());
+    }
+
+    #[test]
+    fn flush_terminates_under_continuous_load() {
+        // Exporter which produces a new span for each exported one
+        struct Feedback(Tracer<AllSampler, ()>);
+        impl Exporter<()> for Feedback {
+            fn export(&mut self, spans: Vec<FinishedSpan<()>>) -> Result<()> {
+                for _ in spans {
+                    let _ = self.0.span("loop").start_with_state(());
+                }
+                Ok(())
+            }
+        }
+
+        let (span_tx, span_rx) = crossbeam_channel::bounded(10);
+        let tracer = Tracer::with_sender(AllSampler, span_tx);
+        let reporter = ReporterBuilder::new(span_rx, Feedback(tracer.clone()))
+            .max_batch_size(1)
+            .max_batch_age(Duration::from_secs(60))
+            .spawn();
+        let _ = tracer.span("a").start_with_state(());
+        reporter.flush().unwrap();
+    }
+
+    #[test]
+    fn batches_by_age() {
+        let (span_tx, span_rx) = crossbeam_channel::bounded(10);
+        let tracer = Tracer::with_sender(AllSampler, span_tx);
+        let exporter = TestExporter::default();
+        let _reporter = ReporterBuilder::new(span_rx, exporter.clone())
+            .max_batch_age(Duration::from_millis(10))
+            .spawn();
+        let _ = tracer.span("a").start_with_state(());
+
+        let start = Instant::now();
+        while exporter.batches.lock().unwrap().is_empty() {
+            assert!(start.elapsed() < Duration::from_secs(10));
+            thread::sleep(Duration::from_millis(5));
+        }
+        assert_eq!(*exporter.batches.lock().unwrap(), vec![vec!["a"]]);
+    }
+
+    #[test]
+    fn stops_when_senders_are_dropped() {
+        let (span_tx, span_rx) = crossbeam_channel::bounded(10);
+        let tracer = Tracer::with_sender(AllSampler, span_tx);
+        let exporter = TestExporter::default();
+        let reporter = ReporterBuilder::new(span_rx, exporter.clone()).spawn();
+        let _ = tracer.span("a").start_with_state(());
+        drop(tracer);
+
+        let start = Instant::now();
+        while !*exporter.shutdown.lock().unwrap() {
+            assert!(start.elapsed() < Duration::from_secs(10));
+            thread::sleep(Duration::from_millis(5));
+        }
+        assert_eq!(*exporter.batches.lock().unwrap(), vec![vec!["a"]]);
+        assert!(reporter.flush().is_err());
+        reporter.shutdown(Duration::from_secs(10)).unwrap();
+    }
+}
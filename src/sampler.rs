@@ -1,7 +1,12 @@
 //! `Sampler` trait and its built-in implementations.
-use crate::span::CandidateSpan;
+use crate::span::{CandidateSpan, SpanReference};
+use crate::state::SpanContextState;
 use crate::{ErrorKind, Result};
 use rand::{self, Rng};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
 
 /// `Sampler` decides whether a new trace should be sampled or not.
 pub trait Sampler<T> {
@@ -67,9 +72,564 @@ impl ProbabilisticSampler {
         Ok(ProbabilisticSampler { sampling_rate })
     }
 }
+impl ProbabilisticSampler {
+    fn sample(&self) -> bool {
+        rand::thread_rng().gen_range(0.0..1.0) < self.sampling_rate
+    }
+}
 impl<T> Sampler<T> for ProbabilisticSampler {
     fn is_sampled(&self, _span: &CandidateSpan<T>) -> bool {
-        rand::thread_rng().gen_range(0.0..1.0) < self.sampling_rate
+        self.sample()
+    }
+}
+
+/// This samples at most a certain number of traces per second.
+///
+/// It is a [token bucket][token_bucket] which is refilled at the rate of `traces_per_second`
+/// and holds at most `burst` tokens. Each sampled trace consumes one token.
+///
+/// Since the limit applies to traces, spans which have references (i.e., the non-root spans of a trace)
+/// are always sampled without consuming tokens.
+/// To follow the sampling decision of the parent span instead, wrap it in `ParentBasedSampler`.
+///
+/// [token_bucket]: https://en.wikipedia.org/wiki/Token_bucket
+///
+/// # Examples
+///
+/// ```
+/// use rustracing::sampler::{ParentBasedSampler, RateLimitingSampler};
+/// use rustracing::state::SpanContextState;
+/// use rustracing::Tracer;
+///
+/// let sampler = ParentBasedSampler::new(RateLimitingSampler::new(0.1, 2.0).unwrap());
+/// let (span_tx, _span_rx) = crossbeam_channel::bounded(10);
+/// let tracer = Tracer::<_, SpanContextState>::with_sender(sampler, span_tx);
+///
+/// let root = tracer.span("foo").start();
+/// assert!(root.is_sampled());
+/// assert!(tracer.span("foo").start().is_sampled());
+/// assert!(!tracer.span("foo").start().is_sampled());
+///
+/// // Child spans follow the decision of their parent without consuming tokens
+/// assert!(root.child("bar", |options| options.start()).is_sampled());
+/// ```
+#[derive(Debug)]
+pub struct RateLimitingSampler {
+    bucket: TokenBucket,
+}
+impl RateLimitingSampler {
+    /// Makes a new `RateLimitingSampler` instance.
+    ///
+    /// The bucket is initially full, so up to `burst` traces can be sampled at once.
+    ///
+    /// # Errors
+    ///
+    /// If `traces_per_second` is negative or not finite, or `burst` is less than `1.0` or not finite,
+    /// it will return an error with the kind `ErrorKind::InvalidInput`.
+    pub fn new(traces_per_second: f64, burst: f64) -> Result<Self> {
+        let bucket = track!(TokenBucket::new(traces_per_second, burst))?;
+        Ok(RateLimitingSampler { bucket })
+    }
+
+    /// Returns the number of traces added to the bucket per second.
+    pub fn traces_per_second(&self) -> f64 {
+        self.bucket.rate
+    }
+
+    /// Returns the maximum number of traces which can be sampled at once.
+    pub fn burst(&self) -> f64 {
+        self.bucket.burst
+    }
+}
+impl<T> Sampler<T> for RateLimitingSampler {
+    fn is_sampled(&self, span: &CandidateSpan<T>) -> bool {
+        !span.references().is_empty() || self.bucket.try_acquire(Instant::now())
+    }
+}
+
+#[derive(Debug)]
+struct TokenBucket {
+    rate: f64,
+    burst: f64,
+    state: Mutex<TokenBucketState>,
+}
+impl TokenBucket {
+    fn new(rate: f64, burst: f64) -> Result<Self> {
+        track_assert!(rate.is_finite() && rate >= 0.0, ErrorKind::InvalidInput; rate);
+        track_assert!(burst.is_finite() && burst >= 1.0, ErrorKind::InvalidInput; burst);
+        Ok(TokenBucket {
+            rate,
+            burst,
+            state: Mutex::new(TokenBucketState {
+                tokens: burst,
+                last_refill: Instant::now(),
+            }),
+        })
+    }
+
+    fn try_acquire(&self, now: Instant) -> bool {
+        let mut state = self
+            .state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Some(elapsed) = now.checked_duration_since(state.last_refill) {
+            state.tokens = (state.tokens + elapsed.as_secs_f64() * self.rate).min(self.burst);
+            state.last_refill = now;
+        }
+        if state.tokens >= 1.0 {
+            state.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[derive(Debug)]
+struct TokenBucketState {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+/// The default value of `PerOperationSampler::max_operations`.
+pub const DEFAULT_MAX_OPERATIONS: usize = 2000;
+
+/// Sampling strategy of an operation used by `PerOperationSampler`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SamplingStrategy {
+    /// Samples a certain percentage of traces (see `ProbabilisticSampler`).
+    Probabilistic(f64),
+
+    /// Samples at most a certain number of traces per second (see `RateLimitingSampler`).
+    RateLimiting {
+        /// The number of traces sampled per second.
+        traces_per_second: f64,
+
+        /// The maximum number of traces which can be sampled at once.
+        burst: f64,
+    },
+}
+
+/// This samples traces using a strategy selected by the operation name of the root span.
+///
+/// The operations whose strategies are not specified explicitly are sampled by the default strategy.
+/// Each operation has its own instance of the strategy
+/// (e.g., the rate limits are applied per operation).
+///
+/// To bound memory usage, at most `max_operations` operations are tracked individually.
+/// The operations which exceed the limit share a single instance of the default strategy.
+///
+/// The strategies are consulted only for root spans:
+/// spans which have references are always sampled and do not affect the strategies (e.g., the rate limits).
+///
+/// # Examples
+///
+/// ```
+/// use rustracing::sampler::{PerOperationSampler, SamplingStrategy};
+/// use rustracing::Tracer;
+///
+/// let sampler = PerOperationSampler::new(SamplingStrategy::Probabilistic(1.0))
+///     .unwrap()
+///     .strategy("health_check", SamplingStrategy::Probabilistic(0.0))
+///     .unwrap();
+/// let (span_tx, _span_rx) = crossbeam_channel::bounded(10);
+/// let tracer = Tracer::with_sender(sampler, span_tx);
+///
+/// assert!(tracer.span("foo").start_with_state(()).is_sampled());
+/// assert!(!tracer.span("health_check").start_with_state(()).is_sampled());
+/// ```
+#[derive(Debug)]
+pub struct PerOperationSampler {
+    default_strategy: SamplingStrategy,
+    max_operations: usize,
+    operations: Mutex<HashMap<Cow<'static, str>, StrategySampler>>,
+    fallback: StrategySampler,
+}
+impl PerOperationSampler {
+    /// Makes a new `PerOperationSampler` instance.
+    ///
+    /// # Errors
+    ///
+    /// If `default_strategy` has invalid parameters,
+    /// it will return an error with the kind `ErrorKind::InvalidInput`.
+    pub fn new(default_strategy: SamplingStrategy) -> Result<Self> {
+        let fallback = track!(StrategySampler::new(default_strategy))?;
+        Ok(PerOperationSampler {
+            default_strategy,
+            max_operations: DEFAULT_MAX_OPERATIONS,
+            operations: Mutex::new(HashMap::new()),
+            fallback,
+        })
+    }
+
+    /// Sets the sampling strategy of the operation `operation_name`.
+    ///
+    /// The explicitly specified operations are always tracked regardless of `max_operations`.
+    ///
+    /// # Errors
+    ///
+    /// If `strategy` has invalid parameters,
+    /// it will return an error with the kind `ErrorKind::InvalidInput`.
+    pub fn strategy<N>(self, operation_name: N, strategy: SamplingStrategy) -> Result<Self>
+    where
+        N: Into<Cow<'static, str>>,
+    {
+        let sampler = track!(StrategySampler::new(strategy))?;
+        self.lock().insert(operation_name.into(), sampler);
+        Ok(self)
+    }
+
+    /// Sets the maximum number of operations tracked by this sampler.
+    ///
+    /// The default value is `DEFAULT_MAX_OPERATIONS`.
+    pub fn max_operations(mut self, n: usize) -> Self {
+        self.max_operations = n;
+        self
+    }
+
+    /// Returns the number of operations tracked by this sampler.
+    pub fn operation_count(&self) -> usize {
+        self.lock().len()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<Cow<'static, str>, StrategySampler>> {
+        self.operations
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+impl<T> Sampler<T> for PerOperationSampler {
+    fn is_sampled(&self, span: &CandidateSpan<T>) -> bool {
+        if !span.references().is_empty() {
+            return true;
+        }
+        let mut operations = self.lock();
+        if let Some(sampler) = operations.get(span.operation_name()) {
+            return sampler.is_sampled();
+        }
+        if operations.len() >= self.max_operations {
+            return self.fallback.is_sampled();
+        }
+        let sampler = StrategySampler::new(self.default_strategy).expect("never fails");
+        let sampled = sampler.is_sampled();
+        operations.insert(Cow::Owned(span.operation_name().to_owned()), sampler);
+        sampled
+    }
+}
+
+#[derive(Debug)]
+enum StrategySampler {
+    Probabilistic(ProbabilisticSampler),
+    RateLimiting(RateLimitingSampler),
+}
+impl StrategySampler {
+    fn new(strategy: SamplingStrategy) -> Result<Self> {
+        Ok(match strategy {
+            SamplingStrategy::Probabilistic(sampling_rate) => {
+                StrategySampler::Probabilistic(track!(ProbabilisticSampler::new(sampling_rate))?)
+            }
+            SamplingStrategy::RateLimiting {
+                traces_per_second,
+                burst,
+            } => StrategySampler::RateLimiting(track!(RateLimitingSampler::new(
+                traces_per_second,
+                burst
+            ))?),
+        })
+    }
+
+    fn is_sampled(&self) -> bool {
+        match self {
+            StrategySampler::Probabilistic(s) => s.sample(),
+            StrategySampler::RateLimiting(s) => s.bucket.try_acquire(Instant::now()),
+        }
+    }
+}
+
+/// The default value of `AdaptiveSampler::window`.
+pub const DEFAULT_ADAPTIVE_WINDOW: Duration = Duration::from_secs(60);
+
+/// The default value of `AdaptiveSampler::lower_bound_interval`.
+pub const DEFAULT_LOWER_BOUND_INTERVAL: Duration = Duration::from_secs(60);
+
+const WINDOW_BUCKETS: usize = 10;
+
+/// This samples traces with a probability adjusted to achieve a target throughput.
+///
+/// The request rate of each operation is measured over a sliding window,
+/// and the operation is sampled with the probability `target / rate` (capped at `1.0`).
+///
+/// In addition, each operation is guaranteed to be sampled at least once per `lower_bound_interval`,
+/// so rare operations are not starved.
+///
+/// To bound memory usage, at most `max_operations` operations are tracked individually.
+/// The operations which exceed the limit share a single measurement.
+///
+/// Only root spans are counted as requests:
+/// spans which have references are always sampled and do not affect the measurements.
+///
+/// # Examples
+///
+/// ```
+/// use rustracing::sampler::AdaptiveSampler;
+/// use rustracing::Tracer;
+///
+/// let sampler = AdaptiveSampler::new(0.0).unwrap();
+/// let (span_tx, _span_rx) = crossbeam_channel::bounded(10);
+/// let tracer = Tracer::with_sender(sampler, span_tx);
+///
+/// // Sampled by the lower bound guarantee
+/// assert!(tracer.span("foo").start_with_state(()).is_sampled());
+/// assert!(!tracer.span("foo").start_with_state(()).is_sampled());
+/// assert!(tracer.span("bar").start_with_state(()).is_sampled());
+/// ```
+#[derive(Debug)]
+pub struct AdaptiveSampler {
+    target_traces_per_second: f64,
+    window: Duration,
+    lower_bound_interval: Duration,
+    max_operations: usize,
+    state: Mutex<AdaptiveState>,
+}
+impl AdaptiveSampler {
+    /// Makes a new `AdaptiveSampler` instance which samples
+    /// `target_traces_per_second` traces per second for each operation.
+    ///
+    /// # Errors
+    ///
+    /// If `target_traces_per_second` is negative or not finite,
+    /// it will return an error with the kind `ErrorKind::InvalidInput`.
+    pub fn new(target_traces_per_second: f64) -> Result<Self> {
+        track_assert!(
+            target_traces_per_second.is_finite() && target_traces_per_second >= 0.0,
+            ErrorKind::InvalidInput;
+            target_traces_per_second
+        );
+        Ok(AdaptiveSampler {
+            target_traces_per_second,
+            window: DEFAULT_ADAPTIVE_WINDOW,
+            lower_bound_interval: DEFAULT_LOWER_BOUND_INTERVAL,
+            max_operations: DEFAULT_MAX_OPERATIONS,
+            state: Mutex::new(AdaptiveState::default()),
+        })
+    }
+
+    /// Sets the length of the sliding window used to measure the request rates.
+    ///
+    /// The default value is `DEFAULT_ADAPTIVE_WINDOW`.
+    pub fn window(mut self, window: Duration) -> Self {
+        self.window = window;
+        self
+    }
+
+    /// Sets the interval in which each operation is sampled at least once.
+    ///
+    /// The default value is `DEFAULT_LOWER_BOUND_INTERVAL`.
+    pub fn lower_bound_interval(mut self, interval: Duration) -> Self {
+        self.lower_bound_interval = interval;
+        self
+    }
+
+    /// Sets the maximum number of operations tracked by this sampler.
+    ///
+    /// The default value is `DEFAULT_MAX_OPERATIONS`.
+    pub fn max_operations(mut self, n: usize) -> Self {
+        self.max_operations = n;
+        self
+    }
+
+    /// Returns the current sampling probability of the operation `operation_name`.
+    ///
+    /// If the operation has not been seen yet, it returns `None`.
+    pub fn sampling_rate(&self, operation_name: &str) -> Option<f64> {
+        let state = self.lock();
+        let stats = state.operations.get(operation_name)?;
+        Some(self.probability(stats, Instant::now()))
+    }
+
+    fn sample(&self, operation_name: &str, now: Instant, random: f64) -> bool {
+        let mut state = self.lock();
+        let state = &mut *state;
+        let stats = if let Some(stats) = state.operations.get_mut(operation_name) {
+            stats
+        } else if state.operations.len() < self.max_operations {
+            state
+                .operations
+                .entry(operation_name.to_owned())
+                .or_insert_with(|| OperationStats::new(now))
+        } else {
+            state
+                .fallback
+                .get_or_insert_with(|| OperationStats::new(now))
+        };
+
+        stats.record(now, self.window);
+        let guaranteed = match stats.last_sampled {
+            None => true,
+            Some(t) => now.saturating_duration_since(t) >= self.lower_bound_interval,
+        };
+        if guaranteed || random < self.probability(stats, now) {
+            stats.last_sampled = Some(now);
+            true
+        } else {
+            false
+        }
+    }
+
+    fn probability(&self, stats: &OperationStats, now: Instant) -> f64 {
+        let rate = stats.rate(now, self.window);
+        if rate > 0.0 {
+            (self.target_traces_per_second / rate).min(1.0)
+        } else {
+            1.0
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, AdaptiveState> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+impl<T> Sampler<T> for AdaptiveSampler {
+    fn is_sampled(&self, span: &CandidateSpan<T>) -> bool {
+        if !span.references().is_empty() {
+            return true;
+        }
+        let random = rand::thread_rng().gen_range(0.0..1.0);
+        self.sample(span.operation_name(), Instant::now(), random)
+    }
+}
+
+#[derive(Debug, Default)]
+struct AdaptiveState {
+    operations: HashMap<String, OperationStats>,
+    fallback: Option<OperationStats>,
+}
+
+#[derive(Debug)]
+struct OperationStats {
+    counts: [u64; WINDOW_BUCKETS],
+    current: usize,
+    bucket_start: Instant,
+    first_seen: Instant,
+    last_sampled: Option<Instant>,
+}
+impl OperationStats {
+    fn new(now: Instant) -> Self {
+        OperationStats {
+            counts: [0; WINDOW_BUCKETS],
+            current: 0,
+            bucket_start: now,
+            first_seen: now,
+            last_sampled: None,
+        }
+    }
+
+    fn record(&mut self, now: Instant, window: Duration) {
+        let bucket_len = bucket_len(window);
+        let elapsed = now.saturating_duration_since(self.bucket_start);
+        if elapsed >= window {
+            self.counts = [0; WINDOW_BUCKETS];
+            self.bucket_start = now;
+        } else {
+            while now.saturating_duration_since(self.bucket_start) >= bucket_len {
+                self.current = (self.current + 1) % WINDOW_BUCKETS;
+                self.counts[self.current] = 0;
+                self.bucket_start += bucket_len;
+            }
+        }
+        self.counts[self.current] += 1;
+    }
+
+    fn rate(&self, now: Instant, window: Duration) -> f64 {
+        let total = self.counts.iter().sum::<u64>();
+        let bucket_len = bucket_len(window);
+        let covered = bucket_len * (WINDOW_BUCKETS as u32 - 1)
+            + now.saturating_duration_since(self.bucket_start);
+        let elapsed = now
+            .saturating_duration_since(self.first_seen)
+            .min(covered)
+            .max(bucket_len);
+        total as f64 / elapsed.as_secs_f64()
+    }
+}
+
+fn bucket_len(window: Duration) -> Duration {
+    (window / WINDOW_BUCKETS as u32).max(Duration::from_millis(1))
+}
+
+/// Span context state which carries the sampling decision of the span.
+pub trait SampledFlag {
+    /// Returns `true` if the span is sampled.
+    fn is_sampled(&self) -> bool;
+}
+impl SampledFlag for SpanContextState {
+    fn is_sampled(&self) -> bool {
+        SpanContextState::is_sampled(self)
+    }
+}
+
+/// This follows the sampling decision of the parent span.
+///
+/// The first `ChildOf` reference (or the first `FollowsFrom` one if there are no `ChildOf` references)
+/// is regarded as the parent. If there are no references, the decision is delegated to the root sampler.
+///
+/// # Examples
+///
+/// ```
+/// use rustracing::sampler::{AllSampler, ParentBasedSampler};
+/// use rustracing::span::SpanContext;
+/// use rustracing::state::{SpanContextState, SpanId, TraceId};
+/// use rustracing::Tracer;
+///
+/// let (span_tx, _span_rx) = crossbeam_channel::bounded(10);
+/// let tracer = Tracer::<_, SpanContextState>::with_sender(ParentBasedSampler::new(AllSampler), span_tx);
+///
+/// // The caller decided not to sample the trace
+/// let mut caller = SpanContextState::new(TraceId::new(1), SpanId::new(2));
+/// caller.set_sampled(false);
+/// let caller = SpanContext::new(caller, Vec::new());
+/// let span = tracer.span("foo").child_of(&caller).start();
+/// assert!(!span.is_sampled());
+///
+/// // Root spans are sampled by `AllSampler`
+/// let span = tracer.span("bar").start();
+/// assert!(span.is_sampled());
+/// ```
+#[derive(Debug, Clone)]
+pub struct ParentBasedSampler<S> {
+    root: S,
+}
+impl<S> ParentBasedSampler<S> {
+    /// Makes a new `ParentBasedSampler` instance.
+    ///
+    /// `root` is used to decide whether root spans should be sampled or not.
+    pub fn new(root: S) -> Self {
+        ParentBasedSampler { root }
+    }
+
+    /// Returns a reference to the root sampler.
+    pub fn root_sampler(&self) -> &S {
+        &self.root
+    }
+}
+impl<S, T> Sampler<T> for ParentBasedSampler<S>
+where
+    S: Sampler<T>,
+    T: SampledFlag,
+{
+    fn is_sampled(&self, span: &CandidateSpan<T>) -> bool {
+        let parent = span
+            .references()
+            .iter()
+            .find(|r| r.is_child_of())
+            .or_else(|| span.references().first())
+            .map(SpanReference::span);
+        match parent {
+            Some(parent) => parent.is_sampled(),
+            None => self.root.is_sampled(span),
+        }
     }
 }
 
@@ -125,3 +685,196 @@ where
         self.0.is_sampled(span) && self.1.is_sampled(span)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn token_bucket_works() {
+        let bucket = TokenBucket::new(2.0, 3.0).unwrap();
+        let start = Instant::now();
+        let now = start + Duration::from_millis(1);
+        assert!((0..3).all(|_| bucket.try_acquire(now)));
+        assert!(!bucket.try_acquire(now));
+
+        // Refilled with one token per 500ms
+        let now = now + Duration::from_millis(500);
+        assert!(bucket.try_acquire(now));
+        assert!(!bucket.try_acquire(now));
+
+        // Refilled up to the burst size
+        let now = now + Duration::from_secs(10);
+        assert_eq!((0..10).filter(|_| bucket.try_acquire(now)).count(), 3);
+    }
+
+    #[test]
+    fn rate_limiting_sampler_rejects_invalid_parameters() {
+        assert!(RateLimitingSampler::new(-1.0, 1.0).is_err());
+        assert!(RateLimitingSampler::new(f64::INFINITY, 1.0).is_err());
+        assert!(RateLimitingSampler::new(1.0, 0.5).is_err());
+        assert!(RateLimitingSampler::new(0.0, 1.0).is_ok());
+    }
+
+    #[test]
+    fn rate_limiting_sampler_samples_child_spans() {
+        let sampler = RateLimitingSampler::new(0.0, 1.0).unwrap();
+        let (span_tx, _span_rx) = crossbeam_channel::unbounded();
+        let tracer = crate::Tracer::with_sender(sampler, span_tx);
+
+        let root = tracer.span("root").start_with_state(());
+        assert!(root.is_sampled());
+        assert!(!tracer.span("root").start_with_state(()).is_sampled());
+        for _ in 0..3 {
+            let child = root.child("child", |options| options.start_with_state(()));
+            assert!(child.is_sampled());
+        }
+    }
+
+    #[test]
+    fn per_operation_sampler_works() {
+        let sampler = PerOperationSampler::new(SamplingStrategy::RateLimiting {
+            traces_per_second: 0.0,
+            burst: 1.0,
+        })
+        .unwrap()
+        .strategy("all", SamplingStrategy::Probabilistic(1.0))
+        .unwrap()
+        .max_operations(3);
+        let (span_tx, _span_rx) = crossbeam_channel::unbounded();
+        let tracer = crate::Tracer::with_sender(sampler, span_tx);
+        let is_sampled = |name: &'static str| tracer.span(name).start_with_state(()).is_sampled();
+
+        assert!(is_sampled("all"));
+        assert!(is_sampled("all"));
+
+        // Each operation has its own rate limit
+        assert!(is_sampled("foo"));
+        assert!(!is_sampled("foo"));
+        assert!(is_sampled("bar"));
+        assert!(!is_sampled("bar"));
+
+        // The untracked operations share the fallback rate limit
+        assert!(is_sampled("baz"));
+        assert!(!is_sampled("qux"));
+    }
+
+    #[test]
+    fn per_operation_sampler_samples_child_spans() {
+        let sampler = PerOperationSampler::new(SamplingStrategy::RateLimiting {
+            traces_per_second: 0.0,
+            burst: 1.0,
+        })
+        .unwrap();
+        let (span_tx, _span_rx) = crossbeam_channel::unbounded();
+        let tracer = crate::Tracer::with_sender(sampler, span_tx);
+
+        let root = tracer.span("foo").start_with_state(());
+        assert!(root.is_sampled());
+        assert!(!tracer.span("foo").start_with_state(()).is_sampled());
+
+        // The strategies are not consulted for child spans
+        let child = root.child("foo", |options| options.start_with_state(()));
+        assert!(child.is_sampled());
+        let child = root.child("bar", |options| options.start_with_state(()));
+        assert!(child.is_sampled());
+        assert!(tracer.span("bar").start_with_state(()).is_sampled());
+    }
+
+    #[test]
+    fn adaptive_sampler_works() {
+        let sampler = AdaptiveSampler::new(2.0)
+            .unwrap()
+            .window(Duration::from_secs(10))
+            .lower_bound_interval(Duration::from_secs(5));
+        let start = Instant::now();
+
+        // 100 requests per second
+        for i in 0..1000 {
+            let now = start + Duration::from_millis(i * 10);
+            sampler.sample("foo", now, 0.5);
+        }
+        let now = start + Duration::from_secs(10);
+        let stats = &sampler.lock().operations["foo"];
+        let probability = sampler.probability(stats, now);
+        assert!((probability - 0.02).abs() < 0.001, "{}", probability);
+    }
+
+    #[test]
+    fn adaptive_sampler_guarantees_lower_bound() {
+        let sampler = AdaptiveSampler::new(0.0)
+            .unwrap()
+            .lower_bound_interval(Duration::from_secs(5))
+            .max_operations(1);
+        let start = Instant::now();
+        assert!(sampler.sample("foo", start, 0.0));
+        assert!(!sampler.sample("foo", start + Duration::from_secs(4), 0.0));
+        assert!(sampler.sample("foo", start + Duration::from_secs(5), 0.0));
+
+        // The untracked operations share the lower bound
+        assert!(sampler.sample("bar", start, 0.0));
+        assert!(!sampler.sample("baz", start, 0.0));
+        assert_eq!(sampler.lock().operations.len(), 1);
+    }
+
+    #[test]
+    fn adaptive_sampler_does_not_count_child_spans() {
+        let (span_tx, _span_rx) = crossbeam_channel::unbounded();
+        let tracer = crate::Tracer::with_sender(AdaptiveSampler::new(0.0).unwrap(), span_tx);
+
+        let root = tracer.span("foo").start_with_state(());
+        assert!(root.is_sampled());
+        for _ in 0..3 {
+            let child = root.child("bar", |options| options.start_with_state(()));
+            assert!(child.is_sampled());
+        }
+
+        // `bar` has not been seen as a request, so it is sampled by the lower bound guarantee
+        assert!(tracer.span("bar").start_with_state(()).is_sampled());
+        assert!(!tracer.span("foo").start_with_state(()).is_sampled());
+    }
+
+    #[test]
+    fn parent_based_sampler_works() {
+        let sampler = ParentBasedSampler::new(NullSampler);
+        let (span_tx, _span_rx) = crossbeam_channel::unbounded();
+        let tracer = crate::Tracer::<_, SpanContextState>::with_sender(sampler, span_tx);
+        let context = |sampled| {
+            let mut state = SpanContextState::root();
+            state.set_sampled(sampled);
+            crate::span::SpanContext::new(state, Vec::new())
+        };
+
+        assert!(!tracer.span("root").start().is_sampled());
+        assert!(tracer
+            .span("a")
+            .child_of(&context(true))
+            .start()
+            .is_sampled());
+        assert!(!tracer
+            .span("b")
+            .child_of(&context(false))
+            .start()
+            .is_sampled());
+
+        // `ChildOf` references take precedence over `FollowsFrom` ones
+        let span = tracer
+            .span("c")
+            .follows_from(&context(false))
+            .child_of(&context(true))
+            .start();
+        assert!(span.is_sampled());
+    }
+
+    #[test]
+    fn per_operation_sampler_rejects_invalid_strategies() {
+        assert!(PerOperationSampler::new(SamplingStrategy::Probabilistic(1.5)).is_err());
+        let sampler = PerOperationSampler::new(SamplingStrategy::Probabilistic(0.5)).unwrap();
+        let strategy = SamplingStrategy::RateLimiting {
+            traces_per_second: 1.0,
+            burst: 0.0,
+        };
+        assert!(sampler.strategy("foo", strategy).is_err());
+    }
+}
@@ -0,0 +1,273 @@
+//! Thread-local active span management.
+//!
+//! Each thread has a stack of active spans.
+//! A span is activated by a guard (`ActiveSpan` or `SpanScope`) and
+//! it is deactivated when the guard is dropped, which restores the previously active span.
+//!
+//! A new span which has no explicit references becomes a `ChildOf` the active span by default
+//! (see `StartSpanOptions::ignore_active_span` and `StartSpanOptions::child_of_active`).
+//!
+//! # Examples
+//!
+//! ```
+//! use rustracing::sampler::AllSampler;
+//! use rustracing::state::SpanContextState;
+//! use rustracing::Tracer;
+//!
+//! let (span_tx, span_rx) = crossbeam_channel::bounded(10);
+//! let tracer = Tracer::<_, SpanContextState>::with_sender(AllSampler, span_tx);
+//! {
+//!     let _parent = tracer.start_active("parent", |options| options.start());
+//!     {
+//!         // `child` implicitly becomes a child of `parent`.
+//!         let _child = tracer.span("child").start();
+//!     }
+//! }
+//! assert!(tracer.active_span().is_none());
+//!
+//! let child = span_rx.try_recv().unwrap();
+//! let parent = span_rx.try_recv().unwrap();
+//! assert_eq!(
+//!     child.context().state().parent_span_id(),
+//!     Some(parent.context().state().span_id())
+//! );
+//! ```
+use crate::span::{Span, SpanHandle};
+use std::any::Any;
+use std::cell::{Cell, RefCell};
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+
+thread_local! {
+    static ACTIVE_SPANS: RefCell<Vec<(u64, Box<dyn Any>)>> = const { RefCell::new(Vec::new()) };
+    static NEXT_SCOPE_ID: Cell<u64> = const { Cell::new(0) };
+}
+
+/// Returns the handle of the span which is currently active in this thread.
+///
+/// If there are no active spans of the type `Span<T>`, it will return `None`.
+pub fn active_span<T>() -> Option<SpanHandle<T>>
+where
+    T: Clone + 'static,
+{
+    ACTIVE_SPANS
+        .try_with(|spans| {
+            spans
+                .borrow()
+                .iter()
+                .rev()
+                .find_map(|(_, span)| span.downcast_ref::<SpanHandle<T>>().cloned())
+        })
+        .ok()
+        .flatten()
+}
+
+/// Calls `f` with the handle of the span which is currently active in this thread.
+///
+/// Unlike `active_span`, the handle is not cloned.
+pub(crate) fn with_active_span<T, F, R>(f: F) -> Option<R>
+where
+    T: 'static,
+    F: FnOnce(&SpanHandle<T>) -> R,
+{
+    ACTIVE_SPANS
+        .try_with(|spans| {
+            spans
+                .borrow()
+                .iter()
+                .rev()
+                .find_map(|(_, span)| span.downcast_ref::<SpanHandle<T>>())
+                .map(f)
+        })
+        .ok()
+        .flatten()
+}
+
+/// A guard that keeps a span handle active while it is alive.
+///
+/// This does not own the span, so dropping this guard does not finish the span.
+#[derive(Debug)]
+pub struct SpanScope {
+    id: u64,
+    _not_send: PhantomData<*const ()>,
+}
+impl SpanScope {
+    /// Activates the span associated with `handle` in the current thread.
+    pub fn enter<T>(handle: &SpanHandle<T>) -> Self
+    where
+        T: Clone + 'static,
+    {
+        handle.enable_active_parent();
+        let id = NEXT_SCOPE_ID.with(|next| {
+            let id = next.get();
+            next.set(id.wrapping_add(1));
+            id
+        });
+        ACTIVE_SPANS.with(|spans| {
+            spans.borrow_mut().push((id, Box::new(handle.clone())));
+        });
+        SpanScope {
+            id,
+            _not_send: PhantomData,
+        }
+    }
+}
+impl Drop for SpanScope {
+    fn drop(&mut self) {
+        let id = self.id;
+        let _ = ACTIVE_SPANS.try_with(|spans| {
+            // Removes the entry and drops it after the borrow is released.
+            let mut spans = spans.borrow_mut();
+            spans
+                .iter()
+                .rposition(|x| x.0 == id)
+                .map(|i| spans.remove(i))
+        });
+    }
+}
+
+/// A guard that owns a span and keeps it active while it is alive.
+///
+/// When this guard is dropped, the span is deactivated and then finished.
+#[derive(Debug)]
+pub struct ActiveSpan<T> {
+    // NOTE: `_scope` is dropped before `span`.
+    _scope: SpanScope,
+    span: Span<T>,
+}
+impl<T> ActiveSpan<T>
+where
+    T: Clone + 'static,
+{
+    /// Activates `span` in the current thread.
+    pub fn new(span: Span<T>) -> Self {
+        let scope = SpanScope::enter(&span.handle());
+        ActiveSpan {
+            _scope: scope,
+            span,
+        }
+    }
+}
+impl<T> Deref for ActiveSpan<T> {
+    type Target = Span<T>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.span
+    }
+}
+impl<T> DerefMut for ActiveSpan<T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.span
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::AllSampler;
+    use crate::state::SpanContextState;
+    use crate::Tracer;
+
+    fn span_id(handle: &SpanHandle<SpanContextState>) -> u64 {
+        handle.context().unwrap().state().span_id().to_u64()
+    }
+
+    #[test]
+    fn nested_scopes_are_restored() {
+        let (span_tx, _span_rx) = crossbeam_channel::bounded(10);
+        let tracer = Tracer::<_, SpanContextState>::with_sender(AllSampler, span_tx);
+        assert!(tracer.active_span().is_none());
+
+        let outer = tracer.start_active("outer", |options| options.start());
+        let outer_id = span_id(&outer.handle());
+        assert_eq!(span_id(&tracer.active_span().unwrap()), outer_id);
+        {
+            let inner = tracer.start_active("inner", |options| options.start());
+            assert_eq!(
+                span_id(&tracer.active_span().unwrap()),
+                span_id(&inner.handle())
+            );
+            assert_eq!(
+                inner
+                    .context()
+                    .unwrap()
+                    .state()
+                    .parent_span_id()
+                    .unwrap()
+                    .to_u64(),
+                outer_id
+            );
+        }
+        assert_eq!(span_id(&tracer.active_span().unwrap()), outer_id);
+
+        // Out-of-order drop
+        let a = tracer.start_active("a", |options| options.start());
+        let b = tracer.start_active("b", |options| options.start());
+        let b_id = span_id(&b.handle());
+        drop(a);
+        assert_eq!(span_id(&tracer.active_span().unwrap()), b_id);
+        drop(b);
+        assert_eq!(span_id(&tracer.active_span().unwrap()), outer_id);
+
+        drop(outer);
+        assert!(tracer.active_span().is_none());
+    }
+
+    #[test]
+    fn default_parent_can_be_overridden() {
+        let (span_tx, _span_rx) = crossbeam_channel::bounded(10);
+        let tracer = Tracer::<_, SpanContextState>::with_sender(AllSampler, span_tx);
+        let other = tracer.span("other").start();
+        let active = tracer.start_active("active", |options| options.start());
+
+        // Spans without explicit references default to `ChildOf` the active span.
+        let span = tracer.span("child").start();
+        assert_eq!(
+            span.context().unwrap().state().parent_span_id(),
+            Some(active.context().unwrap().state().span_id())
+        );
+
+        // Explicit references replace the active span reference.
+        let span = tracer.span("explicit").child_of(&other).start();
+        assert_eq!(
+            span.context().unwrap().state().parent_span_id(),
+            Some(other.context().unwrap().state().span_id())
+        );
+
+        let span = tracer.span("root").ignore_active_span().start();
+        assert_eq!(span.context().unwrap().state().parent_span_id(), None);
+
+        let span = tracer
+            .span("root")
+            .child_of_active()
+            .ignore_active_span()
+            .start();
+        assert_eq!(span.context().unwrap().state().parent_span_id(), None);
+
+        drop(active);
+        let span = tracer.span("root").start();
+        assert_eq!(span.context().unwrap().state().parent_span_id(), None);
+
+        // Spans of other state types are not affected.
+        let (span_tx, _span_rx) = crossbeam_channel::bounded(10);
+        let unit_tracer = Tracer::<_, ()>::with_sender(AllSampler, span_tx);
+        assert!(unit_tracer.active_span().is_none());
+    }
+
+    #[test]
+    fn plain_span_in_scope_becomes_child() {
+        let (span_tx, span_rx) = crossbeam_channel::bounded(10);
+        let tracer = Tracer::<_, SpanContextState>::with_sender(AllSampler, span_tx);
+        let parent = tracer.span("parent").start();
+        {
+            let _scope = SpanScope::enter(&parent.handle());
+            let _child = tracer.clone().span("x").start();
+        }
+        let child = span_rx.try_recv().unwrap();
+        assert_eq!(
+            child.context().state().parent_span_id(),
+            Some(parent.context().unwrap().state().span_id())
+        );
+        assert_eq!(child.references().len(), 1);
+    }
+}
@@ -2,11 +2,17 @@
 use crate::carrier;
 use crate::convert::MaybeAsRef;
 use crate::log::{Log, LogBuilder, StdErrorLogFieldsBuilder};
+use crate::processor::SpanProcessor;
 use crate::sampler::{AllSampler, Sampler};
+use crate::scope;
 use crate::tag::{StdTag, Tag, TagValue};
+use crate::tracer::Counters;
 use crate::Result;
+use crossbeam_channel::TrySendError;
 use std::borrow::Cow;
+use std::fmt;
 use std::io::{Read, Write};
+use std::sync::{Arc, Mutex};
 use std::time::SystemTime;
 
 /// Finished span receiver.
@@ -45,7 +51,7 @@ impl<T> Span<T> {
         SpanHandle(
             self.0
                 .as_ref()
-                .map(|inner| (inner.context.clone(), inner.span_tx.clone())),
+                .map(|inner| (inner.context.clone(), inner.sink.clone())),
         )
     }
 
@@ -188,6 +194,66 @@ impl<T> Span<T> {
         self.handle().follower(operation_name, f)
     }
 
+    /// Finishes this span and sends it to the associated `SpanReceiver`.
+    ///
+    /// This is the same as dropping the span, except that the outcome of the delivery is returned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rustracing::sampler::AllSampler;
+    /// use rustracing::span::SpanDelivery;
+    /// use rustracing::Tracer;
+    ///
+    /// let (span_tx, span_rx) = crossbeam_channel::bounded(1);
+    /// let tracer = Tracer::with_sender(AllSampler, span_tx);
+    ///
+    /// let span = tracer.span("foo").start_with_state(());
+    /// assert_eq!(span.finish(), SpanDelivery::Delivered);
+    ///
+    /// let span = tracer.span("bar").start_with_state(());
+    /// assert_eq!(span.finish(), SpanDelivery::DroppedFull);
+    ///
+    /// std::mem::drop(span_rx);
+    /// let span = tracer.span("baz").start_with_state(());
+    /// assert_eq!(span.finish(), SpanDelivery::DroppedDisconnected);
+    /// ```
+    pub fn finish(mut self) -> SpanDelivery {
+        self.send()
+    }
+
+    fn send(&mut self) -> SpanDelivery {
+        if let Some(inner) = self.0.take() {
+            let mut finished = FinishedSpan {
+                operation_name: inner.operation_name,
+                start_time: inner.start_time,
+                finish_time: inner.finish_time.unwrap_or_else(SystemTime::now),
+                references: inner.references,
+                tags: inner.tags,
+                logs: inner.logs,
+                context: inner.context,
+            };
+            let delivery = if !inner
+                .sink
+                .processors
+                .iter()
+                .all(|p| p.on_end(&mut finished))
+            {
+                SpanDelivery::Discarded
+            } else {
+                match inner.sink.span_tx.try_send(finished) {
+                    Ok(()) => SpanDelivery::Delivered,
+                    Err(TrySendError::Full(_)) => SpanDelivery::DroppedFull,
+                    Err(TrySendError::Disconnected(_)) => SpanDelivery::DroppedDisconnected,
+                }
+            };
+            inner.sink.counters.record_delivery(delivery);
+            delivery
+        } else {
+            SpanDelivery::NotSampled
+        }
+    }
+
     pub(crate) fn new(
         operation_name: Cow<'static, str>,
         start_time: SystemTime,
@@ -195,7 +261,7 @@ impl<T> Span<T> {
         tags: Vec<Tag>,
         state: T,
         baggage_items: Vec<BaggageItem>,
-        span_tx: SpanSender<T>,
+        sink: SpanSink<T>,
     ) -> Self {
         let context = SpanContext::new(state, baggage_items);
         let inner = SpanInner {
@@ -206,25 +272,14 @@ impl<T> Span<T> {
             tags,
             logs: Vec::new(),
             context,
-            span_tx,
+            sink,
         };
         Span(Some(inner))
     }
 }
 impl<T> Drop for Span<T> {
     fn drop(&mut self) {
-        if let Some(inner) = self.0.take() {
-            let finished = FinishedSpan {
-                operation_name: inner.operation_name,
-                start_time: inner.start_time,
-                finish_time: inner.finish_time.unwrap_or_else(SystemTime::now),
-                references: inner.references,
-                tags: inner.tags,
-                logs: inner.logs,
-                context: inner.context,
-            };
-            let _ = inner.span_tx.try_send(finished);
-        }
+        let _ = self.send();
     }
 }
 impl<T> MaybeAsRef<SpanContext<T>> for Span<T> {
@@ -233,6 +288,25 @@ impl<T> MaybeAsRef<SpanContext<T>> for Span<T> {
     }
 }
 
+/// The outcome of sending a finished span to the `SpanReceiver`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SpanDelivery {
+    /// The span was sent to the channel.
+    Delivered,
+
+    /// The span was dropped because the channel was full.
+    DroppedFull,
+
+    /// The span was dropped because the channel was disconnected.
+    DroppedDisconnected,
+
+    /// The span was discarded by a `SpanProcessor`.
+    Discarded,
+
+    /// The span was not sampled, so there was nothing to send.
+    NotSampled,
+}
+
 #[derive(Debug)]
 struct SpanInner<T> {
     operation_name: Cow<'static, str>,
@@ -242,11 +316,86 @@ struct SpanInner<T> {
     tags: Vec<Tag>,
     logs: Vec<Log>,
     context: SpanContext<T>,
+    sink: SpanSink<T>,
+}
+
+/// The destination of finished spans.
+/// Function which returns the context of the active span of the current thread.
+type ActiveParentLookup<T> = fn() -> Option<SpanContext<T>>;
+
+pub(crate) struct SpanSink<T> {
     span_tx: SpanSender<T>,
+    counters: Arc<Counters>,
+    processors: Arc<Vec<Arc<dyn SpanProcessor<T>>>>,
+    active_parent: Arc<Mutex<Option<ActiveParentLookup<T>>>>,
+}
+impl<T> SpanSink<T> {
+    pub(crate) fn new(span_tx: SpanSender<T>, counters: Arc<Counters>) -> Self {
+        SpanSink {
+            span_tx,
+            counters,
+            processors: Arc::new(Vec::new()),
+            active_parent: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Enables the lookup of the active span by the spans started via this sink.
+    ///
+    /// The lookup needs `T: Clone + 'static`, which `StartSpanOptions` does not require,
+    /// so it is registered when a span of this sink is activated (see the `scope` module).
+    pub(crate) fn enable_active_parent(&self)
+    where
+        T: Clone + 'static,
+    {
+        let mut lookup = self
+            .active_parent
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if lookup.is_none() {
+            *lookup = Some(|| {
+                scope::with_active_span(|span: &SpanHandle<T>| span.context().cloned()).flatten()
+            });
+        }
+    }
+
+    fn active_parent(&self) -> Option<SpanContext<T>> {
+        let lookup = *self
+            .active_parent
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        lookup.and_then(|lookup| lookup())
+    }
+
+    pub(crate) fn counters(&self) -> &Counters {
+        &self.counters
+    }
+
+    pub(crate) fn add_processor(&mut self, processor: Arc<dyn SpanProcessor<T>>) {
+        Arc::make_mut(&mut self.processors).push(processor);
+    }
+}
+impl<T> Clone for SpanSink<T> {
+    fn clone(&self) -> Self {
+        SpanSink {
+            span_tx: self.span_tx.clone(),
+            counters: Arc::clone(&self.counters),
+            processors: Arc::clone(&self.processors),
+            active_parent: Arc::clone(&self.active_parent),
+        }
+    }
+}
+impl<T> fmt::Debug for SpanSink<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SpanSink")
+            .field("span_tx", &self.span_tx)
+            .field("counters", &self.counters)
+            .field("processors", &self.processors.len())
+            .finish()
+    }
 }
 
 /// Finished span.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct FinishedSpan<T> {
     operation_name: Cow<'static, str>,
     start_time: SystemTime,
@@ -291,6 +440,24 @@ impl<T> FinishedSpan<T> {
     pub fn context(&self) -> &SpanContext<T> {
         &self.context
     }
+
+    /// Sets the operation name of this span.
+    pub fn set_operation_name<N>(&mut self, name: N)
+    where
+        N: Into<Cow<'static, str>>,
+    {
+        self.operation_name = name.into();
+    }
+
+    /// Returns the mutable reference to the tags of this span.
+    pub fn tags_mut(&mut self) -> &mut Vec<Tag> {
+        &mut self.tags
+    }
+
+    /// Returns the mutable reference to the logs of this span.
+    pub fn logs_mut(&mut self) -> &mut Vec<Log> {
+        &mut self.logs
+    }
 }
 
 /// Span context.
@@ -404,6 +571,7 @@ impl<T> MaybeAsRef<SpanContext<T>> for SpanContext<T> {
 pub struct BaggageItem {
     name: String,
     value: String,
+    properties: Vec<String>,
 }
 impl BaggageItem {
     /// Makes a new `BaggageItem` instance.
@@ -411,9 +579,23 @@ impl BaggageItem {
         BaggageItem {
             name: name.to_owned(),
             value: value.to_owned(),
+            properties: Vec::new(),
         }
     }
 
+    /// Adds the property (metadata) to this item.
+    ///
+    /// A property is an opaque string which has the form of either `key` or `key=value`.
+    /// Properties are only meaningful for formats that support them (e.g., W3C Baggage).
+    pub fn add_property(&mut self, property: &str) {
+        self.properties.push(property.to_owned());
+    }
+
+    /// Returns the properties of this item.
+    pub fn properties(&self) -> &[String] {
+        &self.properties
+    }
+
     /// Returns the name of this item.
     pub fn name(&self) -> &str {
         &self.name
@@ -454,11 +636,17 @@ impl<T> SpanReference<T> {
 /// Candidate span for tracing.
 #[derive(Debug)]
 pub struct CandidateSpan<'a, T: 'a> {
+    operation_name: &'a str,
     tags: &'a [Tag],
     references: &'a [SpanReference<T>],
     baggage_items: &'a [BaggageItem],
 }
 impl<'a, T: 'a> CandidateSpan<'a, T> {
+    /// Returns the operation name of this span.
+    pub fn operation_name(&self) -> &str {
+        self.operation_name
+    }
+
     /// Returns the tags of this span.
     pub fn tags(&self) -> &[Tag] {
         self.tags
@@ -483,7 +671,9 @@ pub struct StartSpanOptions<'a, S: 'a, T: 'a> {
     tags: Vec<Tag>,
     references: Vec<SpanReference<T>>,
     baggage_items: Vec<BaggageItem>,
-    span_tx: &'a SpanSender<T>,
+    active_parent: Option<SpanContext<T>>,
+    ignore_active_span: bool,
+    sink: &'a SpanSink<T>,
     sampler: &'a S,
 }
 impl<'a, S: 'a, T: 'a> StartSpanOptions<'a, S, T>
@@ -513,6 +703,7 @@ where
             self.references.push(reference);
             self.baggage_items
                 .extend(context.baggage_items().iter().cloned());
+            self.active_parent = None;
         }
         self
     }
@@ -528,10 +719,40 @@ where
             self.references.push(reference);
             self.baggage_items
                 .extend(context.baggage_items().iter().cloned());
+            self.active_parent = None;
         }
         self
     }
 
+    /// Adds the `ChildOf` reference to the active span of the current thread (see the `scope` module).
+    ///
+    /// By default, a span which has no explicit references becomes a child of the active span
+    /// if the active span was activated through the same tracer (or its clones).
+    /// This method looks up the active span regardless of which tracer activated it.
+    ///
+    /// This does nothing if references have already been added or there is no active span.
+    /// Adding a reference explicitly after this call replaces the active span reference.
+    pub fn child_of_active(mut self) -> Self
+    where
+        T: Clone + 'static,
+    {
+        if self.references.is_empty() {
+            self.active_parent =
+                scope::with_active_span(|span: &SpanHandle<T>| span.context().cloned()).flatten();
+            self.ignore_active_span = false;
+        }
+        self
+    }
+
+    /// Does not use the active span as the parent of this span.
+    ///
+    /// Without this, a span which has no explicit references becomes a `ChildOf` the active span.
+    pub fn ignore_active_span(mut self) -> Self {
+        self.active_parent = None;
+        self.ignore_active_span = true;
+        self
+    }
+
     /// Starts a new span.
     pub fn start(mut self) -> Span<T>
     where
@@ -539,38 +760,24 @@ where
     {
         self.normalize();
         if !self.is_sampled() {
+            self.sink.counters.record_sampled_out();
             return Span(None);
         }
         let state = T::from(self.span());
-        Span::new(
-            self.operation_name,
-            self.start_time.unwrap_or_else(SystemTime::now),
-            self.references,
-            self.tags,
-            state,
-            self.baggage_items,
-            self.span_tx.clone(),
-        )
+        self.start_sampled(state)
     }
 
     /// Starts a new span with the explicit `state`.
     pub fn start_with_state(mut self, state: T) -> Span<T> {
         self.normalize();
         if !self.is_sampled() {
+            self.sink.counters.record_sampled_out();
             return Span(None);
         }
-        Span::new(
-            self.operation_name,
-            self.start_time.unwrap_or_else(SystemTime::now),
-            self.references,
-            self.tags,
-            state,
-            self.baggage_items,
-            self.span_tx.clone(),
-        )
+        self.start_sampled(state)
     }
 
-    pub(crate) fn new<N>(operation_name: N, span_tx: &'a SpanSender<T>, sampler: &'a S) -> Self
+    pub(crate) fn new<N>(operation_name: N, sink: &'a SpanSink<T>, sampler: &'a S) -> Self
     where
         N: Into<Cow<'static, str>>,
     {
@@ -580,23 +787,58 @@ where
             tags: Vec::new(),
             references: Vec::new(),
             baggage_items: Vec::new(),
-            span_tx,
+            active_parent: None,
+            ignore_active_span: false,
+            sink,
             sampler,
         }
     }
 
     fn normalize(&mut self) {
-        self.tags.reverse();
-        self.tags.sort_by(|a, b| a.name().cmp(b.name()));
-        self.tags.dedup_by(|a, b| a.name() == b.name());
+        if self.references.is_empty() && !self.ignore_active_span {
+            let sink = self.sink;
+            let parent = self.active_parent.take().or_else(|| sink.active_parent());
+            if let Some(context) = parent {
+                self.references.push(SpanReference::ChildOf(context.state));
+                self.baggage_items.extend(context.baggage_items);
+            }
+        }
+
+        sort_and_dedup_tags(&mut self.tags);
 
         self.baggage_items.reverse();
         self.baggage_items.sort_by(|a, b| a.name().cmp(b.name()));
         self.baggage_items.dedup_by(|a, b| a.name() == b.name());
     }
 
-    fn span(&self) -> CandidateSpan<T> {
+    fn start_sampled(mut self, state: T) -> Span<T> {
+        self.sink.counters.record_started();
+
+        let mut tags = Vec::new();
+        for processor in self.sink.processors.iter() {
+            processor.on_start(&self.span(), &mut tags);
+        }
+        if !tags.is_empty() {
+            let explicit_tags = &self.tags;
+            tags.retain(|tag| explicit_tags.iter().all(|x| x.name() != tag.name()));
+            self.tags.extend(tags);
+            sort_and_dedup_tags(&mut self.tags);
+        }
+
+        Span::new(
+            self.operation_name,
+            self.start_time.unwrap_or_else(SystemTime::now),
+            self.references,
+            self.tags,
+            state,
+            self.baggage_items,
+            self.sink.clone(),
+        )
+    }
+
+    fn span(&self) -> CandidateSpan<'_, T> {
         CandidateSpan {
+            operation_name: &self.operation_name,
             references: &self.references,
             tags: &self.tags,
             baggage_items: &self.baggage_items,
@@ -617,9 +859,16 @@ where
     }
 }
 
+/// Sorts `tags` by name, keeping only the last added one among the tags with the same name.
+fn sort_and_dedup_tags(tags: &mut Vec<Tag>) {
+    tags.reverse();
+    tags.sort_by(|a, b| a.name().cmp(b.name()));
+    tags.dedup_by(|a, b| a.name() == b.name());
+}
+
 /// Immutable handle of `Span`.
 #[derive(Debug, Clone)]
-pub struct SpanHandle<T>(Option<(SpanContext<T>, SpanSender<T>)>);
+pub struct SpanHandle<T>(Option<(SpanContext<T>, SpanSink<T>)>);
 impl<T> SpanHandle<T> {
     /// Returns `true` if this span is sampled (i.e., being traced).
     pub fn is_sampled(&self) -> bool {
@@ -628,7 +877,16 @@ impl<T> SpanHandle<T> {
 
     /// Returns the context of this span.
     pub fn context(&self) -> Option<&SpanContext<T>> {
-        self.0.as_ref().map(|&(ref context, _)| context)
+        self.0.as_ref().map(|(context, _)| context)
+    }
+
+    pub(crate) fn enable_active_parent(&self)
+    where
+        T: Clone + 'static,
+    {
+        if let Some((_, sink)) = &self.0 {
+            sink.enable_active_parent();
+        }
     }
 
     /// Gets the baggage item that has the name `name`.
@@ -647,9 +905,9 @@ impl<T> SpanHandle<T> {
         T: Clone,
         F: FnOnce(StartSpanOptions<AllSampler, T>) -> Span<T>,
     {
-        if let Some(&(ref context, ref span_tx)) = self.0.as_ref() {
+        if let Some((context, sink)) = self.0.as_ref() {
             let options =
-                StartSpanOptions::new(operation_name, span_tx, &AllSampler).child_of(context);
+                StartSpanOptions::new(operation_name, sink, &AllSampler).child_of(context);
             f(options)
         } else {
             Span::inactive()
@@ -663,9 +921,9 @@ impl<T> SpanHandle<T> {
         T: Clone,
         F: FnOnce(StartSpanOptions<AllSampler, T>) -> Span<T>,
     {
-        if let Some(&(ref context, ref span_tx)) = self.0.as_ref() {
+        if let Some((context, sink)) = self.0.as_ref() {
             let options =
-                StartSpanOptions::new(operation_name, span_tx, &AllSampler).follows_from(context);
+                StartSpanOptions::new(operation_name, sink, &AllSampler).follows_from(context);
             f(options)
         } else {
             Span::inactive()
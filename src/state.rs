@@ -0,0 +1,560 @@
+//! Built-in span context state.
+//!
+//! `SpanContextState` is a ready-made implementation-dependent state for `SpanContext`.
+//! It holds the identifiers needed to refer to a distinct span across process boundaries,
+//! and it can be derived from a `CandidateSpan`, so `StartSpanOptions::start()` works with it.
+//!
+//! # Examples
+//!
+//! ```
+//! use rustracing::sampler::AllSampler;
+//! use rustracing::state::SpanContextState;
+//! use rustracing::Tracer;
+//!
+//! let (span_tx, span_rx) = crossbeam_channel::bounded(10);
+//! let tracer = Tracer::<_, SpanContextState>::with_sender(AllSampler, span_tx);
+//! {
+//!     let parent = tracer.span("parent").start();
+//!     let _child = tracer.span("child").child_of(&parent).start();
+//! }
+//!
+//! let child = span_rx.try_recv().unwrap();
+//! let parent = span_rx.try_recv().unwrap();
+//! assert_eq!(child.context().state().trace_id(), parent.context().state().trace_id());
+//! assert_eq!(
+//!     child.context().state().parent_span_id(),
+//!     Some(parent.context().state().span_id())
+//! );
+//! ```
+use crate::span::{CandidateSpan, SpanReference};
+use crate::{Error, ErrorKind, Result};
+use rand::Rng;
+use std::fmt;
+use std::str::FromStr;
+
+/// 128-bit trace identifier.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct TraceId(u128);
+impl TraceId {
+    /// Makes a new `TraceId` instance.
+    pub fn new(id: u128) -> Self {
+        TraceId(id)
+    }
+
+    /// Returns the value of this identifier.
+    pub fn to_u128(self) -> u128 {
+        self.0
+    }
+
+    /// Returns the upper 64 bits of this identifier.
+    pub fn high(self) -> u64 {
+        (self.0 >> 64) as u64
+    }
+
+    /// Returns the lower 64 bits of this identifier.
+    pub fn low(self) -> u64 {
+        self.0 as u64
+    }
+
+    /// Returns `true` if this identifier is not zero.
+    pub fn is_valid(self) -> bool {
+        self.0 != 0
+    }
+
+    /// Formats this identifier as 16 lowercase hex digits if its upper 64 bits are zero,
+    /// otherwise as 32 digits (the same as `to_string()`).
+    ///
+    /// This is the form used by the formats which support both 64-bit and 128-bit trace identifiers
+    /// (e.g., B3, Jaeger and Zipkin).
+    pub fn to_compact_string(self) -> String {
+        if self.high() == 0 {
+            format!("{:016x}", self.low())
+        } else {
+            self.to_string()
+        }
+    }
+
+    pub(crate) fn random() -> Self {
+        TraceId(random_nonzero(|rng| rng.gen()))
+    }
+}
+impl fmt::Display for TraceId {
+    /// Formats this identifier as 32 lowercase hex digits.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:032x}", self.0)
+    }
+}
+impl FromStr for TraceId {
+    type Err = Error;
+
+    /// Parses a hex string which has up to 32 digits.
+    fn from_str(s: &str) -> Result<Self> {
+        let id = track!(parse_hex(s, 32))?;
+        Ok(TraceId(id))
+    }
+}
+
+/// 64-bit span identifier.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct SpanId(u64);
+impl SpanId {
+    /// Makes a new `SpanId` instance.
+    pub fn new(id: u64) -> Self {
+        SpanId(id)
+    }
+
+    /// Returns the value of this identifier.
+    pub fn to_u64(self) -> u64 {
+        self.0
+    }
+
+    /// Returns `true` if this identifier is not zero.
+    pub fn is_valid(self) -> bool {
+        self.0 != 0
+    }
+
+    pub(crate) fn random() -> Self {
+        SpanId(random_nonzero(|rng| rng.gen()))
+    }
+}
+impl fmt::Display for SpanId {
+    /// Formats this identifier as 16 lowercase hex digits.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+impl FromStr for SpanId {
+    type Err = Error;
+
+    /// Parses a hex string which has up to 16 digits.
+    fn from_str(s: &str) -> Result<Self> {
+        let id = track!(parse_hex(s, 16))?;
+        Ok(SpanId(id as u64))
+    }
+}
+
+/// Built-in span context state.
+///
+/// This consists of a 128-bit trace identifier, a 64-bit span identifier,
+/// the identifier of the parent span (if any), the sampled/debug flags,
+/// the vendor-specific trace state and the debug identifier (if any).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SpanContextState {
+    trace_id: TraceId,
+    span_id: SpanId,
+    parent_span_id: Option<SpanId>,
+    sampled: bool,
+    debug: bool,
+    trace_state: TraceState,
+    debug_id: Option<String>,
+}
+impl SpanContextState {
+    /// Makes a new `SpanContextState` instance.
+    ///
+    /// The resulting state has no parent and is marked as sampled.
+    ///
+    /// This is mainly used to reconstruct a context received from another process.
+    pub fn new(trace_id: TraceId, span_id: SpanId) -> Self {
+        SpanContextState {
+            trace_id,
+            span_id,
+            parent_span_id: None,
+            sampled: true,
+            debug: false,
+            trace_state: TraceState::default(),
+            debug_id: None,
+        }
+    }
+
+    /// Makes a new state which carries only the debug identifier `debug_id`.
+    ///
+    /// The resulting state has no valid identifiers and is marked as debug (and thus sampled).
+    /// A span started as a child of it becomes the root of a new trace
+    /// which inherits the debug identifier.
+    ///
+    /// This is used when a request asks for a debug trace
+    /// (e.g., by the `jaeger-debug-id` header) without continuing an existing one.
+    pub fn debug_root(debug_id: &str) -> Self {
+        let mut state = Self::new(TraceId::default(), SpanId::default());
+        state.set_debug(true);
+        state.debug_id = Some(debug_id.to_owned());
+        state
+    }
+
+    /// Makes a new state which carries only the sampling decision `sampled`.
+    ///
+    /// Like `debug_root`, the resulting state has no valid identifiers.
+    /// A span started as a child of it becomes the root of a new trace which inherits the decision,
+    /// and samplers which respect the parent's decision (e.g., `ParentBasedSampler`) honor it.
+    ///
+    /// This is used when a request only carries a sampling state (e.g., the `b3: 0` header).
+    pub fn sampling_only(sampled: bool) -> Self {
+        let mut state = Self::new(TraceId::default(), SpanId::default());
+        state.set_sampled(sampled);
+        state
+    }
+
+    /// Makes a new state of a root span which has randomly generated identifiers.
+    pub fn root() -> Self {
+        Self::new(TraceId::random(), SpanId::random())
+    }
+
+    /// Returns the trace identifier of this state.
+    pub fn trace_id(&self) -> TraceId {
+        self.trace_id
+    }
+
+    /// Returns the span identifier of this state.
+    pub fn span_id(&self) -> SpanId {
+        self.span_id
+    }
+
+    /// Returns the identifier of the parent span if it exists.
+    pub fn parent_span_id(&self) -> Option<SpanId> {
+        self.parent_span_id
+    }
+
+    /// Returns `true` if the span is sampled.
+    pub fn is_sampled(&self) -> bool {
+        self.sampled
+    }
+
+    /// Returns `true` if the span is marked as debug.
+    pub fn is_debug(&self) -> bool {
+        self.debug
+    }
+
+    /// Returns the vendor-specific trace state.
+    pub fn trace_state(&self) -> &TraceState {
+        &self.trace_state
+    }
+
+    /// Returns the debug identifier if this is (or is the root derived from) a state
+    /// made by `debug_root`.
+    ///
+    /// Exporters may record it on the span (e.g., as the `jaeger-debug-id` tag)
+    /// so that the trace can be searched by the identifier.
+    pub fn debug_id(&self) -> Option<&str> {
+        self.debug_id.as_deref()
+    }
+
+    /// Returns `true` if both the trace and span identifiers are valid (i.e., non-zero).
+    ///
+    /// Invalid states (e.g., made by `debug_root`) cannot be propagated by most formats
+    /// and are not recorded as references by exporters.
+    pub fn is_valid(&self) -> bool {
+        self.trace_id.is_valid() && self.span_id.is_valid()
+    }
+
+    /// Returns `true` if this state only carries a debug identifier (see `debug_root`).
+    pub fn is_debug_root(&self) -> bool {
+        self.debug_id.is_some() && !self.trace_id.is_valid()
+    }
+
+    /// Sets the identifier of the parent span.
+    pub fn set_parent_span_id(&mut self, parent_span_id: Option<SpanId>) {
+        self.parent_span_id = parent_span_id;
+    }
+
+    /// Sets the sampled flag.
+    pub fn set_sampled(&mut self, sampled: bool) {
+        self.sampled = sampled;
+    }
+
+    /// Sets the debug flag.
+    ///
+    /// Debug spans are always regarded as sampled.
+    pub fn set_debug(&mut self, debug: bool) {
+        self.debug = debug;
+        if debug {
+            self.sampled = true;
+        }
+    }
+
+    /// Sets the vendor-specific trace state.
+    pub fn set_trace_state(&mut self, trace_state: TraceState) {
+        self.trace_state = trace_state;
+    }
+}
+impl<'a> From<CandidateSpan<'a, SpanContextState>> for SpanContextState {
+    /// Derives the state of a new span.
+    ///
+    /// The first `ChildOf` reference (or the first `FollowsFrom` one if there are no `ChildOf` references)
+    /// is used as the parent. If there are no references, a new trace is started.
+    ///
+    /// If the parent has no valid identifiers (see `SpanContextState::debug_root` and `SpanContextState::sampling_only`),
+    /// a new trace is started and the sampled flag, the debug flag and the debug identifier are inherited.
+    fn from(span: CandidateSpan<'a, SpanContextState>) -> Self {
+        let parent = span
+            .references()
+            .iter()
+            .find(|r| r.is_child_of())
+            .or_else(|| span.references().first())
+            .map(SpanReference::span);
+        match parent {
+            Some(parent) if !parent.is_valid() => {
+                let mut state = SpanContextState::root();
+                state.sampled = parent.sampled;
+                state.debug = parent.debug;
+                state.debug_id = parent.debug_id.clone();
+                state
+            }
+            Some(parent) => SpanContextState {
+                trace_id: parent.trace_id,
+                span_id: SpanId::random(),
+                parent_span_id: Some(parent.span_id),
+                sampled: true,
+                debug: parent.debug,
+                trace_state: parent.trace_state.clone(),
+                debug_id: None,
+            },
+            None => SpanContextState::root(),
+        }
+    }
+}
+
+/// Vendor-specific trace state.
+///
+/// This is the list of key/value pairs carried by the [W3C `tracestate`][tracestate] header.
+/// The entries are kept in order, the most recently updated one comes first.
+///
+/// # Examples
+///
+/// ```
+/// use rustracing::state::TraceState;
+///
+/// let mut state: TraceState = "rojo=00f067aa0ba902b7, congo=t61rcWkgMzE".parse().unwrap();
+/// assert_eq!(state.get("congo"), Some("t61rcWkgMzE"));
+///
+/// state.insert("congo", "ucfJifl5GOE").unwrap();
+/// assert_eq!(state.to_string(), "congo=ucfJifl5GOE,rojo=00f067aa0ba902b7");
+/// ```
+///
+/// [tracestate]: https://www.w3.org/TR/trace-context/#tracestate-header
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct TraceState {
+    entries: Vec<(String, String)>,
+}
+impl TraceState {
+    /// The maximum number of entries.
+    pub const MAX_ENTRIES: usize = 32;
+
+    /// Makes a new empty `TraceState` instance.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the entries of this trace state.
+    pub fn entries(&self) -> &[(String, String)] {
+        &self.entries
+    }
+
+    /// Returns `true` if this trace state has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Gets the value associated with `key`.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|x| x.0 == key)
+            .map(|x| x.1.as_str())
+    }
+
+    /// Inserts the entry to the front of this trace state.
+    ///
+    /// If there is an existing entry with the same key, it will be replaced.
+    /// If the number of entries exceeds `MAX_ENTRIES`, the last entry will be removed.
+    ///
+    /// # Errors
+    ///
+    /// If `key` or `value` is malformed,
+    /// it will return an error with the kind `ErrorKind::InvalidInput`.
+    pub fn insert(&mut self, key: &str, value: &str) -> Result<()> {
+        track_assert!(is_valid_trace_state_key(key), ErrorKind::InvalidInput; key);
+        track_assert!(is_valid_trace_state_value(value), ErrorKind::InvalidInput; value);
+        self.entries.retain(|x| x.0 != key);
+        self.entries.insert(0, (key.to_owned(), value.to_owned()));
+        self.entries.truncate(Self::MAX_ENTRIES);
+        Ok(())
+    }
+
+    /// Removes the entry associated with `key`.
+    pub fn remove(&mut self, key: &str) -> Option<String> {
+        let i = self.entries.iter().position(|x| x.0 == key)?;
+        Some(self.entries.remove(i).1)
+    }
+}
+impl fmt::Display for TraceState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, (key, value)) in self.entries.iter().enumerate() {
+            if i != 0 {
+                write!(f, ",")?;
+            }
+            write!(f, "{}={}", key, value)?;
+        }
+        Ok(())
+    }
+}
+impl FromStr for TraceState {
+    type Err = Error;
+
+    /// Parses a comma separated list of `key=value` pairs.
+    ///
+    /// Empty list members are ignored.
+    fn from_str(s: &str) -> Result<Self> {
+        let mut entries: Vec<(String, String)> = Vec::new();
+        for member in s.split(',') {
+            let member = member.trim_matches(|c| c == ' ' || c == '\t');
+            if member.is_empty() {
+                continue;
+            }
+            let mut kv = member.splitn(2, '=');
+            let key = kv.next().expect("never fails");
+            let value = track_assert_some!(kv.next(), ErrorKind::InvalidInput; member);
+            track_assert!(is_valid_trace_state_key(key), ErrorKind::InvalidInput; key);
+            track_assert!(is_valid_trace_state_value(value), ErrorKind::InvalidInput; value);
+            track_assert!(
+                entries.iter().all(|x| x.0 != key),
+                ErrorKind::InvalidInput,
+                "Duplicate key: {:?}",
+                key
+            );
+            entries.push((key.to_owned(), value.to_owned()));
+        }
+        track_assert!(entries.len() <= Self::MAX_ENTRIES, ErrorKind::InvalidInput; entries.len());
+        Ok(TraceState { entries })
+    }
+}
+
+fn is_valid_trace_state_key(key: &str) -> bool {
+    fn is_key_char(b: u8) -> bool {
+        matches!(b, b'a'..=b'z' | b'0'..=b'9' | b'_' | b'-' | b'*' | b'/')
+    }
+
+    let mut parts = key.splitn(2, '@');
+    let tenant = parts.next().expect("never fails");
+    if let Some(system) = parts.next() {
+        // multi-tenant key: `tenant@system`
+        !tenant.is_empty()
+            && tenant.len() <= 241
+            && tenant.bytes().all(is_key_char)
+            && !system.is_empty()
+            && system.len() <= 14
+            && system.as_bytes()[0].is_ascii_lowercase()
+            && system.bytes().all(is_key_char)
+    } else {
+        !key.is_empty()
+            && key.len() <= 256
+            && key.as_bytes()[0].is_ascii_lowercase()
+            && key.bytes().all(is_key_char)
+    }
+}
+
+fn is_valid_trace_state_value(value: &str) -> bool {
+    !value.is_empty()
+        && value.len() <= 256
+        && !value.ends_with(' ')
+        && value
+            .bytes()
+            .all(|b| (0x20..=0x7e).contains(&b) && b != b',' && b != b'=')
+}
+
+fn random_nonzero<T, F>(f: F) -> T
+where
+    T: Default + PartialEq,
+    F: Fn(&mut rand::rngs::ThreadRng) -> T,
+{
+    let mut rng = rand::thread_rng();
+    loop {
+        let id = f(&mut rng);
+        if id != T::default() {
+            return id;
+        }
+    }
+}
+
+fn parse_hex(s: &str, max_digits: usize) -> Result<u128> {
+    track_assert!(!s.is_empty(), ErrorKind::InvalidInput);
+    track_assert!(s.len() <= max_digits, ErrorKind::InvalidInput; s);
+    track_assert!(
+        s.bytes().all(|b| b.is_ascii_hexdigit()),
+        ErrorKind::InvalidInput;
+        s
+    );
+    let id = u128::from_str_radix(s, 16).expect("never fails");
+    Ok(id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::AllSampler;
+    use crate::Tracer;
+
+    #[test]
+    fn id_format_works() {
+        let trace_id = TraceId::new(0x0af7651916cd43dd8448eb211c80319c);
+        assert_eq!(trace_id.to_string(), "0af7651916cd43dd8448eb211c80319c");
+        assert_eq!(trace_id.high(), 0x0af7651916cd43dd);
+        assert_eq!(trace_id.low(), 0x8448eb211c80319c);
+        assert_eq!(trace_id.to_string().parse::<TraceId>().unwrap(), trace_id);
+        assert_eq!("1".parse::<TraceId>().unwrap(), TraceId::new(1));
+        assert_eq!(
+            trace_id.to_compact_string(),
+            "0af7651916cd43dd8448eb211c80319c"
+        );
+        assert_eq!(TraceId::new(0xabc).to_compact_string(), "0000000000000abc");
+
+        let span_id = SpanId::new(0xb7ad6b7169203331);
+        assert_eq!(span_id.to_string(), "b7ad6b7169203331");
+        assert_eq!(span_id.to_string().parse::<SpanId>().unwrap(), span_id);
+
+        assert!("".parse::<SpanId>().is_err());
+        assert!("xyz".parse::<SpanId>().is_err());
+        assert!("00000000000000001".parse::<SpanId>().is_err());
+    }
+
+    #[test]
+    fn trace_state_works() {
+        let state: TraceState = "foo=1,, bar@baz=2 ,\tqux=a b".parse().unwrap();
+        assert_eq!(state.entries().len(), 3);
+        assert_eq!(state.get("bar@baz"), Some("2"));
+        assert_eq!(state.to_string(), "foo=1,bar@baz=2,qux=a b");
+
+        assert!("foo".parse::<TraceState>().is_err());
+        assert!("Foo=1".parse::<TraceState>().is_err());
+        assert!("foo=1,foo=2".parse::<TraceState>().is_err());
+        assert!("foo=a,b=".parse::<TraceState>().is_err());
+
+        let too_many = (0..33)
+            .map(|i| format!("k{}=v", i))
+            .collect::<Vec<_>>()
+            .join(",");
+        assert!(too_many.parse::<TraceState>().is_err());
+    }
+
+    #[test]
+    fn state_derived_from_references() {
+        let (span_tx, span_rx) = crossbeam_channel::bounded(10);
+        let tracer = Tracer::with_sender(AllSampler, span_tx);
+        {
+            let root = tracer.span("root").start();
+            let _follower = tracer.span("follower").follows_from(&root).start();
+        }
+        let follower = span_rx.try_recv().unwrap();
+        let root = span_rx.try_recv().unwrap();
+
+        let root: &SpanContextState = root.context().state();
+        assert!(root.trace_id().is_valid());
+        assert!(root.span_id().is_valid());
+        assert_eq!(root.parent_span_id(), None);
+        assert!(root.is_sampled());
+
+        let follower = follower.context().state();
+        assert_eq!(follower.trace_id(), root.trace_id());
+        assert_eq!(follower.parent_span_id(), Some(root.span_id()));
+        assert_ne!(follower.span_id(), root.span_id());
+    }
+}
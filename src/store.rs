@@ -0,0 +1,329 @@
+//! In-memory span store.
+use crate::exporter::Exporter;
+use crate::span::{FinishedSpan, SpanReceiver};
+use crate::state::{SpanContextState, TraceId};
+use crate::tag::TagValue;
+use crate::Result;
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+/// The default capacity of `SpanStore`.
+pub const DEFAULT_SPAN_STORE_CAPACITY: usize = 1024;
+
+/// Bounded in-memory store of the most recently finished spans.
+///
+/// When the store is full, the oldest span is evicted to make room for a new one.
+///
+/// `SpanStore` is a cheaply cloneable handle to the shared buffer,
+/// so it can be passed to `ReporterBuilder` as an exporter while being queried elsewhere
+/// (e.g., by a debugging endpoint).
+///
+/// # Examples
+///
+/// ```
+/// use rustracing::sampler::AllSampler;
+/// use rustracing::state::SpanContextState;
+/// use rustracing::store::{SpanQuery, SpanStore};
+/// use rustracing::tag::StdTag;
+/// use rustracing::Tracer;
+///
+/// let (span_tx, span_rx) = crossbeam_channel::bounded(10);
+/// let tracer = Tracer::with_sender(AllSampler, span_tx);
+/// {
+///     let _foo = tracer.span("foo").start_with_state(SpanContextState::root());
+///     let _bar = tracer
+///         .span("bar")
+///         .tag(StdTag::error())
+///         .start_with_state(SpanContextState::root());
+/// }
+///
+/// let store = SpanStore::new(100);
+/// store.receive(&span_rx);
+/// assert_eq!(store.len(), 2);
+///
+/// let spans = store.query(&SpanQuery::new().error(true));
+/// assert_eq!(spans.len(), 1);
+/// assert_eq!(spans[0].operation_name(), "bar");
+/// ```
+#[derive(Debug, Clone)]
+pub struct SpanStore {
+    spans: Arc<Mutex<VecDeque<FinishedSpan<SpanContextState>>>>,
+    capacity: usize,
+}
+impl SpanStore {
+    /// Makes a new `SpanStore` instance which keeps at most `capacity` spans.
+    ///
+    /// A value of `0` is treated as `1`.
+    pub fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        SpanStore {
+            spans: Arc::new(Mutex::new(VecDeque::with_capacity(capacity))),
+            capacity,
+        }
+    }
+
+    /// Returns the maximum number of spans kept by this store.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Returns the number of spans in this store.
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// Returns `true` if this store has no spans, otherwise `false`.
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    /// Adds `span` to this store.
+    ///
+    /// If the store is full, the oldest span is evicted.
+    pub fn push(&self, span: FinishedSpan<SpanContextState>) {
+        let mut spans = self.lock();
+        if spans.len() == self.capacity {
+            spans.pop_front();
+        }
+        spans.push_back(span);
+    }
+
+    /// Adds all the spans which have been received by `span_rx` to this store.
+    ///
+    /// It returns the number of the received spans.
+    pub fn receive(&self, span_rx: &SpanReceiver<SpanContextState>) -> usize {
+        let mut count = 0;
+        while let Ok(span) = span_rx.try_recv() {
+            self.push(span);
+            count += 1;
+        }
+        count
+    }
+
+    /// Returns the clones of all the spans in this store, from the oldest to the newest.
+    pub fn spans(&self) -> Vec<FinishedSpan<SpanContextState>> {
+        self.lock().iter().cloned().collect()
+    }
+
+    /// Returns the clones of the spans matching `query`, from the oldest to the newest.
+    pub fn query(&self, query: &SpanQuery) -> Vec<FinishedSpan<SpanContextState>> {
+        self.lock()
+            .iter()
+            .filter(|span| query.matches(span))
+            .cloned()
+            .collect()
+    }
+
+    /// Removes all the spans from this store.
+    pub fn clear(&self) {
+        self.lock().clear();
+    }
+
+    fn lock(&self) -> MutexGuard<'_, VecDeque<FinishedSpan<SpanContextState>>> {
+        self.spans
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+impl Default for SpanStore {
+    fn default() -> Self {
+        Self::new(DEFAULT_SPAN_STORE_CAPACITY)
+    }
+}
+impl Exporter<SpanContextState> for SpanStore {
+    fn export(&mut self, spans: Vec<FinishedSpan<SpanContextState>>) -> Result<()> {
+        for span in spans {
+            self.push(span);
+        }
+        Ok(())
+    }
+}
+
+/// Query of `SpanStore`.
+///
+/// A span matches a query if it satisfies all the conditions of the query.
+/// An empty query matches all the spans.
+#[derive(Debug, Clone, Default)]
+pub struct SpanQuery {
+    operation_name: Option<Cow<'static, str>>,
+    tags: Vec<(Cow<'static, str>, TagValue)>,
+    min_duration: Option<Duration>,
+    error: Option<bool>,
+    trace_id: Option<TraceId>,
+}
+impl SpanQuery {
+    /// Makes a new `SpanQuery` instance which matches all the spans.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Restricts the matching spans to the ones whose operation name is `name`.
+    pub fn operation_name<N>(mut self, name: N) -> Self
+    where
+        N: Into<Cow<'static, str>>,
+    {
+        self.operation_name = Some(name.into());
+        self
+    }
+
+    /// Restricts the matching spans to the ones which have the tag `name` whose value is `value`.
+    ///
+    /// This can be called multiple times to require multiple tags.
+    pub fn tag<N, V>(mut self, name: N, value: V) -> Self
+    where
+        N: Into<Cow<'static, str>>,
+        V: Into<TagValue>,
+    {
+        self.tags.push((name.into(), value.into()));
+        self
+    }
+
+    /// Restricts the matching spans to the ones which took `duration` or longer.
+    pub fn min_duration(mut self, duration: Duration) -> Self {
+        self.min_duration = Some(duration);
+        self
+    }
+
+    /// Restricts the matching spans by the error status.
+    ///
+    /// A span is regarded as an error if it has the tag `error=true` (see `StdTag::error`).
+    pub fn error(mut self, error: bool) -> Self {
+        self.error = Some(error);
+        self
+    }
+
+    /// Restricts the matching spans to the ones which belong to the trace `trace_id`.
+    pub fn trace_id(mut self, trace_id: TraceId) -> Self {
+        self.trace_id = Some(trace_id);
+        self
+    }
+
+    /// Returns `true` if `span` matches this query, otherwise `false`.
+    pub fn matches(&self, span: &FinishedSpan<SpanContextState>) -> bool {
+        if let Some(name) = &self.operation_name {
+            if span.operation_name() != name {
+                return false;
+            }
+        }
+        let has_tag = |name: &str, value: &TagValue| {
+            span.tags()
+                .iter()
+                .any(|t| t.name() == name && t.value() == value)
+        };
+        if !self.tags.iter().all(|(name, value)| has_tag(name, value)) {
+            return false;
+        }
+        if let Some(min) = self.min_duration {
+            let duration = span
+                .finish_time()
+                .duration_since(span.start_time())
+                .unwrap_or_default();
+            if duration < min {
+                return false;
+            }
+        }
+        if let Some(error) = self.error {
+            if has_tag("error", &TagValue::Boolean(true)) != error {
+                return false;
+            }
+        }
+        if let Some(trace_id) = self.trace_id {
+            if span.context().state().trace_id() != trace_id {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::AllSampler;
+    use crate::state::SpanId;
+    use crate::tag::{StdTag, Tag};
+    use crate::Tracer;
+    use std::time::UNIX_EPOCH;
+
+    #[test]
+    fn store_evicts_oldest_spans() {
+        let (span_tx, span_rx) = crossbeam_channel::unbounded();
+        let tracer = Tracer::with_sender(AllSampler, span_tx);
+        for name in &["a", "b", "c"] {
+            let _span = tracer
+                .span(*name)
+                .start_with_state(SpanContextState::root());
+        }
+
+        let store = SpanStore::new(2);
+        assert_eq!(store.receive(&span_rx), 3);
+        let names = store
+            .spans()
+            .iter()
+            .map(|s| s.operation_name().to_owned())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["b", "c"]);
+
+        store.clear();
+        assert!(store.is_empty());
+    }
+
+    #[test]
+    fn query_works() {
+        let (span_tx, span_rx) = crossbeam_channel::unbounded();
+        let tracer = Tracer::with_sender(AllSampler, span_tx);
+        let specs = [
+            ("foo", 1, 10, false),
+            ("foo", 2, 30, true),
+            ("bar", 1, 50, false),
+        ];
+        for &(name, trace_id, millis, error) in &specs {
+            let mut span = tracer
+                .span(name)
+                .start_time(UNIX_EPOCH)
+                .tag(Tag::new("millis", millis as i64))
+                .start_with_state(SpanContextState::new(
+                    TraceId::new(trace_id),
+                    SpanId::new(1),
+                ));
+            if error {
+                span.set_tag(StdTag::error);
+            }
+            span.set_finish_time(|| UNIX_EPOCH + Duration::from_millis(millis));
+        }
+        let mut store = SpanStore::default();
+        store.export(span_rx.try_iter().collect()).unwrap();
+
+        let millis = |query: SpanQuery| {
+            store
+                .query(&query)
+                .iter()
+                .map(|s| {
+                    s.finish_time()
+                        .duration_since(UNIX_EPOCH)
+                        .unwrap()
+                        .as_millis()
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(millis(SpanQuery::new()), [10, 30, 50]);
+        assert_eq!(millis(SpanQuery::new().operation_name("foo")), [10, 30]);
+        assert_eq!(millis(SpanQuery::new().tag("millis", 30i64)), [30]);
+        assert_eq!(
+            millis(SpanQuery::new().min_duration(Duration::from_millis(30))),
+            [30, 50]
+        );
+        assert_eq!(millis(SpanQuery::new().error(true)), [30]);
+        assert_eq!(millis(SpanQuery::new().error(false)), [10, 50]);
+        assert_eq!(
+            millis(
+                SpanQuery::new()
+                    .trace_id(TraceId::new(1))
+                    .operation_name("bar")
+            ),
+            [50]
+        );
+    }
+}
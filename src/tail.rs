@@ -0,0 +1,720 @@
+//! Tail-based trace sampling.
+//!
+//! Unlike `Sampler`s which decide at the start of a trace,
+//! `TailSampler` decides whether a trace should be kept after its spans have finished,
+//! so it can keep, for example, every trace that contains an error.
+//!
+//! `TailSampler` sits between a `SpanReceiver` and the downstream consumer (e.g., `Reporter`).
+//! It buffers the finished spans per trace until the root span finishes or a timeout fires,
+//! then evaluates the policies and forwards or discards the whole trace.
+//!
+//! # Examples
+//!
+//! ```
+//! use rustracing::sampler::AllSampler;
+//! use rustracing::state::SpanContextState;
+//! use rustracing::tag::StdTag;
+//! use rustracing::tail::{ErrorPolicy, LatencyPolicy, TailSamplerBuilder};
+//! use rustracing::Tracer;
+//! use std::time::Duration;
+//!
+//! let (span_tx, span_rx) = crossbeam_channel::bounded(10);
+//! let (sampled_tx, sampled_rx) = crossbeam_channel::bounded(10);
+//! let tracer = Tracer::<_, SpanContextState>::with_sender(AllSampler, span_tx);
+//! let sampler = TailSamplerBuilder::new(span_rx, sampled_tx)
+//!     .policy(ErrorPolicy)
+//!     .policy(LatencyPolicy::new(Duration::from_secs(1)))
+//!     .spawn();
+//! {
+//!     let _ok = tracer.span("ok").start();
+//!     let _failed = tracer.span("failed").tag(StdTag::error()).start();
+//! }
+//! sampler.flush().unwrap();
+//!
+//! let span = sampled_rx.try_recv().unwrap();
+//! assert_eq!(span.operation_name(), "failed");
+//! assert!(sampled_rx.try_recv().is_err());
+//! ```
+use crate::span::{FinishedSpan, SpanReceiver, SpanSender};
+use crate::state::{SpanContextState, TraceId};
+use crate::tag::TagValue;
+use crate::{Error, ErrorKind, Result};
+use crossbeam_channel::{self as channel, Receiver, RecvTimeoutError, Sender};
+use std::borrow::Cow;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use trackable::error::ErrorKindExt;
+
+/// The default value of the maximum time to wait for the root span of a trace.
+pub const DEFAULT_DECISION_WAIT: Duration = Duration::from_secs(10);
+
+/// The default value of the maximum number of traces buffered at once.
+pub const DEFAULT_MAX_TRACES: usize = 10_000;
+
+/// The default value of the maximum number of spans buffered per trace.
+pub const DEFAULT_MAX_SPANS_PER_TRACE: usize = 1000;
+
+/// `TracePolicy` decides whether a finished trace should be kept or not.
+pub trait TracePolicy: Send {
+    /// Returns `true` if the trace consisting of `spans` should be kept.
+    fn is_kept(&self, spans: &[FinishedSpan<SpanContextState>]) -> bool;
+}
+impl<F> TracePolicy for F
+where
+    F: Fn(&[FinishedSpan<SpanContextState>]) -> bool + Send,
+{
+    fn is_kept(&self, spans: &[FinishedSpan<SpanContextState>]) -> bool {
+        self(spans)
+    }
+}
+
+/// This keeps traces which have one or more spans tagged with `error=true`.
+#[derive(Debug, Clone)]
+pub struct ErrorPolicy;
+impl TracePolicy for ErrorPolicy {
+    fn is_kept(&self, spans: &[FinishedSpan<SpanContextState>]) -> bool {
+        let error = TagValue::Boolean(true);
+        spans.iter().any(|s| {
+            s.tags()
+                .iter()
+                .any(|t| t.name() == "error" && *t.value() == error)
+        })
+    }
+}
+
+/// This keeps traces which took a certain duration or longer.
+///
+/// The duration of a trace is measured from the earliest start time to the latest finish time of its spans.
+#[derive(Debug, Clone)]
+pub struct LatencyPolicy {
+    threshold: Duration,
+}
+impl LatencyPolicy {
+    /// Makes a new `LatencyPolicy` instance.
+    pub fn new(threshold: Duration) -> Self {
+        LatencyPolicy { threshold }
+    }
+}
+impl TracePolicy for LatencyPolicy {
+    fn is_kept(&self, spans: &[FinishedSpan<SpanContextState>]) -> bool {
+        let start = spans.iter().map(|s| s.start_time()).min();
+        let finish = spans.iter().map(|s| s.finish_time()).max();
+        match (start, finish) {
+            (Some(start), Some(finish)) => finish
+                .duration_since(start)
+                .is_ok_and(|d| d >= self.threshold),
+            _ => false,
+        }
+    }
+}
+
+/// This keeps traces which have one or more spans with a certain tag.
+#[derive(Debug, Clone)]
+pub struct TagPolicy {
+    name: Cow<'static, str>,
+    value: TagValue,
+}
+impl TagPolicy {
+    /// Makes a new `TagPolicy` instance which keeps traces having the tag `name` whose value is `value`.
+    pub fn new<N, V>(name: N, value: V) -> Self
+    where
+        N: Into<Cow<'static, str>>,
+        V: Into<TagValue>,
+    {
+        TagPolicy {
+            name: name.into(),
+            value: value.into(),
+        }
+    }
+}
+impl TracePolicy for TagPolicy {
+    fn is_kept(&self, spans: &[FinishedSpan<SpanContextState>]) -> bool {
+        spans.iter().any(|s| {
+            s.tags()
+                .iter()
+                .any(|t| t.name() == self.name && *t.value() == self.value)
+        })
+    }
+}
+
+/// This keeps a certain percentage of traces.
+///
+/// The decision is derived from the trace identifier,
+/// so every process using the same sampling rate makes the same decision for a trace.
+#[derive(Debug, Clone)]
+pub struct ProbabilisticPolicy {
+    sampling_rate: f64,
+}
+impl ProbabilisticPolicy {
+    /// Makes a new `ProbabilisticPolicy` instance.
+    ///
+    /// # Errors
+    ///
+    /// If `sampling_rate` is not in the range `0.0...1.0`,
+    /// it will return an error with the kind `ErrorKind::InvalidInput`.
+    pub fn new(sampling_rate: f64) -> Result<Self> {
+        track_assert!(0.0 <= sampling_rate, ErrorKind::InvalidInput);
+        track_assert!(sampling_rate <= 1.0, ErrorKind::InvalidInput);
+        Ok(ProbabilisticPolicy { sampling_rate })
+    }
+
+    fn is_kept_trace(&self, trace_id: TraceId) -> bool {
+        (trace_id.low() as f64 / u64::MAX as f64) < self.sampling_rate
+    }
+}
+impl TracePolicy for ProbabilisticPolicy {
+    fn is_kept(&self, spans: &[FinishedSpan<SpanContextState>]) -> bool {
+        spans
+            .first()
+            .is_some_and(|s| self.is_kept_trace(s.context().state().trace_id()))
+    }
+}
+
+/// `TailSampler` builder.
+pub struct TailSamplerBuilder {
+    span_rx: SpanReceiver<SpanContextState>,
+    span_tx: SpanSender<SpanContextState>,
+    policies: Vec<Box<dyn TracePolicy>>,
+    decision_wait: Duration,
+    max_traces: usize,
+    max_spans_per_trace: usize,
+}
+impl TailSamplerBuilder {
+    /// Makes a new `TailSamplerBuilder` instance.
+    ///
+    /// The spans received from `span_rx` are forwarded to `span_tx` if their traces are kept.
+    pub fn new(
+        span_rx: SpanReceiver<SpanContextState>,
+        span_tx: SpanSender<SpanContextState>,
+    ) -> Self {
+        TailSamplerBuilder {
+            span_rx,
+            span_tx,
+            policies: Vec::new(),
+            decision_wait: DEFAULT_DECISION_WAIT,
+            max_traces: DEFAULT_MAX_TRACES,
+            max_spans_per_trace: DEFAULT_MAX_SPANS_PER_TRACE,
+        }
+    }
+
+    /// Adds a policy.
+    ///
+    /// A trace is kept if any of the policies decides to keep it.
+    /// If no policies are added, all traces are kept.
+    pub fn policy<P>(mut self, policy: P) -> Self
+    where
+        P: TracePolicy + 'static,
+    {
+        self.policies.push(Box::new(policy));
+        self
+    }
+
+    /// Sets the maximum time to wait for the root span of a trace.
+    ///
+    /// The time is measured from when the first span of the trace is received.
+    /// After that, the decision is made with the spans received so far.
+    ///
+    /// Note that a root span is a span which has no parent.
+    /// So the traces continuing an upstream trace (i.e., the ones whose local root spans have remote parents)
+    /// always wait for the full `decision_wait` (or until they are evicted by `max_traces` or `max_spans_per_trace`).
+    ///
+    /// The default value is `DEFAULT_DECISION_WAIT`.
+    pub fn decision_wait(mut self, wait: Duration) -> Self {
+        self.decision_wait = wait;
+        self
+    }
+
+    /// Sets the maximum number of traces buffered at once.
+    ///
+    /// If the limit is exceeded, the decision for the oldest trace is made immediately.
+    ///
+    /// The default value is `DEFAULT_MAX_TRACES`.
+    /// A value of `0` is treated as `1`.
+    pub fn max_traces(mut self, n: usize) -> Self {
+        self.max_traces = n.max(1);
+        self
+    }
+
+    /// Sets the maximum number of spans buffered per trace.
+    ///
+    /// If a trace reaches the limit, the decision for it is made immediately.
+    ///
+    /// The default value is `DEFAULT_MAX_SPANS_PER_TRACE`.
+    /// A value of `0` is treated as `1`.
+    pub fn max_spans_per_trace(mut self, n: usize) -> Self {
+        self.max_spans_per_trace = n.max(1);
+        self
+    }
+
+    /// Spawns a background thread that samples the spans received from `span_rx`,
+    /// and returns the handle of it.
+    pub fn spawn(self) -> TailSampler {
+        let (command_tx, command_rx) = channel::unbounded();
+        let counters = Arc::new(Counters::default());
+        let worker = self.into_worker(command_rx, Arc::clone(&counters));
+        let handle = thread::spawn(move || worker.run());
+        TailSampler {
+            command_tx,
+            counters,
+            handle: Some(handle),
+        }
+    }
+
+    fn into_worker(self, command_rx: Receiver<Command>, counters: Arc<Counters>) -> Worker {
+        Worker {
+            span_rx: self.span_rx,
+            span_tx: self.span_tx,
+            command_rx,
+            policies: self.policies,
+            decision_wait: self.decision_wait,
+            max_traces: self.max_traces,
+            max_spans_per_trace: self.max_spans_per_trace,
+            pending: HashMap::new(),
+            deadlines: VecDeque::new(),
+            decided: HashMap::new(),
+            decided_order: VecDeque::new(),
+            counters,
+        }
+    }
+}
+impl fmt::Debug for TailSamplerBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TailSamplerBuilder")
+            .field("policies", &self.policies.len())
+            .field("decision_wait", &self.decision_wait)
+            .field("max_traces", &self.max_traces)
+            .field("max_spans_per_trace", &self.max_spans_per_trace)
+            .finish()
+    }
+}
+
+/// Tail-based sampler which runs on a background thread.
+///
+/// The background thread stops when all the corresponding `SpanSender`s are dropped,
+/// or when `shutdown` is called.
+/// In both cases, the decisions for the buffered traces are made before the thread stops.
+#[derive(Debug)]
+pub struct TailSampler {
+    command_tx: Sender<Command>,
+    counters: Arc<Counters>,
+    handle: Option<JoinHandle<()>>,
+}
+impl TailSampler {
+    /// Makes the decisions for all the traces received so far without waiting for their root spans.
+    ///
+    /// This method blocks until the decisions are made.
+    pub fn flush(&self) -> Result<()> {
+        let (reply_tx, reply_rx) = channel::bounded(1);
+        track!(self
+            .command_tx
+            .send(Command::Flush(reply_tx))
+            .map_err(|_| Error::from(ErrorKind::Other.cause("TailSampler thread has stopped"))))?;
+        track!(reply_rx
+            .recv()
+            .map_err(|_| Error::from(ErrorKind::Other.cause("TailSampler thread has stopped"))))
+    }
+
+    /// Makes the decisions for all the buffered traces and stops the background thread.
+    ///
+    /// If the shutdown does not complete within `timeout`,
+    /// this method returns an error with the kind `ErrorKind::Other`.
+    pub fn shutdown(mut self, timeout: Duration) -> Result<()> {
+        let (reply_tx, reply_rx) = channel::bounded(1);
+        let handle = self.handle.take().expect("never fails");
+        if self.command_tx.send(Command::Shutdown(reply_tx)).is_ok() {
+            match reply_rx.recv_timeout(timeout) {
+                Ok(()) | Err(RecvTimeoutError::Disconnected) => {}
+                Err(RecvTimeoutError::Timeout) => {
+                    track_panic!(ErrorKind::Other, "Shutdown timed out: {:?}", timeout)
+                }
+            }
+        }
+        track_assert!(
+            handle.join().is_ok(),
+            ErrorKind::Other,
+            "TailSampler thread panicked"
+        );
+        Ok(())
+    }
+
+    /// Returns the statistics of this sampler.
+    pub fn stats(&self) -> TailSamplerStats {
+        self.counters.snapshot()
+    }
+}
+impl Drop for TailSampler {
+    fn drop(&mut self) {
+        if self.handle.is_some() {
+            let (reply_tx, _) = channel::bounded(1);
+            let _ = self.command_tx.send(Command::Shutdown(reply_tx));
+        }
+    }
+}
+
+/// Statistics of the traces processed by a `TailSampler`.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+pub struct TailSamplerStats {
+    kept_traces: u64,
+    discarded_traces: u64,
+    late_spans: u64,
+    dropped_spans: u64,
+}
+impl TailSamplerStats {
+    /// Returns the number of the traces kept by the policies.
+    pub fn kept_traces(&self) -> u64 {
+        self.kept_traces
+    }
+
+    /// Returns the number of the traces discarded by the policies.
+    pub fn discarded_traces(&self) -> u64 {
+        self.discarded_traces
+    }
+
+    /// Returns the number of the spans received after the decisions for their traces were made.
+    ///
+    /// Such spans follow the decisions if they are still remembered.
+    pub fn late_spans(&self) -> u64 {
+        self.late_spans
+    }
+
+    /// Returns the number of the kept spans dropped because the output channel was full or disconnected.
+    pub fn dropped_spans(&self) -> u64 {
+        self.dropped_spans
+    }
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    kept_traces: AtomicU64,
+    discarded_traces: AtomicU64,
+    late_spans: AtomicU64,
+    dropped_spans: AtomicU64,
+}
+impl Counters {
+    fn snapshot(&self) -> TailSamplerStats {
+        TailSamplerStats {
+            kept_traces: self.kept_traces.load(Ordering::Relaxed),
+            discarded_traces: self.discarded_traces.load(Ordering::Relaxed),
+            late_spans: self.late_spans.load(Ordering::Relaxed),
+            dropped_spans: self.dropped_spans.load(Ordering::Relaxed),
+        }
+    }
+}
+
+enum Command {
+    Flush(Sender<()>),
+    Shutdown(Sender<()>),
+}
+
+struct PendingTrace {
+    spans: Vec<FinishedSpan<SpanContextState>>,
+    deadline: Instant,
+}
+
+struct Worker {
+    span_rx: SpanReceiver<SpanContextState>,
+    span_tx: SpanSender<SpanContextState>,
+    command_rx: Receiver<Command>,
+    policies: Vec<Box<dyn TracePolicy>>,
+    decision_wait: Duration,
+    max_traces: usize,
+    max_spans_per_trace: usize,
+    pending: HashMap<TraceId, PendingTrace>,
+    deadlines: VecDeque<(Instant, TraceId)>,
+    decided: HashMap<TraceId, bool>,
+    decided_order: VecDeque<TraceId>,
+    counters: Arc<Counters>,
+}
+impl Worker {
+    fn run(mut self) {
+        loop {
+            let timer = self
+                .deadlines
+                .front()
+                .map_or_else(channel::never, |&(deadline, _)| channel::at(deadline));
+            channel::select! {
+                recv(self.span_rx) -> span => match span {
+                    Ok(span) => self.push(span),
+                    Err(_) => {
+                        self.decide_all();
+                        return;
+                    }
+                },
+                recv(self.command_rx) -> command => match command {
+                    Ok(Command::Flush(reply_tx)) => {
+                        self.drain();
+                        self.decide_all();
+                        let _ = reply_tx.send(());
+                    }
+                    Ok(Command::Shutdown(reply_tx)) => {
+                        self.drain();
+                        self.decide_all();
+                        let _ = reply_tx.send(());
+                        return;
+                    }
+                    Err(_) => {
+                        self.decide_all();
+                        return;
+                    }
+                },
+                recv(timer) -> _ => self.expire(Instant::now()),
+            }
+        }
+    }
+
+    fn push(&mut self, span: FinishedSpan<SpanContextState>) {
+        let trace_id = span.context().state().trace_id();
+        if let Some(&keep) = self.decided.get(&trace_id) {
+            self.counters.late_spans.fetch_add(1, Ordering::Relaxed);
+            if keep {
+                self.forward(vec![span]);
+            }
+            return;
+        }
+
+        let is_root = span.context().state().parent_span_id().is_none();
+        let deadline = Instant::now() + self.decision_wait;
+        let deadlines = &mut self.deadlines;
+        let trace = self.pending.entry(trace_id).or_insert_with(|| {
+            deadlines.push_back((deadline, trace_id));
+            PendingTrace {
+                spans: Vec::new(),
+                deadline,
+            }
+        });
+        trace.spans.push(span);
+        if is_root || trace.spans.len() >= self.max_spans_per_trace {
+            self.decide(trace_id);
+        }
+
+        while self.pending.len() > self.max_traces {
+            let (_, oldest) = self.deadlines.pop_front().expect("never fails");
+            self.decide(oldest);
+        }
+    }
+
+    fn drain(&mut self) {
+        while let Ok(span) = self.span_rx.try_recv() {
+            self.push(span);
+        }
+    }
+
+    fn expire(&mut self, now: Instant) {
+        while let Some(&(deadline, trace_id)) = self.deadlines.front() {
+            if deadline > now {
+                break;
+            }
+            self.deadlines.pop_front();
+            if self
+                .pending
+                .get(&trace_id)
+                .is_some_and(|t| t.deadline == deadline)
+            {
+                self.decide(trace_id);
+            }
+        }
+    }
+
+    fn decide_all(&mut self) {
+        while let Some((_, trace_id)) = self.deadlines.pop_front() {
+            self.decide(trace_id);
+        }
+    }
+
+    fn decide(&mut self, trace_id: TraceId) {
+        let trace = if let Some(trace) = self.pending.remove(&trace_id) {
+            trace
+        } else {
+            return;
+        };
+        if self.deadlines.len() > self.max_traces * 2 {
+            self.compact_deadlines();
+        }
+        let keep =
+            self.policies.is_empty() || self.policies.iter().any(|p| p.is_kept(&trace.spans));
+
+        self.decided.insert(trace_id, keep);
+        self.decided_order.push_back(trace_id);
+        if self.decided_order.len() > self.max_traces {
+            let oldest = self.decided_order.pop_front().expect("never fails");
+            self.decided.remove(&oldest);
+        }
+
+        if keep {
+            self.counters.kept_traces.fetch_add(1, Ordering::Relaxed);
+            self.forward(trace.spans);
+        } else {
+            self.counters
+                .discarded_traces
+                .fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Removes the deadlines of the traces which have already been decided.
+    fn compact_deadlines(&mut self) {
+        let pending = &self.pending;
+        self.deadlines.retain(|(deadline, trace_id)| {
+            pending
+                .get(trace_id)
+                .is_some_and(|t| t.deadline == *deadline)
+        });
+    }
+
+    fn forward(&mut self, spans: Vec<FinishedSpan<SpanContextState>>) {
+        for span in spans {
+            if self.span_tx.try_send(span).is_err() {
+                self.counters.dropped_spans.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::AllSampler;
+    use crate::state::SpanId;
+    use crate::tag::{StdTag, Tag};
+    use crate::Tracer;
+    use std::time::UNIX_EPOCH;
+
+    type TestTracer = Tracer<AllSampler, SpanContextState>;
+
+    fn finish(
+        tracer: &TestTracer,
+        name: &'static str,
+        trace_id: u128,
+        parent: Option<u64>,
+        tag: Option<Tag>,
+    ) {
+        let mut state = SpanContextState::new(
+            TraceId::new(trace_id),
+            SpanId::new(trace_id as u64 * 10 + 1),
+        );
+        state.set_parent_span_id(parent.map(SpanId::new));
+        let mut options = tracer.span(name).start_time(UNIX_EPOCH);
+        if let Some(tag) = tag {
+            options = options.tag(tag);
+        }
+        let mut span = options.start_with_state(state);
+        span.set_finish_time(|| UNIX_EPOCH + Duration::from_millis(trace_id as u64 * 100));
+    }
+
+    fn names(rx: &SpanReceiver<SpanContextState>) -> Vec<String> {
+        rx.try_iter()
+            .map(|s| s.operation_name().to_owned())
+            .collect()
+    }
+
+    #[test]
+    fn policies_work() {
+        let (span_tx, span_rx) = channel::unbounded();
+        let (sampled_tx, sampled_rx) = channel::unbounded();
+        let tracer = Tracer::with_sender(AllSampler, span_tx);
+        let sampler = TailSamplerBuilder::new(span_rx, sampled_tx)
+            .policy(ErrorPolicy)
+            .policy(LatencyPolicy::new(Duration::from_millis(300)))
+            .policy(TagPolicy::new("user", "alice"))
+            .spawn();
+
+        finish(&tracer, "error_child", 1, Some(1), Some(StdTag::error()));
+        finish(&tracer, "error_root", 1, None, None);
+        finish(&tracer, "fast", 2, None, None);
+        finish(&tracer, "slow", 3, None, None);
+        finish(
+            &tracer,
+            "tagged",
+            2,
+            Some(1),
+            Some(Tag::new("user", "alice")),
+        );
+        sampler.flush().unwrap();
+        assert_eq!(names(&sampled_rx), ["error_child", "error_root", "slow"]);
+
+        // Late spans follow the decisions
+        finish(&tracer, "late", 1, Some(1), None);
+        finish(&tracer, "late", 2, Some(1), None);
+        sampler.flush().unwrap();
+        assert_eq!(names(&sampled_rx), ["late"]);
+
+        // Traces without root spans are decided by flush
+        finish(
+            &tracer,
+            "tagged",
+            4,
+            Some(1),
+            Some(Tag::new("user", "alice")),
+        );
+        sampler.flush().unwrap();
+        assert_eq!(names(&sampled_rx), ["tagged"]);
+
+        let stats = sampler.stats();
+        assert_eq!(stats.kept_traces(), 3);
+        assert_eq!(stats.discarded_traces(), 1);
+        assert_eq!(stats.late_spans(), 3);
+        sampler.shutdown(Duration::from_secs(5)).unwrap();
+    }
+
+    #[test]
+    fn decision_wait_works() {
+        let (span_tx, span_rx) = channel::unbounded();
+        let (sampled_tx, sampled_rx) = channel::unbounded();
+        let tracer = Tracer::with_sender(AllSampler, span_tx);
+        let _sampler = TailSamplerBuilder::new(span_rx, sampled_tx)
+            .decision_wait(Duration::from_millis(10))
+            .spawn();
+
+        finish(&tracer, "child", 1, Some(1), None);
+        let span = sampled_rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(span.operation_name(), "child");
+    }
+
+    #[test]
+    fn memory_bounds_work() {
+        let (span_tx, span_rx) = channel::unbounded();
+        let (sampled_tx, sampled_rx) = channel::unbounded();
+        let tracer = Tracer::with_sender(AllSampler, span_tx);
+        let sampler = TailSamplerBuilder::new(span_rx, sampled_tx)
+            .max_traces(2)
+            .max_spans_per_trace(2)
+            .spawn();
+
+        finish(&tracer, "a", 1, Some(1), None);
+        finish(&tracer, "b", 2, Some(1), None);
+        finish(&tracer, "c", 3, Some(1), None); // The trace 1 is evicted
+        finish(&tracer, "d", 2, Some(1), None); // The trace 2 reaches the limit
+        drop(tracer);
+        sampler.shutdown(Duration::from_secs(5)).unwrap();
+        assert_eq!(names(&sampled_rx), ["a", "b", "d", "c"]);
+    }
+
+    #[test]
+    fn deadlines_of_decided_traces_are_compacted() {
+        let (span_tx, span_rx) = channel::unbounded();
+        let (sampled_tx, _sampled_rx) = channel::unbounded();
+        let (_command_tx, command_rx) = channel::unbounded();
+        let tracer = Tracer::with_sender(AllSampler, span_tx);
+        let mut worker = TailSamplerBuilder::new(span_rx, sampled_tx)
+            .max_traces(4)
+            .into_worker(command_rx, Arc::default());
+
+        for trace_id in 1..=100 {
+            finish(&tracer, "root", trace_id, None, None);
+        }
+        worker.drain();
+        assert!(worker.pending.is_empty());
+        assert!(worker.deadlines.len() <= 8);
+    }
+
+    #[test]
+    fn probabilistic_policy_works() {
+        let policy = ProbabilisticPolicy::new(0.5).unwrap();
+        assert!(policy.is_kept_trace(TraceId::new(1)));
+        assert!(!policy.is_kept_trace(TraceId::new(u128::from(u64::MAX))));
+        assert!(ProbabilisticPolicy::new(1.5).is_err());
+    }
+}
@@ -1,6 +1,11 @@
+use crate::processor::SpanProcessor;
 use crate::sampler::Sampler;
-use crate::span::{SpanReceiver, SpanSender, StartSpanOptions};
+use crate::scope::{self, ActiveSpan};
+use crate::span::{
+    Span, SpanDelivery, SpanHandle, SpanReceiver, SpanSender, SpanSink, StartSpanOptions,
+};
 use std::borrow::Cow;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 
 /// Tracer.
@@ -22,7 +27,7 @@ use std::sync::Arc;
 #[derive(Debug)]
 pub struct Tracer<S, T> {
     sampler: Arc<S>,
-    span_tx: SpanSender<T>,
+    sink: SpanSink<T>,
 }
 impl<S: Sampler<T>, T> Tracer<S, T> {
     /// This constructor is mainly for backward compatibility, it has the same interface
@@ -38,24 +43,90 @@ impl<S: Sampler<T>, T> Tracer<S, T> {
     pub fn with_sender(sampler: S, span_tx: SpanSender<T>) -> Self {
         Tracer {
             sampler: Arc::new(sampler),
-            span_tx,
+            sink: SpanSink::new(span_tx, Arc::new(Counters::default())),
         }
     }
 
     /// Returns `StartSpanOptions` for starting a span which has the name `operation_name`.
-    pub fn span<N>(&self, operation_name: N) -> StartSpanOptions<S, T>
+    pub fn span<N>(&self, operation_name: N) -> StartSpanOptions<'_, S, T>
     where
         N: Into<Cow<'static, str>>,
     {
-        StartSpanOptions::new(operation_name, &self.span_tx, &self.sampler)
+        StartSpanOptions::new(operation_name, &self.sink, &*self.sampler)
+    }
+
+    /// Starts a span and makes it the active span of the current thread.
+    ///
+    /// Like other spans, the span becomes a `ChildOf` the currently active span
+    /// unless other references are added (see `StartSpanOptions::ignore_active_span`).
+    ///
+    /// The span stays active until the returned guard is dropped.
+    /// After that, the previously active span becomes active again.
+    pub fn start_active<N, F>(&self, operation_name: N, f: F) -> ActiveSpan<T>
+    where
+        N: Into<Cow<'static, str>>,
+        T: Clone + 'static,
+        F: FnOnce(StartSpanOptions<S, T>) -> Span<T>,
+    {
+        ActiveSpan::new(f(self.span(operation_name).child_of_active()))
+    }
+
+    /// Returns the handle of the active span of the current thread.
+    ///
+    /// If there is no active span, it will return `None`.
+    pub fn active_span(&self) -> Option<SpanHandle<T>>
+    where
+        T: Clone + 'static,
+    {
+        scope::active_span()
     }
 }
 impl<S, T> Tracer<S, T> {
+    /// Returns the statistics of the spans started by this tracer.
+    ///
+    /// The counters are shared among the clones of this tracer
+    /// (including the ones created by `clone_with_sampler`),
+    /// and the spans started via `Span::child` or `SpanHandle::child` are also counted.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rustracing::sampler::AllSampler;
+    /// use rustracing::Tracer;
+    ///
+    /// let (span_tx, _span_rx) = crossbeam_channel::bounded(1);
+    /// let tracer = Tracer::with_sender(AllSampler, span_tx);
+    /// {
+    ///     let _foo = tracer.span("foo").start_with_state(());
+    ///     let _bar = tracer.span("bar").start_with_state(());
+    /// }
+    ///
+    /// let stats = tracer.stats();
+    /// assert_eq!(stats.started(), 2);
+    /// assert_eq!(stats.delivered(), 1);
+    /// assert_eq!(stats.dropped_full(), 1);
+    /// ```
+    pub fn stats(&self) -> TracerStats {
+        self.sink.counters().snapshot()
+    }
+
+    /// Registers the given span processor.
+    ///
+    /// The processor is applied to the spans started by this tracer (and its clones made after this call),
+    /// including the descendant spans started via `Span::child` or `SpanHandle::child`.
+    /// Processors are invoked in the registration order.
+    pub fn add_processor<P>(&mut self, processor: P)
+    where
+        P: SpanProcessor<T> + 'static,
+    {
+        self.sink.add_processor(Arc::new(processor));
+    }
+
     /// Clone with the given `sampler`.
     pub fn clone_with_sampler<U: Sampler<T>>(&self, sampler: U) -> Tracer<U, T> {
         Tracer {
             sampler: Arc::new(sampler),
-            span_tx: self.span_tx.clone(),
+            sink: self.sink.clone(),
         }
     }
 }
@@ -63,7 +134,97 @@ impl<S, T> Clone for Tracer<S, T> {
     fn clone(&self) -> Self {
         Tracer {
             sampler: Arc::clone(&self.sampler),
-            span_tx: self.span_tx.clone(),
+            sink: self.sink.clone(),
+        }
+    }
+}
+
+/// Statistics of the spans started by a `Tracer`.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+pub struct TracerStats {
+    started: u64,
+    sampled_out: u64,
+    delivered: u64,
+    dropped_full: u64,
+    dropped_disconnected: u64,
+    discarded: u64,
+}
+impl TracerStats {
+    /// Returns the number of the started (i.e., sampled) spans.
+    pub fn started(&self) -> u64 {
+        self.started
+    }
+
+    /// Returns the number of the spans which were not started because they were not sampled.
+    pub fn sampled_out(&self) -> u64 {
+        self.sampled_out
+    }
+
+    /// Returns the number of the finished spans sent to the `SpanReceiver`.
+    pub fn delivered(&self) -> u64 {
+        self.delivered
+    }
+
+    /// Returns the number of the finished spans dropped because the channel was full.
+    pub fn dropped_full(&self) -> u64 {
+        self.dropped_full
+    }
+
+    /// Returns the number of the finished spans dropped because the channel was disconnected.
+    pub fn dropped_disconnected(&self) -> u64 {
+        self.dropped_disconnected
+    }
+
+    /// Returns the number of the finished spans discarded by `SpanProcessor`s.
+    pub fn discarded(&self) -> u64 {
+        self.discarded
+    }
+
+    /// Returns the total number of the dropped spans.
+    ///
+    /// Note that the spans discarded by `SpanProcessor`s are not included.
+    pub fn dropped(&self) -> u64 {
+        self.dropped_full + self.dropped_disconnected
+    }
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Counters {
+    started: AtomicU64,
+    sampled_out: AtomicU64,
+    delivered: AtomicU64,
+    dropped_full: AtomicU64,
+    dropped_disconnected: AtomicU64,
+    discarded: AtomicU64,
+}
+impl Counters {
+    pub(crate) fn record_started(&self) {
+        self.started.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_sampled_out(&self) {
+        self.sampled_out.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_delivery(&self, delivery: SpanDelivery) {
+        let counter = match delivery {
+            SpanDelivery::Delivered => &self.delivered,
+            SpanDelivery::DroppedFull => &self.dropped_full,
+            SpanDelivery::DroppedDisconnected => &self.dropped_disconnected,
+            SpanDelivery::Discarded => &self.discarded,
+            SpanDelivery::NotSampled => return,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> TracerStats {
+        TracerStats {
+            started: self.started.load(Ordering::Relaxed),
+            sampled_out: self.sampled_out.load(Ordering::Relaxed),
+            delivered: self.delivered.load(Ordering::Relaxed),
+            dropped_full: self.dropped_full.load(Ordering::Relaxed),
+            dropped_disconnected: self.dropped_disconnected.load(Ordering::Relaxed),
+            discarded: self.discarded.load(Ordering::Relaxed),
         }
     }
 }